@@ -0,0 +1,333 @@
+//! 编译 contracts/ 下的合约, 将 ABI 与字节码写入 OUT_DIR, 供 `include_str!` 引用,
+//! 并按 ABI 生成调用参数的绑定. solc 由环境变量 SOLC 指定, 默认取 PATH 中的 solc.
+//! 找不到 solc 时改用检入 contracts/artifacts/ 的编译产物, 源码变更后产物过期时给出警告;
+//! 在有 solc 的环境中设置 SOLC_UPDATE_ARTIFACTS=1 构建即重新生成检入的产物
+
+use std::collections::BTreeSet;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+
+const CONTRACTS_DIR: &str = "contracts";
+// 检入的编译产物, 每个产物一组 <产物名>.<扩展名>, SOURCES 为生成时各合约源码的哈希
+const ARTIFACTS_DIR: &str = "contracts/artifacts";
+const ARTIFACT_FILES: [&str; 4] = ["abi", "bin", "bin-runtime", "immutables"];
+
+// (源文件, 合约名, 产物名)
+const ARTIFACTS: [(&str, &str, &str); 4] = [
+    (
+        "CertificateVerifier.sol",
+        "CertificateVerifier",
+        "CertificateVerifier",
+    ),
+    ("Halo2Verifier.sol", "Halo2Verifier", "Halo2Verifier"),
+    ("ShplonkVerifier.sol", "Verifier", "ShplonkVerifier"),
+    ("IssuerRegistry.sol", "IssuerRegistry", "IssuerRegistry"),
+];
+
+// 生成调用绑定的产物, 写入 OUT_DIR/<产物名>.rs
+const BINDINGS: [&str; 2] = ["CertificateVerifier", "IssuerRegistry"];
+
+fn main() {
+    println!("cargo:rerun-if-changed={CONTRACTS_DIR}");
+    println!("cargo:rerun-if-env-changed=SOLC");
+    println!("cargo:rerun-if-env-changed=SOLC_UPDATE_ARTIFACTS");
+
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    let sources = standard_json_input();
+    let artifacts = match compile(&sources) {
+        Some(output) => {
+            let artifacts = compiled_artifacts(&output);
+            if std::env::var("SOLC_UPDATE_ARTIFACTS").is_ok_and(|v| v == "1" || v == "true") {
+                std::fs::create_dir_all(ARTIFACTS_DIR).unwrap();
+                for (name, files) in &artifacts {
+                    for (extension, contents) in ARTIFACT_FILES.iter().zip(files) {
+                        write(
+                            Path::new(ARTIFACTS_DIR),
+                            &format!("{name}.{extension}"),
+                            contents,
+                        );
+                    }
+                }
+                write(Path::new(ARTIFACTS_DIR), "SOURCES", &sources_hash(&sources));
+            }
+            artifacts
+        }
+        None => checked_in_artifacts(&sources),
+    };
+
+    for (name, files) in &artifacts {
+        for (extension, contents) in ARTIFACT_FILES.iter().zip(files) {
+            write(&out_dir, &format!("{name}.{extension}"), contents);
+        }
+        if BINDINGS.contains(name) {
+            let abi = serde_json::from_str(&files[0]).expect("contract ABI");
+            write(&out_dir, &format!("{name}.rs"), &bindings(&abi));
+        }
+    }
+}
+
+/// solc 输出中各产物的 ABI、创建字节码、运行时代码与 immutable 变量位置, 顺序同 ARTIFACT_FILES
+fn compiled_artifacts(output: &Value) -> Vec<(&'static str, [String; 4])> {
+    if let Some(errors) = output["errors"].as_array() {
+        let errors = errors
+            .iter()
+            .filter(|error| error["severity"] == "error")
+            .map(|error| error["formattedMessage"].as_str().unwrap_or_default())
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            panic!("solc 编译合约失败:\n{}", errors.join("\n"));
+        }
+    }
+
+    ARTIFACTS
+        .iter()
+        .map(|(source, contract, name)| {
+            let artifact = &output["contracts"][format!("{CONTRACTS_DIR}/{source}")][contract];
+            let bytecode = artifact["evm"]["bytecode"]["object"]
+                .as_str()
+                .unwrap_or_default();
+            if bytecode.is_empty() {
+                panic!("solc 没有输出 {source}:{contract} 的字节码");
+            }
+            // immutable 变量在运行时代码中的位置: {astId: [{start, length}]}
+            let immutables = &artifact["evm"]["deployedBytecode"]["immutableReferences"];
+            (
+                *name,
+                [
+                    artifact["abi"].to_string(),
+                    bytecode.to_string(),
+                    artifact["evm"]["deployedBytecode"]["object"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    match immutables.is_null() {
+                        true => "{}".to_string(),
+                        false => immutables.to_string(),
+                    },
+                ],
+            )
+        })
+        .collect()
+}
+
+/// 没有 solc 时读取检入的产物; 缺少产物时无法构建, 源码已变更时产物可能与源码不符
+fn checked_in_artifacts(sources: &Value) -> Vec<(&'static str, [String; 4])> {
+    let read = |file: &str| {
+        std::fs::read_to_string(Path::new(ARTIFACTS_DIR).join(file)).unwrap_or_else(|e| {
+            panic!(
+                "找不到 solc, 也没有检入的编译产物 {ARTIFACTS_DIR}/{file}: {e}. \
+                 请安装 solc 0.8 或通过 SOLC 指定路径"
+            )
+        })
+    };
+    if read("SOURCES").trim() != sources_hash(sources) {
+        println!(
+            "cargo:warning=检入的合约编译产物与 {CONTRACTS_DIR}/ 下的源码不一致, \
+             请在有 solc 的环境中以 SOLC_UPDATE_ARTIFACTS=1 重新生成"
+        );
+    }
+    ARTIFACTS
+        .iter()
+        .map(|(_, _, name)| {
+            (
+                *name,
+                ARTIFACT_FILES.map(|extension| read(&format!("{name}.{extension}"))),
+            )
+        })
+        .collect()
+}
+
+// 各合约源码的哈希, 按源文件名排序, 用于判断检入的产物是否过期
+fn sources_hash(input: &Value) -> String {
+    let sources = input["sources"].as_object().unwrap();
+    let mut hasher = Keccak256::new();
+    for name in sources.keys().collect::<BTreeSet<_>>() {
+        hasher.update(name.as_bytes());
+        hasher.update(sources[name]["content"].as_str().unwrap().as_bytes());
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// ABI 中每个函数一个参数结构体 `<函数名>Call`, 字段按参数顺序以 snake_case 命名,
+/// 实现 `Tokenize` 与 `services::bindings::ContractCall`
+fn bindings(abi: &Value) -> String {
+    let mut code = String::from("// 由 build.rs 按合约 ABI 生成, 请勿手动修改\n");
+    let mut names = BTreeSet::new();
+    let functions = abi
+        .as_array()
+        .expect("contract ABI")
+        .iter()
+        .filter(|item| item["type"] == "function");
+    for function in functions {
+        let name = function["name"].as_str().unwrap();
+        if !names.insert(name) {
+            panic!("不支持为重载函数 {name} 生成绑定");
+        }
+        let params = function["inputs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(i, input)| {
+                let field = match input["name"].as_str().unwrap_or_default() {
+                    "" => format!("arg{i}"),
+                    name => snake_case(name.trim_start_matches('_')),
+                };
+                (field, input["type"].as_str().unwrap())
+            })
+            .collect::<Vec<_>>();
+        let signature = format!(
+            "{name}({})",
+            params
+                .iter()
+                .map(|(_, kind)| *kind)
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        let selector = &Keccak256::digest(signature.as_bytes())[..4];
+        let call = format!("{}{}Call", name[..1].to_uppercase(), &name[1..]);
+
+        code += &format!("\n/// `{signature}` 的参数\n");
+        code += "#[derive(Debug, Clone, Default, PartialEq, Eq)]\n";
+        code += &format!("pub struct {call} {{\n");
+        for (field, kind) in &params {
+            code += &format!("    pub {field}: {},\n", rust_type(kind));
+        }
+        code += "}\n\n";
+
+        code += &format!("impl web3::contract::tokens::Tokenize for {call} {{\n");
+        code += "    fn into_tokens(self) -> Vec<web3::ethabi::Token> {\n        vec![\n";
+        for (field, kind) in &params {
+            code += &format!("            {},\n", token(kind, &format!("self.{field}")));
+        }
+        code += "        ]\n    }\n}\n\n";
+
+        code += &format!("impl super::ContractCall for {call} {{\n");
+        code += &format!("    const NAME: &'static str = \"{name}\";\n");
+        code += &format!("    const SIGNATURE: &'static str = \"{signature}\";\n");
+        code += &format!("    const SELECTOR: [u8; 4] = {selector:?};\n}}\n");
+    }
+    code
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_ascii_uppercase() {
+            if i > 0 && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+fn rust_type(kind: &str) -> &'static str {
+    match kind {
+        "bool" => "bool",
+        "string" => "String",
+        "bytes" => "Vec<u8>",
+        "bytes32" => "web3::types::H256",
+        "address" => "web3::types::Address",
+        "address[]" => "Vec<web3::types::Address>",
+        kind if kind.starts_with("uint") && !kind.ends_with(']') => "web3::types::U256",
+        kind => panic!("不支持为 {kind} 类型的参数生成绑定"),
+    }
+}
+
+fn token(kind: &str, value: &str) -> String {
+    match kind {
+        "bool" => format!("web3::ethabi::Token::Bool({value})"),
+        "string" => format!("web3::ethabi::Token::String({value})"),
+        "bytes" => format!("web3::ethabi::Token::Bytes({value})"),
+        "bytes32" => format!("web3::ethabi::Token::FixedBytes({value}.as_bytes().to_vec())"),
+        "address" => format!("web3::ethabi::Token::Address({value})"),
+        "address[]" => format!(
+            "web3::ethabi::Token::Array({value}.into_iter().map(web3::ethabi::Token::Address).collect())"
+        ),
+        _ => format!("web3::ethabi::Token::Uint({value})"),
+    }
+}
+
+// 源文件以 contracts/<文件名> 为键, 即 solc 输出中合约的完全限定名
+fn standard_json_input() -> Value {
+    let mut sources = serde_json::Map::new();
+    for entry in std::fs::read_dir(CONTRACTS_DIR).expect("contracts directory") {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "sol") {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            sources.insert(
+                format!("{CONTRACTS_DIR}/{name}"),
+                json!({ "content": std::fs::read_to_string(&path).unwrap() }),
+            );
+        }
+    }
+
+    json!({
+        "language": "Solidity",
+        "sources": sources,
+        "settings": {
+            "optimizer": { "enabled": true, "runs": 200 },
+            "outputSelection": {
+                "*": {
+                    "*": [
+                        "abi",
+                        "evm.bytecode.object",
+                        "evm.deployedBytecode.object",
+                        "evm.deployedBytecode.immutableReferences"
+                    ]
+                }
+            }
+        }
+    })
+}
+
+// 找不到 solc 时返回空, 其余错误构建失败
+fn compile(input: &Value) -> Option<Value> {
+    let solc = std::env::var("SOLC").unwrap_or_else(|_| "solc".to_string());
+    let spawned = Command::new(&solc)
+        .arg("--standard-json")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn();
+    let mut child = match spawned {
+        Ok(child) => child,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            println!("cargo:warning=找不到 solc ({solc}), 使用检入 {ARTIFACTS_DIR} 的合约编译产物");
+            return None;
+        }
+        Err(e) => panic!("无法运行 solc ({solc}): {e}"),
+    };
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.to_string().as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    if !output.status.success() {
+        panic!("solc 退出状态 {}", output.status);
+    }
+    Some(serde_json::from_slice(&output.stdout).expect("solc standard JSON output"))
+}
+
+// 内容未变时不改写, 以免检入的产物目录在每次构建后都被视为已修改而重新运行构建脚本
+fn write(dir: &Path, name: &str, contents: &str) {
+    let path = dir.join(name);
+    if std::fs::read_to_string(&path).is_ok_and(|existing| existing == contents) {
+        return;
+    }
+    std::fs::write(path, contents).unwrap();
+}
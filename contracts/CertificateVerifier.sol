@@ -1,23 +1,210 @@
 // SPDX-License-Identifier: MIT
 pragma solidity ^0.8.0;
 
-import "./PoseidonT3.sol";
 import "./Halo2Verifier.sol";
 import "./ShplonkVerifier.sol";
 import "./Pairing.sol";
 import { Constants } from "./Constants.sol";
 
 contract CertificateVerifier is Constants {
-    using PoseidonT3 for uint[2];
-    
     Halo2Verifier public immutable halo2Verifier;
     Verifier public immutable shplonkVerifier;
     uint256 constant FR_SIZE = 32;
     uint256 constant G1_SIZE = 96;
+    // Position of the revocation accumulator root among the disclosure circuit's public inputs,
+    // must match REVOCATION_ROOT_INSTANCE in services/circuit.rs
+    uint256 constant REVOCATION_ROOT_INSTANCE = 47;
+    // Public inputs per disclosed record, its fields followed by its compressed value,
+    // MAX_N_EDU_MSG_DATA + 1 in services/circuit.rs
+    uint256 constant RECORD_INSTANCES = 11;
+    // The disclosure circuit pads every presentation to MAX_N_EDU certificates of MAX_N_EDU_MSG
+    // records each (services/circuit.rs); record r of certificate c is record c * MAX_N_EDU_MSG + r
+    uint256 constant MAX_N_EDU = 2;
+    uint256 constant MAX_N_EDU_MSG = 2;
+    // RevocationReason::Superseded in services/ledger.rs
+    uint8 constant REASON_SUPERSEDED = 3;
+    // Students per shard, MAX_DEGREE in services/shplonk.rs; a certificate's revocation index is
+    // shard * SHARD_SLOTS + its slot in the shard, see revocation_index in services/batches.rs
+    uint256 constant SHARD_SLOTS = 1 << 16;
+
+    // Maintains the revocation accumulator root used by zero-knowledge presentations. The accumulator
+    // is keyed by record and shared by every issuer, so it is kept by the service that deployed this
+    // contract rather than by the issuers: a zero-knowledge presentation trusts this operator to
+    // publish every revocation, while revokeCertificate records each issuer's own revocations
+    address public immutable revocationOperator;
+    bytes32 public revocationRoot;
+
+    struct Batch {
+        bytes32 schemaHash;
+        bytes commitments;
+        // Students in the batch; shards fill in order, so every shard but the last holds SHARD_SLOTS
+        uint256 students;
+        uint256 anchoredAt;
+    }
+
+    // issuer => batchId => batch
+    mapping(address => mapping(bytes32 => Batch)) private batches;
+    // issuer => batchId => batch it was derived from by updateBatch, zero for a fresh batch
+    mapping(address => mapping(bytes32 => bytes32)) private parentBatches;
+
+    // issuer => batchId => word => bitmap of revoked revocation indexes, index i is bit i % 256 of word i / 256
+    mapping(address => mapping(bytes32 => mapping(uint256 => uint256))) private revocations;
+    // issuer => batchId => revocation index => reason code, 0 = not revoked
+    mapping(address => mapping(bytes32 => mapping(uint256 => uint8))) private revocationReasons;
+    // issuer => batchId => revocation index => batch holding the corrected certificate
+    mapping(address => mapping(bytes32 => mapping(uint256 => bytes32))) private supersessions;
+
+    event BatchAnchored(
+        address indexed issuer,
+        bytes32 indexed batchId,
+        bytes32 schemaHash,
+        bytes commitments
+    );
     
-    constructor(address _halo2Verifier, address _shplonkVerifier) {
+    event BatchUpdated(
+        address indexed issuer,
+        bytes32 indexed parentBatchId,
+        bytes32 indexed batchId,
+        bytes delta
+    );
+
+    event CertificateRevoked(
+        address indexed issuer,
+        bytes32 indexed batchId,
+        uint256 index,
+        uint8 reason
+    );
+
+    event CertificateSuperseded(
+        address indexed issuer,
+        bytes32 indexed batchId,
+        uint256 index,
+        bytes32 indexed newBatchId
+    );
+
+    event RevocationRootUpdated(bytes32 root);
+
+    event VerificationAttested(
+        address indexed verifier,
+        bytes32 indexed requestHash,
+        bool verified
+    );
+
+    // `_revocationRoot` is the root of the empty accumulator, RevocationTree::default().root() in
+    // services/revocation.rs, so no proof is accepted against an unset root
+    constructor(address _halo2Verifier, address _shplonkVerifier, bytes32 _revocationRoot) {
+        require(_revocationRoot != bytes32(0), "Revocation root must be set");
         halo2Verifier = Halo2Verifier(_halo2Verifier);
         shplonkVerifier = Verifier(_shplonkVerifier);
+        revocationOperator = msg.sender;
+        revocationRoot = _revocationRoot;
+    }
+
+    function updateRevocationRoot(bytes32 root) external {
+        require(msg.sender == revocationOperator, "Caller is not the revocation operator");
+        revocationRoot = root;
+        emit RevocationRootUpdated(root);
+    }
+
+    // Anchor the commitments of one issuance batch of `students` students; a batch can only be anchored once
+    function anchorBatch(bytes32 batchId, bytes32 schemaHash, bytes calldata commitments, uint256 students) external {
+        _anchor(batchId, schemaHash, commitments, students);
+    }
+
+    // Anchor the commitments of an existing batch after students were appended or updated.
+    // `delta` records the commitment increments sum(v_i * L_i(tau)) for auditors; it is not checked
+    // against the parent, as commitments are stored in their raw encoding rather than as EVM G1 points.
+    // The issuer supersedes the parent certificates of updated students; the others keep verifying
+    // against the parent.
+    // A shard that outgrew its evaluation domain is recommitted over the larger domain: its delta is
+    // then only the difference of the two commitments and does not decompose into slot increments.
+    function updateBatch(
+        bytes32 parentBatchId,
+        bytes32 batchId,
+        bytes32 schemaHash,
+        bytes calldata commitments,
+        bytes calldata delta,
+        uint256 students
+    ) external {
+        require(batches[msg.sender][parentBatchId].anchoredAt != 0, "Parent batch not found");
+        require(delta.length == commitments.length, "Delta length must match commitments");
+        _anchor(batchId, schemaHash, commitments, students);
+
+        parentBatches[msg.sender][batchId] = parentBatchId;
+        emit BatchUpdated(msg.sender, parentBatchId, batchId, delta);
+    }
+
+    function getParentBatch(address issuer, bytes32 batchId) external view returns (bytes32) {
+        return parentBatches[issuer][batchId];
+    }
+
+    // Revoke the certificate at revocation index `index` in one of the caller's batches
+    function revokeCertificate(bytes32 batchId, uint256 index, uint8 reason) external {
+        _revoke(batchId, index, reason);
+    }
+
+    // Replace the certificate at `index` of an earlier batch with the certificate of the same student
+    // in `newBatchId`, a correction batch of the same issuer; the old certificate is revoked as superseded
+    function supersedeCertificate(bytes32 batchId, uint256 index, bytes32 newBatchId) external {
+        require(newBatchId != batchId, "Certificate cannot supersede itself");
+        require(batches[msg.sender][newBatchId].anchoredAt != 0, "Correction batch not found");
+        _revoke(batchId, index, REASON_SUPERSEDED);
+
+        supersessions[msg.sender][batchId][index] = newBatchId;
+        emit CertificateSuperseded(msg.sender, batchId, index, newBatchId);
+    }
+
+    function getRevocation(address issuer, bytes32 batchId, uint256 index)
+        external
+        view
+        returns (bool revoked, uint8 reason)
+    {
+        revoked = revocations[issuer][batchId][index / 256] & (1 << (index % 256)) != 0;
+        reason = revocationReasons[issuer][batchId][index];
+    }
+
+    // Batch holding the certificate that superseded this one, zero if not superseded
+    function getSupersession(address issuer, bytes32 batchId, uint256 index) external view returns (bytes32) {
+        return supersessions[issuer][batchId][index];
+    }
+
+    // One 256-bit word of a batch's revocation bitmap, for bulk status checks
+    function revocationWord(address issuer, bytes32 batchId, uint256 word) external view returns (uint256) {
+        return revocations[issuer][batchId][word];
+    }
+
+    function getBatch(address issuer, bytes32 batchId)
+        external
+        view
+        returns (bytes32 schemaHash, bytes memory commitments, uint256 anchoredAt)
+    {
+        Batch storage batch = batches[issuer][batchId];
+        require(batch.anchoredAt != 0, "Batch not found");
+        return (batch.schemaHash, batch.commitments, batch.anchoredAt);
+    }
+
+    function _anchor(bytes32 batchId, bytes32 schemaHash, bytes calldata commitments, uint256 students) internal {
+        require(commitments.length > 0 && commitments.length % G1_SIZE == 0, "Commitment length must be multiple of G1_SIZE");
+        require(students > 0, "Batch has no students");
+        require(batches[msg.sender][batchId].anchoredAt == 0, "Batch already anchored");
+
+        batches[msg.sender][batchId] = Batch(schemaHash, commitments, students, block.timestamp);
+        emit BatchAnchored(msg.sender, batchId, schemaHash, commitments);
+    }
+
+    function _revoke(bytes32 batchId, uint256 index, uint8 reason) internal {
+        Batch storage batch = batches[msg.sender][batchId];
+        require(batch.anchoredAt != 0, "Batch not found");
+        // index = shard * SHARD_SLOTS + slot; as every shard but the last is full, the shard is within the
+        // batch and the slot within that shard's students exactly when index < students
+        require(index < batch.students, "Index outside batch");
+        require(reason != 0, "Invalid reason");
+        uint256 bit = 1 << (index % 256);
+        require(revocations[msg.sender][batchId][index / 256] & bit == 0, "Certificate already revoked");
+
+        revocations[msg.sender][batchId][index / 256] |= bit;
+        revocationReasons[msg.sender][batchId][index] = reason;
+        emit CertificateRevoked(msg.sender, batchId, index, reason);
     }
 
     // Helper function to chunk data into Fr elements
@@ -55,36 +242,103 @@ contract CertificateVerifier is Constants {
         return points;
     }
 
-    // Helper function to compute compressed value
-    function computeCompressedValue(uint256[] memory data, uint256 xi) internal pure returns (uint256) {
-        uint256 result = data[0];
+    // Combine the openings [start, end), all at `point`, with powers of xi and check them with one pairing
+    function verifyGroup(
+        Pairing.G1Point[] memory commits,
+        Pairing.G1Point[] memory proofs,
+        uint256[] memory values,
+        uint256 start,
+        uint256 end,
+        uint256 point,
+        uint256 xi
+    ) internal view returns (bool) {
+        Pairing.G1Point memory groupCommit = commits[start];
+        Pairing.G1Point memory groupProof = proofs[start];
+        uint256 groupValue = values[start];
         uint256 xiPower = xi;
-        
-        for (uint256 i = 1; i < data.length; i++) {
-            result = addmod(result, mulmod(data[i], xiPower, BABYJUB_P), BABYJUB_P);
+
+        for (uint256 j = start + 1; j < end; j++) {
+            groupCommit = Pairing.plus(groupCommit, Pairing.mulScalar(commits[j], xiPower));
+            groupProof = Pairing.plus(groupProof, Pairing.mulScalar(proofs[j], xiPower));
+            groupValue = addmod(groupValue, mulmod(values[j], xiPower, BABYJUB_P), BABYJUB_P);
             xiPower = mulmod(xiPower, xi, BABYJUB_P);
         }
-        return result;
+        return shplonkVerifier.verify(groupCommit, groupProof, point, groupValue);
     }
 
-    // Helper function to compute compressed commitment
-    function computeCompressedCommitment(
-        Pairing.G1Point[] memory commits, 
+    // Check every opening; `index` splits them into groups sharing one opening point
+    function verifyOpenings(
+        uint256[] memory values,
+        bytes memory commitment,
+        bytes memory proof,
+        bytes memory index,
         uint256 xi
-    ) internal view returns (Pairing.G1Point memory) {
-        Pairing.G1Point memory result = commits[0];
-        uint256 xiPower = xi;
-        
-        for (uint256 i = 1; i < commits.length; i++) {
-            result = Pairing.plus(
-                result,
-                Pairing.mulScalar(commits[i], xiPower)
-            );
-            xiPower = mulmod(xiPower, xi, BABYJUB_P);
+    ) internal view returns (bool) {
+        Pairing.G1Point[] memory commits = chunkCommitmentToG1(commitment);
+        Pairing.G1Point[] memory proofs = abi.decode(proof, (Pairing.G1Point[]));
+        (uint256[] memory points, uint256[] memory sizes) = abi.decode(index, (uint256[], uint256[]));
+        require(values.length > 0, "No openings");
+        require(commits.length == values.length && proofs.length == values.length, "Opening length mismatch");
+        require(points.length == sizes.length, "Opening point length mismatch");
+
+        bool verified = true;
+        uint256 start = 0;
+        for (uint256 i = 0; i < points.length; i++) {
+            require(sizes[i] > 0 && start + sizes[i] <= values.length, "Invalid opening group");
+            verified = verified && verifyGroup(commits, proofs, values, start, start + sizes[i], points[i], xi);
+            start += sizes[i];
         }
-        return result;
+        require(start == values.length, "Opening groups must cover all openings");
+        return verified;
+    }
+
+    // Public input `i` of the disclosure proof; the calldata starts with the public inputs, 32 bytes each
+    function instanceAt(bytes memory halo2Proof, uint256 i) internal pure returns (uint256 value) {
+        assembly {
+            value := mload(add(halo2Proof, add(32, mul(i, 32))))
+        }
+    }
+
+    // The disclosure proof must prove the opened records and its non-revocation proof must be against
+    // the latest root. Opening group c holds the records of certificate c in the order the proof
+    // lists them, so its r-th value is the compressed value of padded record c * MAX_N_EDU_MSG + r
+    function verifyDisclosure(bytes memory halo2Proof, uint256[] memory values, bytes memory index)
+        internal
+        view
+        returns (bool)
+    {
+        (, uint256[] memory sizes) = abi.decode(index, (uint256[], uint256[]));
+        if (halo2Proof.length < (REVOCATION_ROOT_INSTANCE + 1) * 32 || sizes.length > MAX_N_EDU) {
+            return false;
+        }
+        uint256 start = 0;
+        for (uint256 c = 0; c < sizes.length; c++) {
+            if (sizes[c] > MAX_N_EDU_MSG) {
+                return false;
+            }
+            for (uint256 r = 0; r < sizes[c]; r++) {
+                uint256 record = c * MAX_N_EDU_MSG + r;
+                if (instanceAt(halo2Proof, (record + 1) * RECORD_INSTANCES - 1) != values[start + r]) {
+                    return false;
+                }
+            }
+            start += sizes[c];
+        }
+
+        bytes32 provenRoot = bytes32(instanceAt(halo2Proof, REVOCATION_ROOT_INSTANCE));
+        (bool success,) = address(halo2Verifier).staticcall(halo2Proof);
+        return success && provenRoot == revocationRoot;
     }
 
+    // Verify openings of anchored commitments, one group per certificate:
+    //   jsonData   the opened evaluations, one 32-byte big-endian field element per commitment
+    //   commitment the commitments, G1_SIZE bytes each (x || y || padding)
+    //   proof      abi.encode(Pairing.G1Point[]), one opening proof per commitment
+    //   index      abi.encode(uint256[] points, uint256[] sizes): group i holds the next sizes[i]
+    //              openings, all at points[i], the domain element of the certificate's slot
+    //   random     unused
+    // For a zero-knowledge presentation the evaluations are the compressed values of the records
+    // proven by halo2Proof.
     function verifyData(
         bytes memory jsonData,
         bytes memory commitment,
@@ -94,61 +348,36 @@ contract CertificateVerifier is Constants {
         bytes memory random,
         bool is_zk
     ) public view returns (bool) {
-        // 1. Chunk jsonData into Fr elements
-        uint256[] memory data = chunkDataToFr(jsonData);
-        
-        // 2. Calculate xi = poseidon_hash(data)
-        uint[2] memory hashInputs;
-        hashInputs[0] = data[0];
-        hashInputs[1] = data.length > 1 ? data[1] : 0;
-        uint256 xi = hashInputs.hash();
-        
-        // 3. Compute compressed value
-        uint256 compress = computeCompressedValue(data, xi);
-        
-        // 4. Calculate delta = poseidon_hash(compress)
-        hashInputs[0] = compress;
-        hashInputs[1] = 0;
-        uint256 delta = hashInputs.hash();
-        
-        // 5. Calculate final compressed data
-        uint256 finalCompressData = mulmod(compress, delta, BABYJUB_P);
-        
-        // 6. Chunk commitments into G1 points
-        Pairing.G1Point[] memory commits = chunkCommitmentToG1(commitment);
-        
-        // 7. Compute compressed commitment
-        Pairing.G1Point memory compressCommit = computeCompressedCommitment(commits, xi);
-        
-        // 8. Verify proof using Shplonk
-        Pairing.G1Point memory finalCommit = Pairing.plus(
-            compressCommit,
-            Pairing.negate(
-                Pairing.mulScalar(
-                    Pairing.G1Point(SRS_G1_X[0], SRS_G1_Y[0]),
-                    finalCompressData
-                )
-            )
-        );
-        
-        bool proofRes = shplonkVerifier.verify(
-            finalCommit,
-            abi.decode(proof, (Pairing.G1Point)),
-            abi.decode(index, (uint256)),
-            0  // Expected value is 0 since we subtracted the final_compress_data
-        );
-
-        // 9. Verify ZK proof if required
-        bool zkRes = true;
-        if (is_zk) {
-            (bool success,) = address(halo2Verifier).staticcall(halo2Proof);
-            zkRes = success;
+        uint256[] memory values = chunkDataToFr(jsonData);
+        for (uint256 i = 0; i < values.length; i++) {
+            require(values[i] < BABYJUB_P, "Evaluation out of range");
         }
 
-        // 10. Return combined result
+        // The batching challenge depends on everything being verified, so the openings cannot be
+        // chosen after it is known
+        uint256 xi = uint256(keccak256(abi.encode(jsonData, commitment, proof, index))) % BABYJUB_P;
+        bool proofRes = verifyOpenings(values, commitment, proof, index, xi);
+
+        // Verify ZK proof if required; verifyOpenings has checked that the groups cover the values
+        bool zkRes = !is_zk || verifyDisclosure(halo2Proof, values, index);
+
         return proofRes && zkRes;
     }
 
+    // Opt-in on-chain record of a verification; requestHash is keccak256 of this call's calldata
+    function attestVerification(
+        bytes memory jsonData,
+        bytes memory commitment,
+        bytes memory proof,
+        bytes memory halo2Proof,
+        bytes memory index,
+        bytes memory random,
+        bool is_zk
+    ) external returns (bool verified) {
+        verified = verifyData(jsonData, commitment, proof, halo2Proof, index, random, is_zk);
+        emit VerificationAttested(msg.sender, keccak256(msg.data), verified);
+    }
+
     function hexStringToBytes32(string memory s) internal pure returns (bytes32) {
         bytes memory ss = bytes(s);
         require(ss.length == 64, "Invalid hash length");
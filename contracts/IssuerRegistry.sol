@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+// Registry of accredited schools and their signing keys.
+// The accreditation authority registers schools; schools add, retire and rotate their own keys.
+// A key is authorized for an anchor made while both the key and its school were valid,
+// so retiring a key does not invalidate batches it anchored earlier.
+contract IssuerRegistry {
+    struct Issuer {
+        string name;
+        string code;
+        uint64 validFrom;
+        uint64 validUntil; // 0 = no expiry
+    }
+
+    struct Key {
+        bytes32 issuerId;
+        uint64 addedAt;
+        uint64 retiredAt; // 0 = active
+    }
+
+    address public authority;
+
+    // issuerId = keccak256(code)
+    mapping(bytes32 => Issuer) private issuers;
+    mapping(address => Key) private keys;
+
+    event AuthorityTransferred(address indexed previousAuthority, address indexed newAuthority);
+    event IssuerRegistered(bytes32 indexed issuerId, string name, string code, uint64 validFrom, uint64 validUntil);
+    event IssuerValidityChanged(bytes32 indexed issuerId, uint64 validFrom, uint64 validUntil);
+    event KeyAdded(bytes32 indexed issuerId, address indexed key);
+    event KeyRetired(bytes32 indexed issuerId, address indexed key);
+
+    modifier onlyAuthority() {
+        require(msg.sender == authority, "Caller is not the authority");
+        _;
+    }
+
+    constructor() {
+        authority = msg.sender;
+        emit AuthorityTransferred(address(0), msg.sender);
+    }
+
+    function transferAuthority(address newAuthority) external onlyAuthority {
+        require(newAuthority != address(0), "Invalid authority");
+        emit AuthorityTransferred(authority, newAuthority);
+        authority = newAuthority;
+    }
+
+    function issuerId(string memory code) public pure returns (bytes32) {
+        return keccak256(bytes(code));
+    }
+
+    function registerIssuer(
+        string calldata name,
+        string calldata code,
+        address[] calldata initialKeys,
+        uint64 validFrom,
+        uint64 validUntil
+    ) external onlyAuthority returns (bytes32 id) {
+        require(bytes(code).length > 0, "Empty issuer code");
+        require(validUntil == 0 || validUntil > validFrom, "Invalid validity period");
+        id = issuerId(code);
+        require(bytes(issuers[id].code).length == 0, "Issuer already registered");
+
+        issuers[id] = Issuer(name, code, validFrom, validUntil);
+        emit IssuerRegistered(id, name, code, validFrom, validUntil);
+        for (uint256 i = 0; i < initialKeys.length; i++) {
+            _addKey(id, initialKeys[i]);
+        }
+    }
+
+    // Extend, shorten or end an accreditation
+    function setValidity(bytes32 id, uint64 validFrom, uint64 validUntil) external onlyAuthority {
+        require(bytes(issuers[id].code).length != 0, "Issuer not registered");
+        require(validUntil == 0 || validUntil > validFrom, "Invalid validity period");
+        issuers[id].validFrom = validFrom;
+        issuers[id].validUntil = validUntil;
+        emit IssuerValidityChanged(id, validFrom, validUntil);
+    }
+
+    // The authority or an active key of the school may add keys
+    function addKey(bytes32 id, address key) external {
+        require(bytes(issuers[id].code).length != 0, "Issuer not registered");
+        require(msg.sender == authority || _isActiveKeyOf(msg.sender, id), "Not authorized for issuer");
+        _addKey(id, key);
+    }
+
+    // The authority or an active key of the same school may retire a key
+    function retireKey(address key) external {
+        Key storage k = keys[key];
+        require(k.issuerId != bytes32(0) && k.retiredAt == 0, "Key is not active");
+        require(msg.sender == authority || _isActiveKeyOf(msg.sender, k.issuerId), "Not authorized for issuer");
+        k.retiredAt = uint64(block.timestamp);
+        emit KeyRetired(k.issuerId, key);
+    }
+
+    // Replace the calling key with newKey in one transaction
+    function rotateKey(address newKey) external {
+        Key storage k = keys[msg.sender];
+        require(k.issuerId != bytes32(0) && k.retiredAt == 0, "Key is not active");
+        _addKey(k.issuerId, newKey);
+        k.retiredAt = uint64(block.timestamp);
+        emit KeyRetired(k.issuerId, msg.sender);
+    }
+
+    function getIssuer(bytes32 id)
+        external
+        view
+        returns (string memory name, string memory code, uint64 validFrom, uint64 validUntil)
+    {
+        Issuer storage issuer = issuers[id];
+        require(bytes(issuer.code).length != 0, "Issuer not registered");
+        return (issuer.name, issuer.code, issuer.validFrom, issuer.validUntil);
+    }
+
+    function getKey(address key) external view returns (bytes32 id, uint64 addedAt, uint64 retiredAt) {
+        Key storage k = keys[key];
+        return (k.issuerId, k.addedAt, k.retiredAt);
+    }
+
+    // Whether key could anchor for its school at the given time
+    function isAuthorized(address key, uint64 timestamp) external view returns (bool authorized, bytes32 id) {
+        Key storage k = keys[key];
+        id = k.issuerId;
+        if (id == bytes32(0)) {
+            return (false, id);
+        }
+        Issuer storage issuer = issuers[id];
+        authorized = timestamp >= k.addedAt
+            && (k.retiredAt == 0 || timestamp < k.retiredAt)
+            && timestamp >= issuer.validFrom
+            && (issuer.validUntil == 0 || timestamp < issuer.validUntil);
+    }
+
+    function _addKey(bytes32 id, address key) internal {
+        require(key != address(0), "Invalid key");
+        require(keys[key].issuerId == bytes32(0), "Key already registered");
+        keys[key] = Key(id, uint64(block.timestamp), 0);
+        emit KeyAdded(id, key);
+    }
+
+    function _isActiveKeyOf(address key, bytes32 id) internal view returns (bool) {
+        return keys[key].issuerId == id && keys[key].retiredAt == 0;
+    }
+}
@@ -4,29 +4,27 @@ pragma solidity ^0.8.0;
 
 import "./Pairing.sol";
 import { Constants } from "./Constants.sol";
-import "@openzeppelin/utils/Strings.sol";
 
 contract Verifier is Constants {
 
     //using Pairing for *;
 
     // The G1 generator
-    Pairing.G1Point SRS_G1_0 = Pairing.G1Point({
-        X: Constants.SRS_G1_X[0],
-        Y: Constants.SRS_G1_Y[0]
-    });
+    Pairing.G1Point SRS_G1_0;
 
     // The G2 generator
-    Pairing.G2Point g2Generator = Pairing.G2Point({
-        X: [ Constants.SRS_G2_X_0[0], Constants.SRS_G2_X_1[0] ],
-        Y: [ Constants.SRS_G2_Y_0[0], Constants.SRS_G2_Y_1[0] ]
+    Pairing.G2Point g2Generator;
 
-    });
+    Pairing.G2Point SRS_G2_1;
 
-    Pairing.G2Point SRS_G2_1 = Pairing.G2Point({
-        X: [ Constants.SRS_G2_X_0[1], Constants.SRS_G2_X_1[1] ],
-        Y: [ Constants.SRS_G2_Y_0[1], Constants.SRS_G2_Y_1[1] ]
-    });
+    // The opening key of the setup the commitments were made with: the G1 and G2 generators and
+    // tau * G2, see shplonk_verifier_params in services/shplonk.rs. G2 coordinates are encoded as
+    // the pairing precompile expects, imaginary part first.
+    constructor(Pairing.G1Point memory g1, Pairing.G2Point memory g2, Pairing.G2Point memory g2Tau) {
+        SRS_G1_0 = g1;
+        g2Generator = g2;
+        SRS_G2_1 = g2Tau;
+    }
 
     /*
      * Verifies a single-point evaluation of a polynominal using the Shplonk
@@ -202,7 +200,7 @@ contract Verifier is Constants {
             require(zEval == 0, "Verifier.verifyMulti: invalid _zCoeffs");
 
             uint256 iEval = evalPolyAt(_iCoeffs, _indices[i]);
-            require(iEval == _values[i], Strings.toString(iEval));
+            require(iEval == _values[i], "Verifier.verifyShplonk: invalid _iCoeffs");
         }
 
         // Generate the Shplonk commitments to the i and z polynominals
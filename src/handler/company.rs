@@ -4,65 +4,76 @@ use actix_web::{error, web, Result};
 use ipfs_api_backend_hyper::IpfsClient;
 use log::{error, info};
 use serde_json::Value;
-use web3::contract::Contract;
-use web3::{transports::Http, Web3};
 
 use crate::handler::student::get_images;
-use crate::models::{AuthVerifyData, AuthVerifyResultData, AuthenticationData};
-use crate::services::ethereum::get_transaction_data;
-use crate::services::ethereum::{CONTRACT_ABI, CONTRACT_ADDRESS};
-use crate::services::ipfs::get_json_from_ipfs;
+use crate::handler::{
+    anchor_report, certificate_opening, certificate_slot, check_erasure, check_issuer,
+    check_revocation, disclosed_values, issuer_rejection,
+};
+use crate::models::{
+    AttestationReceipt, AuthVerifyData, AuthVerifyResultData, AuthenticationData,
+    CompanyUploadQuery,
+};
+use crate::services::batches::shard_commitments;
+use crate::services::bindings::VerifyDataCall;
+use crate::services::ipfs::{get_json_from_ipfs, try_join_ordered};
+use crate::services::ledger::ATTESTATION_ENABLED;
+use crate::services::network::NetworkRegistry;
+use crate::services::wallet::wallet;
 
 pub async fn upload(
     auth_data: web::Json<AuthenticationData>,
+    query: web::Query<CompanyUploadQuery>,
     ipfs_client: web::Data<IpfsClient>,
 ) -> Result<web::Json<AuthVerifyData>> {
-    let mut data_vec_vec = Vec::new();
-    let mut images_vec_vec = Vec::new();
-    let mut tx_hashs_vec = Vec::new();
-
-    for (data_cid, tx_hash) in auth_data.data_cid.iter().zip(auth_data.tx_hash.iter()) {
-        let original_data_cid = get_json_from_ipfs(&ipfs_client, data_cid)
-            .await
-            .map_err(error::ErrorInternalServerError)?;
-        info!("IPFS原始数据: {original_data_cid}");
-        let original_data_cid_vec = serde_json::from_str::<Vec<String>>(&original_data_cid)
-            .map_err(error::ErrorInternalServerError)?;
-
-        let mut original_data_obj_vec = Vec::new();
-        let mut images_vec = Vec::new();
-
-        for original_data_cid in original_data_cid_vec {
-            let original_data = get_json_from_ipfs(&ipfs_client, &original_data_cid)
+    let ipfs_client: &IpfsClient = &ipfs_client;
+    let n_auth = auth_data.data_cid.len().min(auth_data.tx_hash.len());
+    let auth_type_vec = try_join_ordered(
+        auth_data.data_cid.iter().take(n_auth),
+        |data_cid| async move {
+            let original_data_cid = get_json_from_ipfs(ipfs_client, data_cid)
                 .await
                 .map_err(error::ErrorInternalServerError)?;
+            info!("IPFS原始数据: {original_data_cid}");
+            let original_data_cid_vec = serde_json::from_str::<Vec<String>>(&original_data_cid)
+                .map_err(error::ErrorInternalServerError)?;
 
-            let mut original_data_obj =
-                serde_json::from_str::<BTreeMap<String, Value>>(&original_data)
+            let records = try_join_ordered(original_data_cid_vec, |original_data_cid| async move {
+                let original_data = get_json_from_ipfs(ipfs_client, &original_data_cid)
+                    .await
                     .map_err(error::ErrorInternalServerError)?;
 
-            let images = get_images(&ipfs_client, &original_data_obj)
-                .await
-                .map_err(error::ErrorInternalServerError)?;
+                // 图片 CID 属于承诺的数据, 保留以便按原样重新计算求值
+                let original_data_obj =
+                    serde_json::from_str::<BTreeMap<String, Value>>(&original_data)
+                        .map_err(error::ErrorInternalServerError)?;
 
-            original_data_obj.remove("images");
+                let images = get_images(&original_data_obj);
 
-            original_data_obj_vec.push(original_data_obj);
-            images_vec.push(images);
-        }
+                Ok::<_, error::Error>((original_data_obj, images))
+            })
+            .await?;
 
-        data_vec_vec.push(original_data_obj_vec);
-        images_vec_vec.push(images_vec);
-        tx_hashs_vec.push(tx_hash.clone());
-    }
+            Ok::<_, error::Error>(records.into_iter().unzip::<_, _, Vec<_>, Vec<_>>())
+        },
+    )
+    .await?;
+
+    let (data_vec_vec, images_vec_vec): (Vec<_>, Vec<_>) = auth_type_vec.into_iter().unzip();
+    let tx_hashs_vec = auth_data.tx_hash[..n_auth].to_vec();
 
     let verified_data = AuthVerifyData {
+        id: auth_data.id.clone(),
         data: data_vec_vec,
         images: images_vec_vec,
         tx_hashs: tx_hashs_vec,
-        proof: auth_data.proof.clone(),
+        chain_ids: auth_data.chain_ids.iter().take(n_auth).copied().collect(),
+        shards: auth_data.shards.iter().take(n_auth).copied().collect(),
+        proof: auth_data.proof.iter().take(n_auth).cloned().collect(),
+        edu_types: auth_data.edu_types.iter().take(n_auth).cloned().collect(),
         zk_proof: auth_data.zk_proof.clone(),
         random: auth_data.random.clone(),
+        attest: query.attest,
     };
 
     info!("数据收集成功");
@@ -71,100 +82,156 @@ pub async fn upload(
 
 pub async fn verify_auth_data(
     auth_data: web::Json<AuthVerifyData>,
-    web3: web::Data<Web3<Http>>,
+    networks: web::Data<NetworkRegistry>,
 ) -> Result<web::Json<AuthVerifyResultData>> {
     info!("收到验证请求，开始处理...");
 
-    // 获取合约地址
-    let contract_address = CONTRACT_ADDRESS.get().ok_or_else(|| {
-        error!("合约未部署");
-        error::ErrorInternalServerError("Contract not deployed")
-    })?;
-
-    // 创建合约实例
-    let contract = Contract::from_json(
-        web3.eth(),
-        contract_address
-            .parse()
-            .map_err(error::ErrorInternalServerError)?,
-        CONTRACT_ABI.as_bytes(),
-    )
-    .map_err(error::ErrorInternalServerError)?;
+    if auth_data.attest && !*ATTESTATION_ENABLED {
+        return Err(error::ErrorForbidden("On-chain attestation is disabled"));
+    }
 
-    let mut blockchain_data_vec = Vec::new();
-    for tx_hash in auth_data.tx_hashs.iter() {
-        match get_transaction_data(&web3, tx_hash).await {
-            Ok(data) => {
-                blockchain_data_vec.push(data);
+    let is_zk = !auth_data.zk_proof.is_empty();
+
+    // 每张证书在其锚定批次上的打开, 每个批次从其所在网络读取
+    let mut openings = Vec::new();
+    let mut anchors = Vec::new();
+    for (i, tx_hash) in auth_data.tx_hashs.iter().enumerate() {
+        let network = networks
+            .network(auth_data.chain_ids.get(i).copied())
+            .map_err(error::ErrorBadRequest)?;
+        let fetched = match network.ledger.fetch_batch(tx_hash).await {
+            Ok(batch) => network
+                .ledger
+                .anchor_info(tx_hash)
+                .await
+                .map(|info| (batch, info)),
+            Err(e) => Err(e),
+        };
+        match fetched {
+            Ok((batch, info)) => {
+                let status = check_issuer(network, &info).await?;
+                // 证书编号与锚定交易一一对应, 分片与槽位取自批次登记
+                let id = auth_data
+                    .id
+                    .get(i)
+                    .ok_or_else(|| error::ErrorBadRequest("Missing certificate id"))?;
+                let slot = certificate_slot(
+                    &batch,
+                    id,
+                    auth_data.shards.get(i).copied().unwrap_or_default(),
+                )?;
+                if let Some(erasure) = check_erasure(&batch, &slot) {
+                    info!("证书已按数据主体请求删除: {id}");
+                    return Err(error::ErrorGone(format!(
+                        "Certificate {id} erased by subject request at {}",
+                        erasure.erased_at
+                    )));
+                }
+                let revocation =
+                    check_revocation(network.ledger.as_ref(), &batch, id, &slot, tx_hash).await?;
+                anchors.push(anchor_report(batch.anchor, info, &status));
+                if !status.is_accepted() || revocation.is_some() {
+                    match &revocation {
+                        Some(revocation) => info!("证书已撤销: {revocation:?}"),
+                        None => info!("{tx_hash}: {}", issuer_rejection(&status)),
+                    }
+                    return Ok(web::Json(AuthVerifyResultData {
+                        verified: false,
+                        tx_hash: tx_hash.clone(),
+                        attestation: None,
+                        anchors,
+                        revocation,
+                    }));
+                }
+                // 分片批次只取证书所在分片的承诺
+                let commitment = shard_commitments(&batch.commitments, slot.shard, slot.shards)
+                    .map_err(error::ErrorBadRequest)?;
+                let data = auth_data
+                    .data
+                    .get(i)
+                    .ok_or_else(|| error::ErrorBadRequest("Missing certificate data"))?;
+                let edu_types = match auth_data.edu_types.get(i) {
+                    Some(edu_types) => edu_types.clone(),
+                    None => (0..data.len() as u32).collect(),
+                };
+                let proof = auth_data
+                    .proof
+                    .get(i)
+                    .ok_or_else(|| error::ErrorBadRequest("Missing certificate proof"))?;
+                let proof = hex::decode(proof).map_err(error::ErrorBadRequest)?;
+                let mut opening = certificate_opening(commitment, &proof, &edu_types, &slot)?;
+                // 完整公开时按数据重新计算求值; 零知识模式下数据有隐藏字段,
+                // 求值取自证明块, 由合约与 halo2 证明的公开输入核对
+                if !is_zk {
+                    opening.values = disclosed_values(data, &edu_types)?;
+                }
+                openings.push(opening);
             }
             Err(e) => {
                 error!("获取交易数据失败: {}", e);
                 return Ok(web::Json(AuthVerifyResultData {
                     verified: false,
                     tx_hash: tx_hash.clone(),
+                    attestation: None,
+                    anchors,
+                    revocation: None,
                 }));
             }
         }
     }
 
-    let original_data_bytes =
-        web3::types::Bytes::from(serde_json::to_string(&auth_data.data).unwrap().as_bytes());
-    let blockchain_data_bytes = web3::types::Bytes::from(
-        serde_json::to_string(&blockchain_data_vec)
-            .unwrap()
-            .as_bytes(),
+    if openings.is_empty() {
+        return Err(error::ErrorBadRequest("No certificate to verify"));
+    }
+    let call = VerifyDataCall::from_openings(
+        &openings,
+        hex::decode(&auth_data.zk_proof).map_err(error::ErrorBadRequest)?,
     );
 
     info!("数据准备完毕");
 
-    // 获取以太坊账户
-    let accounts = web3
-        .eth()
-        .accounts()
-        .await
-        .map_err(error::ErrorInternalServerError)?;
+    // 验证合约与链无关, 在默认网络执行
+    let ledger = &networks
+        .default_network()
+        .map_err(error::ErrorInternalServerError)?
+        .ledger;
 
-    let account = accounts
-        .first()
-        .cloned()
-        .ok_or_else(|| error::ErrorInternalServerError("No Ethereum account available"))?;
-
-    // 调用智能合约验证
-    let call_result = contract
-        .call(
-            "verifyData",
-            (
-                original_data_bytes.clone(),
-                blockchain_data_bytes.clone(),
-                auth_data.proof.clone(),
-                auth_data.zk_proof.clone(),
-                auth_data.random.clone(),
-            ),
-            account,
-            Default::default(),
-        )
+    // 只读验证: eth_call 或本地EVM, 不产生交易
+    let verified = ledger
+        .verify(call.clone())
         .await
         .map_err(error::ErrorInternalServerError)?;
 
-    let tx_hash = format!("{call_result:?}");
-
-    // 查询验证结果
-    let verified: bool = contract
-        .query(
-            "verifyData",
-            (
-                original_data_bytes,
-                blockchain_data_bytes,
-                auth_data.proof.clone(),
-                auth_data.zk_proof.clone(),
-                auth_data.random.clone(),
-            ),
-            account,
-            Default::default(),
-            None,
-        )
+    if !auth_data.attest {
+        return Ok(web::Json(AuthVerifyResultData {
+            verified,
+            tx_hash: String::new(),
+            attestation: None,
+            anchors,
+            revocation: None,
+        }));
+    }
+
+    // 请求方显式要求时才在链上记录验证结果
+    let signer = wallet()
+        .map_err(error::ErrorInternalServerError)?
+        .default_signer();
+    let attestation = ledger
+        .attest_verification(signer, call)
         .await
         .map_err(error::ErrorInternalServerError)?;
-
-    Ok(web::Json(AuthVerifyResultData { verified, tx_hash }))
+    info!("验证结果已上链: {attestation:?}");
+
+    Ok(web::Json(AuthVerifyResultData {
+        verified: attestation.verified,
+        tx_hash: format!("{:?}", attestation.tx_hash),
+        attestation: Some(AttestationReceipt {
+            tx_hash: format!("{:?}", attestation.tx_hash),
+            block_number: attestation.block_number,
+            request_hash: format!("{:?}", attestation.request_hash),
+            verified: attestation.verified,
+        }),
+        anchors,
+        revocation: None,
+    }))
 }
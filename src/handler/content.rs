@@ -0,0 +1,196 @@
+use actix_web::http::header;
+use actix_web::{error, web, HttpRequest, HttpResponse, Result};
+use anyhow::Context;
+use futures_util::{stream, StreamExt};
+use ipfs_api_backend_hyper::{IpfsApi, IpfsClient};
+use log::info;
+
+use crate::services::batches::is_issued_image;
+use crate::services::cache::{is_valid_cid, CONTENT_CACHE};
+use crate::services::erasure::is_erased_content;
+use crate::services::image::sniff_content_type;
+use crate::services::ipfs::get_content_from_ipfs;
+
+// 内容可能因数据主体删除请求下线, 不标记 immutable, 下游缓存最多保留一小时
+const CACHE_CONTROL: &str = "public, max-age=3600";
+
+/// 内容在本服务上的访问地址
+pub fn content_url(cid: &str) -> String {
+    let base_url =
+        std::env::var("PUBLIC_BASE_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+    format!("{}/api/content/{cid}", base_url.trim_end_matches('/'))
+}
+
+/// `Range` 请求头的处理结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    // 格式不正确、多段或非 bytes 单位的请求头被忽略, 返回完整内容
+    Ignored,
+    // 闭区间 [start, end]
+    Satisfiable(usize, usize),
+    // 格式正确但不在内容之内, 返回 416
+    Unsatisfiable,
+}
+
+/// 解析单段 `Range: bytes=...` 请求头, 只有格式正确但无法满足的范围才是 Unsatisfiable
+pub fn parse_range(range: &str, len: usize) -> ByteRange {
+    let parse = |range: &str| {
+        let spec = range.trim().strip_prefix("bytes=")?;
+        if spec.contains(',') {
+            return None;
+        }
+        let (start, end) = spec.split_once('-')?;
+        match (start.trim(), end.trim()) {
+            ("", "") => None,
+            ("", suffix) => Some((None, Some(suffix.parse::<usize>().ok()?))),
+            (start, "") => Some((Some(start.parse::<usize>().ok()?), None)),
+            (start, end) => {
+                let (start, end) = (start.parse::<usize>().ok()?, end.parse::<usize>().ok()?);
+                (start <= end).then_some((Some(start), Some(end)))
+            }
+        }
+    };
+
+    match parse(range) {
+        None => ByteRange::Ignored,
+        // 后缀长度为 0 或内容为空时无法满足
+        Some((None, Some(suffix))) if suffix > 0 && len > 0 => {
+            ByteRange::Satisfiable(len.saturating_sub(suffix), len - 1)
+        }
+        Some((Some(start), end)) if start < len => {
+            ByteRange::Satisfiable(start, end.map_or(len - 1, |end| end.min(len - 1)))
+        }
+        Some(_) => ByteRange::Unsatisfiable,
+    }
+}
+
+/// 未缓存的内容直接从IPFS流式返回, 按首个分块判断类型
+async fn stream_content(ipfs_client: &IpfsClient, cid: &str, etag: String) -> Result<HttpResponse> {
+    let mut chunks = ipfs_client
+        .cat(cid)
+        .map(|chunk| chunk.context("Failed to get chunk from IPFS"));
+    let first = match chunks.next().await {
+        Some(chunk) => chunk.map_err(error::ErrorNotFound)?,
+        None => web::Bytes::new(),
+    };
+    let content_type = sniff_content_type(&first);
+    info!("流式返回内容, CID: {cid}, 类型: {content_type}");
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::CACHE_CONTROL, CACHE_CONTROL))
+        .content_type(content_type)
+        .streaming(stream::once(async move { Ok(first) }).chain(chunks)))
+}
+
+/// 已签发证书引用的图片. 只提供批次登记中的图片, 数据主体删除后返回 410
+pub async fn get_content(
+    req: HttpRequest,
+    cid: web::Path<String>,
+    ipfs_client: web::Data<IpfsClient>,
+) -> Result<HttpResponse> {
+    if !is_valid_cid(&cid) {
+        return Err(error::ErrorBadRequest("Invalid CID"));
+    }
+
+    // 先于条件请求检查, 已删除的内容不能以 304 继续使用缓存副本
+    if is_erased_content(&cid) {
+        return Ok(HttpResponse::Gone()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .finish());
+    }
+    if !is_issued_image(&cid) {
+        return Err(error::ErrorNotFound("Content not found"));
+    }
+
+    // CID 即内容哈希, 直接作为强 ETag
+    let etag = format!("\"{cid}\"");
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |v| {
+            v.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+    if not_modified {
+        return Ok(HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .finish());
+    }
+
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok());
+
+    // 范围请求需要完整内容, 经缓存读取; 其余未缓存的内容流式返回
+    let data = match (CONTENT_CACHE.get(&cid), range) {
+        (Some(data), _) => data,
+        (None, Some(_)) => get_content_from_ipfs(&ipfs_client, &cid)
+            .await
+            .map_err(error::ErrorNotFound)?,
+        (None, None) => return stream_content(&ipfs_client, &cid, etag).await,
+    };
+    let content_type = sniff_content_type(&data);
+    info!(
+        "返回内容, CID: {cid}, 类型: {content_type}, 大小: {} bytes",
+        data.len()
+    );
+
+    let range = range.map_or(ByteRange::Ignored, |range| parse_range(range, data.len()));
+    let (mut builder, body) = match range {
+        ByteRange::Satisfiable(start, end) => {
+            let mut builder = HttpResponse::PartialContent();
+            builder.insert_header((
+                header::CONTENT_RANGE,
+                format!("bytes {start}-{end}/{}", data.len()),
+            ));
+            (builder, data[start..=end].to_vec())
+        }
+        ByteRange::Unsatisfiable => {
+            return Ok(HttpResponse::RangeNotSatisfiable()
+                .insert_header((header::CONTENT_RANGE, format!("bytes */{}", data.len())))
+                .finish());
+        }
+        ByteRange::Ignored => (HttpResponse::Ok(), data.to_vec()),
+    };
+
+    Ok(builder
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::CACHE_CONTROL, CACHE_CONTROL))
+        .content_type(content_type)
+        .body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        use ByteRange::*;
+
+        assert_eq!(parse_range("bytes=0-99", 1000), Satisfiable(0, 99));
+        assert_eq!(parse_range("bytes=900-", 1000), Satisfiable(900, 999));
+        assert_eq!(parse_range("bytes=-100", 1000), Satisfiable(900, 999));
+        assert_eq!(parse_range("bytes=-2000", 1000), Satisfiable(0, 999));
+        assert_eq!(parse_range("bytes=500-5000", 1000), Satisfiable(500, 999));
+
+        // 格式正确但无法满足
+        assert_eq!(parse_range("bytes=1000-", 1000), Unsatisfiable);
+        assert_eq!(parse_range("bytes=1000-2000", 1000), Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), Unsatisfiable);
+
+        // 多段、非 bytes 单位或格式错误的请求头被忽略
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), Ignored);
+        assert_eq!(parse_range("items=0-1", 1000), Ignored);
+        assert_eq!(parse_range("bytes=5-1", 1000), Ignored);
+        assert_eq!(parse_range("bytes=-", 1000), Ignored);
+        assert_eq!(parse_range("bytes=abc-", 1000), Ignored);
+        assert_eq!(parse_range("bytes=10", 1000), Ignored);
+    }
+}
@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use actix_web::{error, web, HttpRequest, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use web3::types::{Address, H256};
+
+use crate::services::bindings::{IssuerRegistry, RegisteredIssuer, RegisteredKey};
+use crate::services::network::NetworkRegistry;
+use crate::services::wallet::{wallet, Signer};
+
+use super::{bearer_token, issuer_signer};
+
+lazy_static::lazy_static! {
+    // 管理接口令牌, 未配置时管理接口不可用
+    static ref ADMIN_API_TOKEN: Option<String> = std::env::var("ADMIN_API_TOKEN").ok();
+    // 认证机构在钱包中的签名账户名, 未配置时使用默认签名方
+    static ref REGISTRY_AUTHORITY_SIGNER: Option<String> =
+        std::env::var("REGISTRY_AUTHORITY_SIGNER").ok();
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegistryQuery {
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterIssuerRequest {
+    pub name: String,
+    pub code: String,
+    pub keys: Vec<Address>,
+    // 认证有效期, Unix 秒, valid_until 为 0 表示长期有效
+    pub valid_from: u64,
+    #[serde(default)]
+    pub valid_until: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ValidityRequest {
+    pub valid_from: u64,
+    #[serde(default)]
+    pub valid_until: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyRequest {
+    pub key: Address,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegistryTxResponse {
+    pub tx_hash: H256,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer_id: Option<H256>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IssuerResponse {
+    pub issuer_id: H256,
+    pub name: String,
+    pub code: String,
+    pub valid_from: u64,
+    pub valid_until: u64,
+}
+
+impl From<RegisteredIssuer> for IssuerResponse {
+    fn from(issuer: RegisteredIssuer) -> Self {
+        Self {
+            issuer_id: issuer.id,
+            name: issuer.name,
+            code: issuer.code,
+            valid_from: issuer.valid_from,
+            valid_until: issuer.valid_until,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct KeyResponse {
+    pub key: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer_id: Option<H256>,
+    pub added_at: u64,
+    pub retired_at: u64,
+}
+
+fn issuer_registry(
+    networks: &NetworkRegistry,
+    chain_id: Option<u64>,
+) -> Result<Arc<IssuerRegistry>> {
+    networks
+        .network(chain_id)
+        .map_err(error::ErrorNotFound)?
+        .issuer_registry
+        .clone()
+        .ok_or_else(|| error::ErrorNotFound("Network has no issuer registry"))
+}
+
+/// 校验管理令牌, 并返回认证机构的签名账户
+fn authority(req: &HttpRequest) -> Result<&'static Signer> {
+    let token = ADMIN_API_TOKEN
+        .as_deref()
+        .ok_or_else(|| error::ErrorForbidden("Admin API is disabled"))?;
+    if bearer_token(req) != Some(token) {
+        return Err(error::ErrorUnauthorized("Invalid admin token"));
+    }
+
+    wallet()
+        .and_then(|wallet| wallet.signer(REGISTRY_AUTHORITY_SIGNER.as_deref()))
+        .map_err(error::ErrorInternalServerError)
+}
+
+/// 认证机构登记学校
+pub async fn register_issuer(
+    req: HttpRequest,
+    query: web::Query<RegistryQuery>,
+    body: web::Json<RegisterIssuerRequest>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<RegistryTxResponse>> {
+    let signer = authority(&req)?;
+    let registry = issuer_registry(&networks, query.chain_id)?;
+
+    // 提前拒绝, 避免发送必然回滚的交易
+    let authority = registry
+        .authority()
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    if authority != signer.address() {
+        return Err(error::ErrorForbidden(
+            "Configured signer is not the registry authority",
+        ));
+    }
+
+    let tx_hash = registry
+        .register_issuer(
+            &body.name,
+            &body.code,
+            body.keys.clone(),
+            body.valid_from,
+            body.valid_until,
+            signer,
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    info!(
+        "已登记学校 {} ({}), 交易: {tx_hash:?}",
+        body.name, body.code
+    );
+
+    Ok(web::Json(RegistryTxResponse {
+        tx_hash,
+        issuer_id: Some(IssuerRegistry::issuer_id(&body.code)),
+    }))
+}
+
+/// 认证机构调整学校的认证有效期, 将 valid_until 设为当前时间即撤销认证
+pub async fn set_validity(
+    req: HttpRequest,
+    code: web::Path<String>,
+    query: web::Query<RegistryQuery>,
+    body: web::Json<ValidityRequest>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<RegistryTxResponse>> {
+    let signer = authority(&req)?;
+    let registry = issuer_registry(&networks, query.chain_id)?;
+    let issuer_id = IssuerRegistry::issuer_id(&code);
+
+    let tx_hash = registry
+        .set_validity(issuer_id, body.valid_from, body.valid_until, signer)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    info!("学校 {code} 认证有效期已更新, 交易: {tx_hash:?}");
+
+    Ok(web::Json(RegistryTxResponse {
+        tx_hash,
+        issuer_id: Some(issuer_id),
+    }))
+}
+
+/// 认证机构为学校添加密钥
+pub async fn add_key(
+    req: HttpRequest,
+    code: web::Path<String>,
+    query: web::Query<RegistryQuery>,
+    body: web::Json<KeyRequest>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<RegistryTxResponse>> {
+    let signer = authority(&req)?;
+    let registry = issuer_registry(&networks, query.chain_id)?;
+    let issuer_id = IssuerRegistry::issuer_id(&code);
+
+    let tx_hash = registry
+        .add_key(issuer_id, body.key, signer)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    info!("学校 {code} 添加密钥 {:?}, 交易: {tx_hash:?}", body.key);
+
+    Ok(web::Json(RegistryTxResponse {
+        tx_hash,
+        issuer_id: Some(issuer_id),
+    }))
+}
+
+/// 认证机构停用密钥, 用于密钥泄露等情况
+pub async fn retire_key(
+    req: HttpRequest,
+    key: web::Path<Address>,
+    query: web::Query<RegistryQuery>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<RegistryTxResponse>> {
+    let signer = authority(&req)?;
+    let registry = issuer_registry(&networks, query.chain_id)?;
+
+    let tx_hash = registry
+        .retire_key(*key, signer)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    info!("密钥 {key:?} 已停用, 交易: {tx_hash:?}");
+
+    Ok(web::Json(RegistryTxResponse {
+        tx_hash,
+        issuer_id: None,
+    }))
+}
+
+/// 学校以当前签名账户换用新密钥, 新密钥需随后配置到钱包中
+pub async fn rotate_key(
+    req: HttpRequest,
+    query: web::Query<RegistryQuery>,
+    body: web::Json<KeyRequest>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<RegistryTxResponse>> {
+    let signer = issuer_signer(&req)?;
+    let registry = issuer_registry(&networks, query.chain_id)?;
+
+    let tx_hash = registry
+        .rotate_key(body.key, signer)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    info!(
+        "密钥 {:?} 已轮换为 {:?}, 交易: {tx_hash:?}",
+        signer.address(),
+        body.key
+    );
+
+    Ok(web::Json(RegistryTxResponse {
+        tx_hash,
+        issuer_id: None,
+    }))
+}
+
+/// 按学校编码查询登记信息
+pub async fn get_issuer(
+    code: web::Path<String>,
+    query: web::Query<RegistryQuery>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<IssuerResponse>> {
+    let registry = issuer_registry(&networks, query.chain_id)?;
+    let issuer = registry
+        .get_issuer(IssuerRegistry::issuer_id(&code))
+        .await
+        .map_err(error::ErrorNotFound)?;
+    Ok(web::Json(issuer.into()))
+}
+
+/// 查询密钥所属学校及其启用、停用时间
+pub async fn get_key(
+    key: web::Path<Address>,
+    query: web::Query<RegistryQuery>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<KeyResponse>> {
+    let registry = issuer_registry(&networks, query.chain_id)?;
+    let RegisteredKey {
+        issuer_id,
+        added_at,
+        retired_at,
+    } = registry
+        .get_key(*key)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(web::Json(KeyResponse {
+        key: *key,
+        issuer_id: (!issuer_id.is_zero()).then_some(issuer_id),
+        added_at,
+        retired_at,
+    }))
+}
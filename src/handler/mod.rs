@@ -1,86 +1,150 @@
 use std::collections::BTreeMap;
 
-use actix_web::{error, web, Result};
-use ipfs_api_backend_hyper::{IpfsClient, TryFromUri};
-use log::{debug, error, info, warn};
+use actix_web::{error, web, HttpRequest, Result};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
 use rand_chacha::rand_core::SeedableRng;
 use rand_chacha::ChaCha20Rng;
 use serde_json::Value;
-use snark_verifier_sdk::snark_verifier::halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+use snark_verifier_sdk::snark_verifier::halo2_base::halo2_proofs::halo2curves::bn256::{Fr, G1};
+use snark_verifier_sdk::snark_verifier::halo2_base::halo2_proofs::halo2curves::serde::SerdeObject;
 use snark_verifier_sdk::snark_verifier::halo2_base::utils::ScalarField;
 use snark_verifier_sdk::snark_verifier::util::arithmetic::Field;
-use web3::contract::Contract;
-use web3::{transports::Http, Web3};
 
-use crate::models::{RecordData, VerifiedImage, VerifyResponse};
-use crate::services::compress_fr;
-use crate::services::ethereum::get_transaction_data;
-use crate::services::{
-    ethereum::{CONTRACT_ABI, CONTRACT_ADDRESS},
-    ipfs::get_image_from_ipfs,
+use crate::models::{
+    AnchorReport, BatchShard, RecordData, RevocationReport, VerifiedImage, VerifyResponse,
 };
+use crate::services::anchor::AnchorInfo;
+use crate::services::batches::{get_batch, revocation_index, shard_commitments, G1_SIZE};
+use crate::services::bindings::{Opening, VerifyDataCall};
+use crate::services::cache::{CacheMetrics, CONTENT_CACHE};
+use crate::services::compress_fr;
+use crate::services::erasure::{get_erasure, ErasureRecord};
+use crate::services::inclusion::AnchorStatus;
+use crate::services::issuers::{issuer_status, IssuerStatus, ISSUER_DIRECTORY};
+use crate::services::ledger::{FetchedBatch, Ledger, RevocationReason};
+use crate::services::network::{Network, NetworkRegistry};
+use crate::services::shplonk::{domain_point, shplonk_verify, Commit, Proof, MAX_DEGREE};
+use crate::services::wallet::{wallet, Signer};
 
 pub mod company;
+pub mod content;
+pub mod issuers;
 pub mod school;
 pub mod student;
+pub mod transparency;
 
 pub async fn verify_hash(
     certificate_data: web::Json<serde_json::Value>,
-    web3: web::Data<Web3<Http>>,
+    networks: web::Data<NetworkRegistry>,
 ) -> Result<web::Json<VerifyResponse>> {
     info!("收到验证请求，开始处理...");
 
-    // 获取合约地址
-    let contract_address = CONTRACT_ADDRESS.get().ok_or_else(|| {
-        error!("合约未部署");
-        error::ErrorInternalServerError("Contract not deployed")
-    })?;
-
-    // 创建合约实例
-    let contract = Contract::from_json(
-        web3.eth(),
-        contract_address
-            .parse()
-            .map_err(error::ErrorInternalServerError)?,
-        CONTRACT_ABI.as_bytes(),
-    )
-    .map_err(error::ErrorInternalServerError)?;
-
     // 从请求中提取数据
     let tx_hash = certificate_data["tx_hash"]
         .as_str()
         .ok_or_else(|| error::ErrorBadRequest("Missing tx_hash field"))?;
+    let id = certificate_data["id"]
+        .as_str()
+        .ok_or_else(|| error::ErrorBadRequest("Missing id field"))?;
+    let proof = hex::decode(certificate_data["proof"].as_str().unwrap_or_default())
+        .map_err(error::ErrorBadRequest)?;
 
-    let original_data = serde_json::to_string(&certificate_data["original_data"])
-        .map_err(error::ErrorInternalServerError)?;
-    let original_data_bytes = web3::types::Bytes::from(original_data.as_bytes());
+    // 原始数据为证书的各类学历, 单个对象视为一类
+    let original_data = match &certificate_data["original_data"] {
+        Value::Array(records) => records.clone(),
+        record => vec![record.clone()],
+    };
+    let original_data =
+        serde_json::from_value::<Vec<BTreeMap<String, Value>>>(Value::Array(original_data))
+            .map_err(error::ErrorBadRequest)?;
+    // 证书的全部学历类型依次展示, 与签发时的证明块一一对应
+    let edu_types = (0..original_data.len() as u32).collect::<Vec<_>>();
+
+    // 证书锚定所在的网络
+    let network = networks
+        .network(certificate_data["chain_id"].as_u64())
+        .map_err(error::ErrorBadRequest)?;
+    let ledger = &network.ledger;
 
-    // 从tx_hash获取区块链上的数据
-    let blockchain_data = get_transaction_data(&web3, tx_hash)
+    // 读取tx_hash锚定的承诺
+    let batch = ledger
+        .fetch_batch(tx_hash)
         .await
         .map_err(error::ErrorInternalServerError)?;
-
-    // 获取以太坊账户
-    let accounts = web3
-        .eth()
-        .accounts()
+    let info = ledger
+        .anchor_info(tx_hash)
         .await
         .map_err(error::ErrorInternalServerError)?;
 
-    let account = accounts
-        .first()
-        .cloned()
-        .ok_or_else(|| error::ErrorInternalServerError("No Ethereum account available"))?;
+    // 锚定账户须是锚定时有效的已认证学校密钥
+    let status = check_issuer(network, &info).await?;
+    if !status.is_accepted() {
+        return Ok(web::Json(VerifyResponse {
+            verified: false,
+            message: issuer_rejection(&status),
+            data: None,
+            anchor: Some(anchor_report(batch.anchor, info, &status)),
+            revocation: None,
+        }));
+    }
+
+    // 分片与槽位取自批次登记, 证书自述的值须一致
+    let slot = certificate_slot(
+        &batch,
+        id,
+        BatchShard {
+            shard: certificate_data["shard"].as_u64().unwrap_or_default() as u32,
+            shards: certificate_data["shards"].as_u64().unwrap_or_default() as u32,
+            index: certificate_data["index"].as_u64().unwrap_or_default() as u32,
+            domain_size: certificate_data["domain_size"].as_u64().unwrap_or_default() as u32,
+        },
+    )?;
+
+    // 数据主体已删除的证书不再验证
+    if let Some(erasure) = check_erasure(&batch, &slot) {
+        return Ok(web::Json(VerifyResponse {
+            verified: false,
+            message: format!(
+                "Certificate erased by subject request at {}",
+                erasure.erased_at
+            ),
+            data: None,
+            anchor: Some(anchor_report(batch.anchor, info, &status)),
+            revocation: None,
+        }));
+    }
+
+    // 发证方已撤销的证书不再验证
+    if let Some(revocation) = check_revocation(ledger.as_ref(), &batch, id, &slot, tx_hash).await? {
+        return Ok(web::Json(VerifyResponse {
+            verified: false,
+            message: match &revocation.superseded_by {
+                Some(batch_id) => format!("Certificate superseded by correction batch {batch_id}"),
+                None => format!("Certificate revoked by issuer: {:?}", revocation.reason),
+            },
+            data: None,
+            anchor: Some(anchor_report(batch.anchor, info, &status)),
+            revocation: Some(revocation),
+        }));
+    }
+
+    // 分片批次只取证书所在分片的承诺
+    let commitment = shard_commitments(&batch.commitments, slot.shard, slot.shards)
+        .map_err(error::ErrorBadRequest)?;
+    // 签发时按批次的学历类型数生成证明块, 证书只取其自身的类型
+    let proof = proof
+        .get(..edu_types.len() * OPENING_SIZE)
+        .ok_or_else(|| error::ErrorBadRequest("Invalid certificate proof"))?;
+    let mut opening = certificate_opening(commitment, proof, &edu_types, &slot)?;
+    // 求值按原始数据重新计算, 不采用证明中给出的值
+    opening.values = disclosed_values(&original_data, &edu_types)?;
+
+    let call = VerifyDataCall::from_openings(&[opening], Vec::new());
 
     // 调用智能合约验证
-    let verified: bool = contract
-        .query(
-            "verifyData",
-            (original_data_bytes.clone(), blockchain_data.clone()),
-            account,
-            Default::default(),
-            None,
-        )
+    let verified = ledger
+        .verify(call)
         .await
         .map_err(error::ErrorInternalServerError)?;
 
@@ -88,49 +152,30 @@ pub async fn verify_hash(
         // 如果验证成功，处理图片数据
         let verified_data = None;
 
-        // 创建IPFS客户端
-        let ipfs_url =
-            std::env::var("IPFS_API_URL").unwrap_or_else(|_| "http://localhost:5001".to_string());
-        if let Ok(ipfs_client) = IpfsClient::from_str(&ipfs_url) {
-            if let Some(original_data_obj) = certificate_data["original_data"].as_object() {
-                let mut images = Vec::new();
-
-                // 处理图片数据
-                if let Some(images_array) =
-                    original_data_obj.get("images").and_then(|v| v.as_array())
-                {
-                    for image in images_array {
-                        if let (Some(name), Some(ipfs_cid), Some(size)) = (
-                            image.get("name").and_then(|v| v.as_str()),
-                            image.get("ipfs_cid").and_then(|v| v.as_str()),
-                            image.get("size").and_then(|v| v.as_u64()),
-                        ) {
-                            match get_image_from_ipfs(&ipfs_client, ipfs_cid).await {
-                                Ok(image_data) => {
-                                    let base64_data = base64::encode(&image_data);
-                                    images.push(VerifiedImage {
-                                        ipfs_cid: ipfs_cid.to_string(),
-                                        data: base64_data,
-                                    });
-                                }
-                                Err(e) => {
-                                    error!("获取图片失败 {ipfs_cid}: {e}");
-                                }
-                            }
-                        }
+        if let Some(original_data_obj) = certificate_data["original_data"].as_object() {
+            let mut images = Vec::new();
+
+            // 处理图片数据, 图片内容通过 /api/content/{cid} 获取
+            if let Some(images_array) = original_data_obj.get("images").and_then(|v| v.as_array()) {
+                for image in images_array {
+                    if let Some(ipfs_cid) = image.get("ipfs_cid").and_then(|v| v.as_str()) {
+                        images.push(VerifiedImage {
+                            ipfs_cid: ipfs_cid.to_string(),
+                            url: content::content_url(ipfs_cid),
+                        });
                     }
                 }
+            }
 
-                // 创建验证数据响应
-                // let original_data = original_data_obj.clone().into_iter().collect();
+            // 创建验证数据响应
+            // let original_data = original_data_obj.clone().into_iter().collect();
 
-                // verified_data = Some(VerifiedData {
-                //     original_data,
-                //     images,
-                //     tx_hash: tx_hash.to_string(),
-                //     cid: cid.to_string(),
-                // });
-            }
+            // verified_data = Some(VerifiedData {
+            //     original_data,
+            //     images,
+            //     tx_hash: tx_hash.to_string(),
+            //     cid: cid.to_string(),
+            // });
         }
 
         (
@@ -145,9 +190,242 @@ pub async fn verify_hash(
         verified,
         message,
         data,
+        anchor: Some(anchor_report(batch.anchor, info, &status)),
+        revocation: None,
     }))
 }
 
+/// 证书在批次中的分片与槽位. 登记了该批次时按证书编号取登记中的位置, 分片数与锚定的承诺数核对;
+/// 未登记的旧批次按单个分片处理. 证书自述了分片时须与推出的值一致
+pub fn certificate_slot(batch: &FetchedBatch, id: &str, claimed: BatchShard) -> Result<BatchShard> {
+    let slot = match get_batch(&format!("{:?}", batch.batch_id)) {
+        Some(record) => {
+            if record.shards.len() * record.edu_types() * G1_SIZE != batch.commitments.len() {
+                return Err(error::ErrorConflict(
+                    "Batch record does not match anchored commitments",
+                ));
+            }
+            let (shard, index) = record
+                .position(id)
+                .ok_or_else(|| error::ErrorBadRequest("Certificate not issued in this batch"))?;
+            BatchShard {
+                shard: shard as u32,
+                shards: record.shards.len() as u32,
+                index: index as u32,
+                domain_size: record.shards[shard].domain_size() as u32,
+            }
+        }
+        None => BatchShard {
+            shard: 0,
+            shards: 1,
+            ..claimed
+        },
+    };
+
+    if claimed.shards != 0 && claimed != slot {
+        warn!("证书自述的槽位与批次不符: {claimed:?}, 实际 {slot:?}");
+        return Err(error::ErrorBadRequest(
+            "Certificate shard or slot does not match the batch",
+        ));
+    }
+    Ok(slot)
+}
+
+/// 证书槽位的打开点 ω^index. 旧证书没有定义域大小时取 1
+pub fn opening_point(index: u64, domain_size: u64) -> Result<Fr> {
+    let domain_size = domain_size.max(1) as usize;
+    if !domain_size.is_power_of_two() || domain_size > MAX_DEGREE || index as usize >= domain_size {
+        return Err(error::ErrorBadRequest(format!(
+            "invalid slot {index} of domain {domain_size}"
+        )));
+    }
+    Ok(domain_point(index as usize, domain_size))
+}
+
+// 持有人证明中每个学历类型一块: 证明(96bytes) || 值(32bytes)
+pub const OPENING_SIZE: usize = G1_SIZE + 32;
+
+/// 证书在其分片上的打开. commitments 为分片的全部承诺, edu_types 为展示的学历类型,
+/// proof 为这些类型依次的证明块; 值取自证明块
+pub fn certificate_opening(
+    commitments: &[u8],
+    proof: &[u8],
+    edu_types: &[u32],
+    slot: &BatchShard,
+) -> Result<Opening> {
+    if edu_types.is_empty() || proof.len() != edu_types.len() * OPENING_SIZE {
+        return Err(error::ErrorBadRequest("Invalid certificate proof"));
+    }
+
+    let mut opening = Opening {
+        point: opening_point(slot.index.into(), slot.domain_size.into())?,
+        ..Default::default()
+    };
+    for (&edu_type, chunk) in edu_types.iter().zip(proof.chunks_exact(OPENING_SIZE)) {
+        let edu_type = edu_type as usize;
+        let commitment = commitments
+            .chunks_exact(G1_SIZE)
+            .nth(edu_type)
+            .and_then(G1::from_raw_bytes)
+            .ok_or_else(|| error::ErrorBadRequest(format!("No commitment for type {edu_type}")))?;
+        let (proof, value) = G1::from_raw_bytes(&chunk[..G1_SIZE])
+            .zip(Fr::from_raw_bytes(&chunk[G1_SIZE..]))
+            .ok_or_else(|| error::ErrorBadRequest("Invalid certificate proof"))?;
+
+        opening.commitments.push(commitment);
+        opening.proofs.push(proof);
+        opening.values.push(value);
+    }
+    Ok(opening)
+}
+
+/// 在服务端逐一检查打开, 与合约的校验等价, 不经过链上调用
+pub fn verify_opening(opening: &Opening) -> bool {
+    opening.commitments.len() == opening.values.len()
+        && opening.proofs.len() == opening.values.len()
+        && opening
+            .commitments
+            .iter()
+            .zip(&opening.proofs)
+            .zip(&opening.values)
+            .all(|((commitment, proof), value)| {
+                shplonk_verify(Commit(*commitment), Proof(*proof), *value, opening.point)
+            })
+}
+
+/// 公开数据的各类型求值, 与签发时的压缩方式一致; 每个展示的学历类型对应一份数据
+pub fn disclosed_values(records: &[BTreeMap<String, Value>], edu_types: &[u32]) -> Result<Vec<Fr>> {
+    if records.len() != edu_types.len() {
+        return Err(error::ErrorBadRequest(
+            "Disclosed data does not match the certificate types",
+        ));
+    }
+    Ok(compress_edu_data(records, *RANDOM))
+}
+
+/// 证书的删除记录, 按锚定批次的发证方、批次ID与槽位查找
+pub fn check_erasure(batch: &FetchedBatch, slot: &BatchShard) -> Option<ErasureRecord> {
+    get_erasure(
+        batch.issuer,
+        batch.batch_id,
+        revocation_index(slot.shard as usize, slot.index as usize),
+    )
+}
+
+/// 证书在其锚定批次中的撤销记录. 发证方与批次ID取自锚定事件而非证书自述,
+/// 撤销按 `certificate_slot` 推出的分片与槽位登记
+pub async fn check_revocation(
+    ledger: &dyn Ledger,
+    batch: &FetchedBatch,
+    id: &str,
+    slot: &BatchShard,
+    tx_hash: &str,
+) -> Result<Option<RevocationReport>> {
+    let index = revocation_index(slot.shard as usize, slot.index as usize);
+    let reason = ledger
+        .revocation(batch.issuer, batch.batch_id, index)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let Some(reason) = reason else {
+        return Ok(None);
+    };
+
+    let superseded_by = if reason == RevocationReason::Superseded {
+        ledger
+            .superseded_by(batch.issuer, batch.batch_id, index)
+            .await
+            .map_err(error::ErrorInternalServerError)?
+    } else {
+        None
+    };
+    Ok(Some(RevocationReport {
+        id: id.to_string(),
+        tx_hash: tx_hash.to_string(),
+        reason,
+        reason_code: reason.code(),
+        superseded_by: superseded_by.map(|batch_id| format!("{batch_id:?}")),
+    }))
+}
+
+/// 按网络的发证方登记合约检查锚定账户
+pub async fn check_issuer(network: &Network, info: &AnchorInfo) -> Result<IssuerStatus> {
+    issuer_status(
+        network.issuer_registry.as_deref(),
+        info.sender,
+        info.timestamp,
+    )
+    .await
+    .map_err(error::ErrorInternalServerError)
+}
+
+/// 请求头中的 Bearer 令牌
+pub fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// 按学校接口令牌确定发证学校的签名账户, 不接受请求方指定学校
+pub fn issuer_signer(req: &HttpRequest) -> Result<&'static Signer> {
+    let token =
+        bearer_token(req).ok_or_else(|| error::ErrorUnauthorized("Missing issuer API token"))?;
+    wallet()
+        .map_err(error::ErrorInternalServerError)?
+        .authenticate(token)
+        .map_err(error::ErrorUnauthorized)
+}
+
+/// 锚定账户未通过登记校验时的提示
+pub fn issuer_rejection(status: &IssuerStatus) -> String {
+    match status {
+        IssuerStatus::Unregistered => "Anchor sender is not a registered issuer key".to_string(),
+        IssuerStatus::Unauthorized { issuer_id } => format!(
+            "Issuer key was retired or accreditation of {issuer_id:?} was not valid at anchoring time"
+        ),
+        _ => "Issuer accepted".to_string(),
+    }
+}
+
+/// 响应中的锚定信息, 已登记的学校取登记合约中的名称, 否则按发证方目录解析
+pub fn anchor_report(
+    anchor: AnchorStatus,
+    info: AnchorInfo,
+    issuer: &IssuerStatus,
+) -> AnchorReport {
+    let (status, block_hash) = match anchor {
+        AnchorStatus::Asserted => ("asserted", None),
+        AnchorStatus::Proven { block_hash, .. } => ("proven", Some(format!("{block_hash:?}"))),
+    };
+    let issued_at = DateTime::<Utc>::from_timestamp(info.timestamp as i64, 0)
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default();
+
+    AnchorReport {
+        status: status.to_string(),
+        block_number: info.block_number,
+        block_hash,
+        issued_at,
+        issuer_address: format!("{:?}", info.sender),
+        issuer_name: match issuer {
+            IssuerStatus::Authorized { name, .. } => Some(name.clone()),
+            _ => ISSUER_DIRECTORY
+                .resolve(info.sender)
+                .map(|issuer| issuer.name.clone()),
+        },
+        issuer_code: match issuer {
+            IssuerStatus::Authorized { code, .. } => Some(code.clone()),
+            _ => None,
+        },
+        issuer_status: issuer.as_str().to_string(),
+        confirmations: info.confirmations,
+    }
+}
+
+pub async fn cache_metrics() -> Result<web::Json<CacheMetrics>> {
+    Ok(web::Json(CONTENT_CACHE.metrics()))
+}
+
 lazy_static::lazy_static! {
     pub static ref RANDOM: Fr = {
         let mut rng = ChaCha20Rng::seed_from_u64(0);
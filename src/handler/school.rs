@@ -1,22 +1,48 @@
-use actix_web::{error, web, HttpResponse, Result};
+use std::collections::{BTreeMap, BTreeSet};
+
+use actix_multipart::Multipart;
+use actix_web::{error, web, HttpRequest, HttpResponse, Result};
+use futures_util::TryStreamExt;
 use ipfs_api_backend_hyper::IpfsClient;
-use log::{error, info};
-use serde_json::json;
+use log::{error, info, warn};
+use serde_json::{json, Value};
 use snark_verifier_sdk::snark_verifier::halo2_base::halo2_proofs::halo2curves::{
-    bn256::Fr, serde::SerdeObject,
+    bn256::{Fr, G1},
+    serde::SerdeObject,
 };
-use web3::{transports::Http, Web3};
+use web3::types::{Address, H256};
 
 use crate::services::{
-    certificate::generate_certificate, ethereum::send_transaction, ipfs::process_images,
+    anchor::schema_hash,
+    batches::{
+        get_batch, max_batch_size, record_batch, shard_commitments, BatchRecord, ShardRecord,
+    },
+    certificate::generate_certificate,
+    ipfs::{get_json_from_ipfs, process_images},
+    ledger::FetchedBatch,
+    network::{Network, NetworkRegistry},
+    revocation::{publish_revocation_root, REVOCATION_TREE},
+    wallet::Signer,
+};
+use crate::services::{
+    image::{normalize_image, IMAGE_POLICY},
+    ipfs::{try_join_ordered, upload_record_to_ipfs, upload_to_ipfs},
 };
-use crate::services::{hash_to_u64, ipfs::upload_to_ipfs};
 use crate::{
-    models::{RecordData, UploadResponse},
-    services::shplonk::{shplonk_lagrange_commit, shplonk_lagrange_open, Commit, Proof},
+    models::{
+        BatchShard, Certificate, CorrectionRequest, CorrectionResponse, ImageUploadResult,
+        RecordData, RevokeRequest, RevokeResponse, SupersessionResult, UploadQuery, UploadResponse,
+    },
+    services::shplonk::{
+        domain_point, domain_size, shplonk_lagrange_commit, shplonk_lagrange_delta,
+        shplonk_lagrange_open, shplonk_lagrange_update, Commit, Proof, MAX_DEGREE,
+    },
 };
 
-use super::{classify_edu_data, compress_edu_data, RANDOM};
+use super::{
+    certificate_opening, classify_edu_data, compress_edu_data, issuer_signer, verify_opening,
+    RANDOM,
+};
 
 pub struct School {
     random: Fr,
@@ -27,68 +53,124 @@ impl School {
         Self { random: *RANDOM }
     }
 
-    fn handle_edu_data(&self, edus: Vec<RecordData>) -> Vec<Vec<Fr>> {
-        classify_edu_data(edus)
+    // 每个学历类型一个多项式, 槽位 i 为第 i 个学生的该类学历, 没有时为 0,
+    // 各多项式中同一学生的槽位一致
+    fn handle_edu_data(&self, edus: &[RecordData], edu_types: usize) -> Vec<Vec<Fr>> {
+        let values = edus
             .iter()
-            .map(|edu| compress_edu_data(edu, self.random))
+            .map(|edu| compress_edu_data(&edu.data, self.random))
+            .collect::<Vec<_>>();
+        (0..edu_types)
+            .map(|edu_type| {
+                values
+                    .iter()
+                    .map(|values| values.get(edu_type).copied().unwrap_or(Fr::zero()))
+                    .collect()
+            })
             .collect::<Vec<_>>()
     }
 
-    fn commit(&self, edu_type_compress_evals: &[Vec<Fr>]) -> Vec<Commit> {
+    // 求值补零到 domain_size, 承诺与打开使用同一定义域
+    fn commit(&self, edu_type_compress_evals: &[Vec<Fr>], domain_size: usize) -> Vec<Commit> {
         edu_type_compress_evals
             .iter()
-            .map(|poly_evals| shplonk_lagrange_commit(poly_evals))
+            .map(|poly_evals| shplonk_lagrange_commit(poly_evals, domain_size))
             .collect::<Vec<_>>()
     }
 
-    fn open(&self, idx: u64, edu_type_compress_evals: &[Vec<Fr>]) -> Vec<(Proof, Fr)> {
+    // 在学生槽位对应的定义域元素 ω^index 处打开, 得到的值即承诺的求值
+    fn open(
+        &self,
+        index: usize,
+        edu_type_compress_evals: &[Vec<Fr>],
+        domain_size: usize,
+    ) -> Vec<(Proof, Fr)> {
+        let point = domain_point(index, domain_size);
         edu_type_compress_evals
             .iter()
-            .map(|poly_evals| shplonk_lagrange_open(poly_evals, Fr::from_raw([idx, 0, 0, 0])))
+            .map(|poly_evals| shplonk_lagrange_open(poly_evals, point, domain_size))
             .collect::<Vec<_>>()
     }
 }
 
+// 每个学历类型的 证明(96bytes) || 值(32bytes)
+fn encode_proofs(proofs: &[(Proof, Fr)]) -> Vec<u8> {
+    proofs
+        .iter()
+        .flat_map(|proof| [proof.0 .0.to_raw_bytes(), proof.1.to_raw_bytes()].concat())
+        .collect()
+}
+
+// 请求校验: 单个批次的学生数上限为 MAX_BATCH_SHARDS 个分片
+fn check_batch_size(size: usize) -> Result<()> {
+    if size > max_batch_size() {
+        return Err(error::ErrorPayloadTooLarge(format!(
+            "Batch exceeds {} records",
+            max_batch_size()
+        )));
+    }
+    Ok(())
+}
+
 async fn handle_images(edus: &mut [RecordData], ipfs_client: &IpfsClient) -> Result<()> {
-    for edu in edus {
-        for data in edu.data.iter_mut() {
-            if let Some(images) = data.get_mut("images") {
-                if let Some(images_array) = images.as_array_mut() {
-                    let images_vec = images_array.to_vec();
-                    match process_images(ipfs_client, &images_vec).await {
-                        Ok(processed_images) => *images = json!(processed_images),
-                        Err(e) => {
-                            error!("处理图片失败: {e}");
-                            return Err(error::ErrorInternalServerError(e));
-                        }
-                    }
-                }
-            }
+    let images_vec = edus
+        .iter()
+        .flat_map(|edu| edu.data.iter())
+        .map(|data| data.get("images").and_then(Value::as_array).cloned())
+        .collect::<Vec<_>>();
+
+    let processed_images_vec = try_join_ordered(images_vec, |images| async move {
+        match images {
+            Some(images) => process_images(ipfs_client, &images).await.map(Some),
+            None => Ok(None),
+        }
+    })
+    .await
+    .map_err(|e| {
+        error!("处理图片失败: {e}");
+        error::ErrorInternalServerError(e)
+    })?;
+
+    for (data, processed_images) in edus
+        .iter_mut()
+        .flat_map(|edu| edu.data.iter_mut())
+        .zip(processed_images_vec)
+    {
+        if let Some(processed_images) = processed_images {
+            data.insert("images".to_string(), json!(processed_images));
         }
     }
 
     Ok(())
 }
 
+// 证书数据引用的图片 CID, 登记到批次后可通过 /api/content 获取
+fn record_images(records: &[RecordData]) -> BTreeSet<String> {
+    records
+        .iter()
+        .flat_map(|record| record.data.iter())
+        .filter_map(|data| data.get("images").and_then(Value::as_array))
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect()
+}
+
 async fn handle_origin_data(
     records: &[RecordData],
     ipfs_client: &IpfsClient,
 ) -> Result<Vec<String>> {
-    let mut origin_cids = Vec::with_capacity(records.len());
-
-    for record in records {
-        let mut student_origin_cids = Vec::with_capacity(record.data.len());
-        for data in record.data.iter() {
-            let origin_data_cid = upload_to_ipfs(
+    try_join_ordered(records, |record| async move {
+        // 每条记录以各自的密钥加密, 数据主体删除时销毁密钥; 索引只含 CID, 明文存储
+        let student_origin_cids = try_join_ordered(record.data.iter(), |data| {
+            upload_record_to_ipfs(
                 ipfs_client,
                 serde_json::to_string(&data).unwrap().as_bytes().to_vec(),
             )
-            .await
-            .map_err(error::ErrorInternalServerError)?;
-            student_origin_cids.push(origin_data_cid);
-        }
+        })
+        .await?;
 
-        let origin_data_cid = upload_to_ipfs(
+        upload_to_ipfs(
             ipfs_client,
             serde_json::to_string(&student_origin_cids)
                 .unwrap()
@@ -96,81 +178,332 @@ async fn handle_origin_data(
                 .to_vec(),
         )
         .await
+    })
+    .await
+    .map_err(error::ErrorInternalServerError)
+}
+
+// 证书在IPFS上的各类学历数据, original_data_cid 指向按学历类型排列的数据CID列表
+async fn certificate_records(
+    ipfs_client: &IpfsClient,
+    original_data_cid: &str,
+) -> Result<Vec<BTreeMap<String, Value>>> {
+    let edu_type_cids = get_json_from_ipfs(ipfs_client, original_data_cid)
+        .await
         .map_err(error::ErrorInternalServerError)?;
+    let str_cids =
+        serde_json::from_str::<Vec<String>>(&edu_type_cids).map_err(error::ErrorBadRequest)?;
+    let mut records = Vec::with_capacity(str_cids.len());
+    for cid in str_cids {
+        let record = get_json_from_ipfs(ipfs_client, &cid)
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        records.push(
+            serde_json::from_str::<BTreeMap<String, Value>>(&record)
+                .map_err(error::ErrorBadRequest)?,
+        );
+    }
+    Ok(records)
+}
 
-        origin_cids.push(origin_data_cid);
+// 记录须为证书槽位上承诺的数据: 按登记的求值为该槽位生成打开证明,
+// 以记录的压缩值在锚定的承诺上校验, 学生没有的学历类型求值为 0
+fn check_certificate_records(
+    batch: &FetchedBatch,
+    batch_record: &BatchRecord,
+    id: &str,
+    records: &[BTreeMap<String, Value>],
+) -> Result<()> {
+    let (shard, index) = batch_record
+        .position(id)
+        .ok_or_else(|| error::ErrorNotFound("Certificate not issued in this batch"))?;
+    let evals = batch_record.shards[shard]
+        .evals()
+        .map_err(error::ErrorInternalServerError)?;
+    let mut values = compress_edu_data(records, *RANDOM);
+    if values.len() > evals.len() {
+        return Err(error::ErrorForbidden(
+            "Records do not match the certificate",
+        ));
     }
+    values.resize(evals.len(), Fr::zero());
 
-    Ok(origin_cids)
+    let slot = BatchShard {
+        shard: shard as u32,
+        shards: batch_record.shards.len() as u32,
+        index: index as u32,
+        domain_size: batch_record.shards[shard].domain_size() as u32,
+    };
+    let commitments = shard_commitments(&batch.commitments, slot.shard, slot.shards)
+        .map_err(error::ErrorConflict)?;
+    let proofs = School::new().open(index, &evals, slot.domain_size as usize);
+    let edu_types = (0..evals.len() as u32).collect::<Vec<_>>();
+    let mut opening = certificate_opening(commitments, &encode_proofs(&proofs), &edu_types, &slot)?;
+    opening.values = values;
+    if !verify_opening(&opening) {
+        warn!("记录与证书 {id} 的锚定承诺不符");
+        return Err(error::ErrorForbidden(
+            "Records do not match the certificate",
+        ));
+    }
+    Ok(())
 }
 
-pub async fn upload_and_gen_cert(
-    records: web::Json<Vec<RecordData>>,
-    web3: web::Data<Web3<Http>>,
-    ipfs_client: web::Data<IpfsClient>,
-) -> Result<web::Json<UploadResponse>> {
-    info!("收到上传请求，开始处理...");
+// 零知识展示不暴露槽位, 已撤销或被取代的证书需将各条记录加入撤销累加器并发布新根
+async fn revoke_records<'a>(
+    networks: &NetworkRegistry,
+    records: impl IntoIterator<Item = &'a BTreeMap<String, Value>>,
+) -> anyhow::Result<usize> {
+    let inserted = REVOCATION_TREE.lock().unwrap().revoke(records)?;
+    publish_revocation_root(networks).await?;
+    Ok(inserted)
+}
+
+// 将被新证书取代的旧证书: 原批次中的撤销索引与其各条记录
+struct SupersededCertificate {
+    index: u64,
+    records: Vec<BTreeMap<String, Value>>,
+}
+
+// 签发新批次前核对将被取代的旧证书, 避免锚定后才发现无法取代.
+// 旧证书的记录取代后加入撤销累加器, 须先在其原槽位上核对; 原始数据索引优先取请求中给出的
+async fn superseded_certificates<'a>(
+    network: &Network,
+    ipfs_client: &IpfsClient,
+    issuer: Address,
+    batch: &FetchedBatch,
+    batch_record: &BatchRecord,
+    ids: impl IntoIterator<Item = &'a str>,
+    original_data_cids: &BTreeMap<String, String>,
+) -> Result<BTreeMap<String, SupersededCertificate>> {
+    let mut superseded = BTreeMap::new();
+    for id in ids {
+        if superseded.contains_key(id) {
+            continue;
+        }
+        let index = batch_record.revocation_index(id).ok_or_else(|| {
+            error::ErrorNotFound(format!("Certificate {id} not issued in superseded batch"))
+        })?;
+        let revoked = network
+            .ledger
+            .revocation(issuer, batch.batch_id, index)
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        if let Some(reason) = revoked {
+            return Err(error::ErrorConflict(format!(
+                "Certificate {id} already revoked: {reason:?}"
+            )));
+        }
+        let original_data_cid = original_data_cids
+            .get(id)
+            .or_else(|| batch_record.original_data.get(id))
+            .ok_or_else(|| {
+                error::ErrorBadRequest(format!("Original data of certificate {id} unknown"))
+            })?;
+        let records = certificate_records(ipfs_client, original_data_cid).await?;
+        check_certificate_records(batch, batch_record, id, &records)?;
+        superseded.insert(id.to_string(), SupersededCertificate { index, records });
+    }
+    Ok(superseded)
+}
 
+// 以新批次 new_batch_id 中编号相同的证书取代旧证书, 被取代证书的记录加入撤销累加器.
+// 新批次已锚定, 之后的失败只记入结果, 新证书仍须交给学校; 返回各取代结果与累加器的错误
+#[allow(clippy::too_many_arguments)]
+async fn supersede_certificates(
+    networks: &NetworkRegistry,
+    network: &Network,
+    signer: &Signer,
+    supersedes: &str,
+    old_batch_id: H256,
+    new_batch_id: H256,
+    certificates: &mut [Certificate],
+    mut superseded: BTreeMap<String, SupersededCertificate>,
+) -> (Vec<SupersessionResult>, Option<String>) {
+    let mut results = Vec::with_capacity(superseded.len());
+    let mut superseded_records = Vec::new();
+    for certificate in certificates.iter_mut() {
+        let Some(old) = superseded.remove(&certificate.id) else {
+            continue;
+        };
+        match network
+            .ledger
+            .supersede(signer, old_batch_id, old.index, new_batch_id)
+            .await
+        {
+            Ok(tx_hash) => {
+                info!("证书 {} 已被新证书取代, 交易: {tx_hash:?}", certificate.id);
+                certificate.supersedes = Some(supersedes.to_string());
+                superseded_records.extend(old.records);
+                results.push(SupersessionResult {
+                    id: certificate.id.clone(),
+                    supersession_tx_hash: format!("{tx_hash:?}"),
+                    error: None,
+                });
+            }
+            Err(e) => {
+                error!("证书 {} 取代失败: {e}", certificate.id);
+                results.push(SupersessionResult {
+                    id: certificate.id.clone(),
+                    supersession_tx_hash: String::new(),
+                    error: Some(e.to_string()),
+                });
+            }
+        }
+    }
+
+    // 被取代的证书在零知识展示中同样失效
+    let mut accumulator_error = None;
+    if !superseded_records.is_empty() {
+        match revoke_records(networks, &superseded_records).await {
+            Ok(inserted) => info!("被取代证书的 {inserted} 条记录已加入撤销累加器"),
+            Err(e) => {
+                error!("被取代证书的记录加入撤销累加器失败: {e}");
+                accumulator_error = Some(e.to_string());
+            }
+        }
+    }
+    (results, accumulator_error)
+}
+
+/// 处理数据、生成承诺与证明并锚定, 返回每个学生的证书.
+/// 学生数超过 MAX_DEGREE 时分片, 每个分片各自承诺, 锚定的承诺按分片依次排列
+async fn issue_batch(
+    mut records: Vec<RecordData>,
+    signer: &Signer,
+    network: &Network,
+    ipfs_client: &IpfsClient,
+) -> Result<Vec<Certificate>> {
     let nstu = records.len();
-    let mut records = records.clone();
 
-    handle_images(&mut records, &ipfs_client).await?;
+    handle_images(&mut records, ipfs_client).await?;
     info!("处理图片完成, edus:{records:#?}");
 
-    let origin_cids = handle_origin_data(&records, &ipfs_client).await?;
+    let origin_cids = handle_origin_data(&records, ipfs_client).await?;
     info!("处理原始数据完成, origin_cids:{origin_cids:#?}");
 
     let school = School::new();
 
-    let edu_type_compress_evals = school.handle_edu_data(records.clone());
-    info!("压缩数据完成");
+    // 各分片的学历类型数与整个批次一致
+    let edu_types = records.iter().map(|edu| edu.data.len()).max().unwrap_or(0);
+    let shard_evals = records
+        .chunks(MAX_DEGREE)
+        .map(|shard| school.handle_edu_data(shard, edu_types))
+        .collect::<Vec<_>>();
+    let shard_domains = records
+        .chunks(MAX_DEGREE)
+        .map(|shard| domain_size(shard.len()))
+        .collect::<Vec<_>>();
+    info!("压缩数据完成, 分片数: {}", shard_evals.len());
 
-    let commitments = school.commit(&edu_type_compress_evals);
+    let commitments = shard_evals
+        .iter()
+        .zip(&shard_domains)
+        .flat_map(|(evals, domain_size)| school.commit(evals, *domain_size))
+        .collect::<Vec<_>>();
     info!("生成承诺完成");
 
     let mut student_proof = Vec::with_capacity(nstu);
     for idx in 0..nstu {
-        let idx = hash_to_u64(records[idx].id.as_bytes());
-        let proofs = school.open(idx, &edu_type_compress_evals);
-        let proof_bytes = proofs
-            .iter()
-            .map(|proof| {
-                // 96bytes
-                let p_bytes = proof.0 .0.to_raw_bytes();
-                // 32bytes
-                let v_bytes = proof.1.to_raw_bytes();
-                [p_bytes, v_bytes].concat()
-            })
-            .flatten()
-            .collect::<Vec<_>>();
-
-        student_proof.push(proof_bytes);
+        let proofs = school.open(
+            idx % MAX_DEGREE,
+            &shard_evals[idx / MAX_DEGREE],
+            shard_domains[idx / MAX_DEGREE],
+        );
+        student_proof.push(encode_proofs(&proofs));
     }
     info!("生成证明完成");
 
-    let tx_hash = send_transaction(
-        &web3,
-        commitments
-            .iter()
-            .flat_map(|commitment| commitment.0.to_raw_bytes())
-            .collect::<Vec<_>>(),
-    )
-    .await
-    .map_err(error::ErrorInternalServerError)?;
+    let commitment_bytes = commitments
+        .iter()
+        .flat_map(|commitment| commitment.0.to_raw_bytes())
+        .collect::<Vec<_>>();
+    let anchor = network
+        .ledger
+        .anchor_batch(
+            signer,
+            schema_hash(&classify_edu_data(records.clone())),
+            commitment_bytes,
+            nstu as u64,
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?;
 
-    let tx_hash_str = format!("{tx_hash:?}");
+    let tx_hash_str = format!("{:?}", anchor.tx_hash);
     info!("交易哈希: {tx_hash_str}");
 
+    // 登记槽位与求值, 之后可在该批次上追加或更新学生
+    let batch_record = BatchRecord {
+        issuer: format!("{:?}", anchor.issuer),
+        chain_id: network.chain_id,
+        tx_hash: tx_hash_str.clone(),
+        shards: records
+            .chunks(MAX_DEGREE)
+            .zip(&shard_evals)
+            .map(|(shard, evals)| {
+                ShardRecord::new(shard.iter().map(|edu| edu.id.clone()).collect(), evals)
+            })
+            .collect(),
+        images: record_images(&records),
+        original_data: records
+            .iter()
+            .map(|edu| edu.id.clone())
+            .zip(origin_cids.iter().cloned())
+            .collect(),
+    };
+    // 没有槽位登记就无法追加、撤销或删除该批次的证书, 登记失败时请求失败, 由运维按日志补登
+    record_batch(&format!("{:?}", anchor.batch_id), batch_record).map_err(|e| {
+        error!(
+            "批次已锚定但槽位登记失败, 批次: {:?}, 交易: {:?}: {e}",
+            anchor.batch_id, anchor.tx_hash
+        );
+        error::ErrorInternalServerError(format!(
+            "Batch {:?} was anchored in {:?} but its slots could not be recorded",
+            anchor.batch_id, anchor.tx_hash
+        ))
+    })?;
+
     let mut processed_records = Vec::with_capacity(nstu);
     for idx in 0..nstu {
-        processed_records.push((
-            records[idx].id.clone(),
-            origin_cids[idx].clone(),
-            hex::encode(student_proof[idx].clone()),
-            tx_hash_str.clone(),
-        ));
+        processed_records.push(Certificate {
+            id: records[idx].id.clone(),
+            original_data_cid: origin_cids[idx].clone(),
+            proof: hex::encode(student_proof[idx].clone()),
+            tx_hash: tx_hash_str.clone(),
+            issuer: format!("{:?}", anchor.issuer),
+            batch_id: format!("{:?}", anchor.batch_id),
+            chain_id: network.chain_id,
+            log_inclusion: anchor.inclusion.clone(),
+            supersedes: None,
+            shard: (idx / MAX_DEGREE) as u32,
+            shards: shard_evals.len() as u32,
+            index: (idx % MAX_DEGREE) as u32,
+            domain_size: shard_domains[idx / MAX_DEGREE] as u32,
+        });
     }
 
+    Ok(processed_records)
+}
+
+pub async fn upload_and_gen_cert(
+    req: HttpRequest,
+    records: web::Json<Vec<RecordData>>,
+    query: web::Query<UploadQuery>,
+    networks: web::Data<NetworkRegistry>,
+    ipfs_client: web::Data<IpfsClient>,
+) -> Result<web::Json<UploadResponse>> {
+    info!("收到上传请求，开始处理...");
+
+    // 发证学校的签名账户由接口令牌确定, 未通过认证时直接拒绝, 不做后续处理
+    let signer = issuer_signer(&req)?;
+    let network = networks
+        .network(query.chain_id)
+        .map_err(error::ErrorBadRequest)?;
+    check_batch_size(records.len())?;
+
+    let processed_records =
+        issue_batch(records.into_inner(), signer, network, &ipfs_client).await?;
+
     let certificate_filename = generate_certificate(&processed_records)
         .await
         .map_err(error::ErrorInternalServerError)?;
@@ -179,12 +512,346 @@ pub async fn upload_and_gen_cert(
     let response = UploadResponse {
         success: true,
         certificate_file: certificate_filename,
+        superseded: Vec::new(),
+        accumulator_error: None,
     };
     info!("上传成功返回结果: {response:?}");
 
     Ok(web::Json(response))
 }
 
+/// 在已签发的批次上追加或更新学生, 如补发的毕业生. 承诺按槽位增量更新后锚定为新批次,
+/// 只为本次涉及的学生重新生成证明. 被更新学生在原批次中的证书由新证书取代,
+/// 其余学生的证书仍按原批次验证
+pub async fn append_records(
+    req: HttpRequest,
+    tx_hash: web::Path<String>,
+    records: web::Json<Vec<RecordData>>,
+    query: web::Query<UploadQuery>,
+    networks: web::Data<NetworkRegistry>,
+    ipfs_client: web::Data<IpfsClient>,
+) -> Result<web::Json<UploadResponse>> {
+    info!("收到批次追加请求, 批次: {tx_hash}");
+
+    let signer = issuer_signer(&req)?;
+    let network = networks
+        .network(query.chain_id)
+        .map_err(error::ErrorBadRequest)?;
+    let mut records = records.into_inner();
+    if records.is_empty() {
+        return Err(error::ErrorBadRequest("No records to append"));
+    }
+
+    let batch = network
+        .ledger
+        .fetch_batch(&tx_hash)
+        .await
+        .map_err(error::ErrorNotFound)?;
+    let info = network
+        .ledger
+        .anchor_info(&tx_hash)
+        .await
+        .map_err(error::ErrorNotFound)?;
+    if info.sender != signer.address() {
+        return Err(error::ErrorForbidden(
+            "Batch was anchored by another issuer",
+        ));
+    }
+    let parent_batch_id = batch.batch_id;
+    let mut batch_record = get_batch(&format!("{parent_batch_id:?}"))
+        .ok_or_else(|| error::ErrorNotFound("Batch slots not recorded"))?;
+    let appended = records
+        .iter()
+        .filter(|record| batch_record.position(&record.id).is_none())
+        .count();
+    check_batch_size(
+        batch_record
+            .shards
+            .iter()
+            .map(|shard| shard.students.len())
+            .sum::<usize>()
+            + appended,
+    )?;
+    // 已在批次中的学生被更新, 须在锚定前核对其原证书, 锚定后以新证书取代
+    let updated = superseded_certificates(
+        network,
+        &ipfs_client,
+        info.sender,
+        &batch,
+        &batch_record,
+        records
+            .iter()
+            .map(|record| record.id.as_str())
+            .filter(|id| batch_record.position(id).is_some()),
+        &BTreeMap::new(),
+    )
+    .await?;
+
+    // 96bytes, 按分片依次排列
+    let edu_types = batch_record.edu_types();
+    let shards = batch_record.shards.len();
+    let commitments = batch
+        .commitments
+        .chunks(96)
+        .map(|bytes| G1::from_raw_bytes(bytes).map(Commit))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| error::ErrorInternalServerError("Invalid anchored commitment"))?;
+    if commitments.len() != shards * edu_types {
+        return Err(error::ErrorConflict(
+            "Batch slots do not match anchored commitments",
+        ));
+    }
+    let mut commitments = commitments.into_iter();
+    let mut commitments_by_shard = (0..shards)
+        .map(|_| commitments.by_ref().take(edu_types).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    handle_images(&mut records, &ipfs_client).await?;
+    let origin_cids = handle_origin_data(&records, &ipfs_client).await?;
+
+    let school = School::new();
+    let domains = batch_record
+        .shards
+        .iter()
+        .map(ShardRecord::domain_size)
+        .collect::<Vec<_>>();
+    let mut updates = Vec::new();
+    for record in &records {
+        let values = compress_edu_data(&record.data, school.random);
+        updates.extend(
+            batch_record
+                .upsert(&record.id, &values)
+                .map_err(error::ErrorBadRequest)?,
+        );
+    }
+    if updates.is_empty() {
+        return Err(error::ErrorConflict("Records already issued in this batch"));
+    }
+
+    // 本次涉及的学生所在分片的求值
+    let positions = records
+        .iter()
+        .map(|record| batch_record.position(&record.id))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| error::ErrorInternalServerError("Record missing from batch"))?;
+    let shard_evals = positions
+        .iter()
+        .map(|(shard, _)| *shard)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|shard| {
+            batch_record.shards[shard]
+                .evals()
+                .map(|evals| (shard, evals))
+        })
+        .collect::<anyhow::Result<BTreeMap<_, _>>>()
+        .map_err(error::ErrorInternalServerError)?;
+
+    // 新增的分片与学历类型从空承诺开始
+    commitments_by_shard.resize_with(batch_record.shards.len(), Vec::new);
+    for commitments in commitments_by_shard.iter_mut() {
+        commitments.resize_with(batch_record.edu_types(), || Commit(G1::default()));
+    }
+    let mut commitment_bytes = Vec::new();
+    let mut delta_bytes = Vec::new();
+    for (shard, commitments) in commitments_by_shard.iter().enumerate() {
+        let size = batch_record.shards[shard].domain_size();
+        for (edu_type, commitment) in commitments.iter().enumerate() {
+            // 学生数超出原定义域时分片换用更大的定义域, 该分片整体重新承诺.
+            // 此时 delta 只是新旧承诺之差, 仍满足 commitments = parent + delta,
+            // 但两者位于不同定义域, 不能再拆分为各槽位的增量 sum(v_i * L_i(tau))
+            let updated = if domains.get(shard) == Some(&size) {
+                let delta = shplonk_lagrange_delta(
+                    &updates
+                        .iter()
+                        .filter(|update| update.shard == shard && update.edu_type == edu_type)
+                        .map(|update| (update.slot, update.delta))
+                        .collect::<Vec<_>>(),
+                    size,
+                );
+                shplonk_lagrange_update(commitment, &delta)
+            } else {
+                shplonk_lagrange_commit(&shard_evals[&shard][edu_type], size)
+            };
+            commitment_bytes.extend(updated.0.to_raw_bytes());
+            delta_bytes.extend((updated.0 - commitment.0).to_raw_bytes());
+        }
+    }
+    // 同一学生在请求中多次出现时增量可能相互抵消, 承诺不变时不锚定新批次
+    if commitment_bytes == batch.commitments {
+        return Err(error::ErrorConflict("Records do not change the batch"));
+    }
+    info!("更新承诺完成, 槽位增量: {}", updates.len());
+
+    let anchor = network
+        .ledger
+        .update_batch(
+            signer,
+            parent_batch_id,
+            schema_hash(&classify_edu_data(records.clone())),
+            commitment_bytes,
+            delta_bytes,
+            batch_record
+                .shards
+                .iter()
+                .map(|shard| shard.students.len() as u64)
+                .sum(),
+        )
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let tx_hash_str = format!("{:?}", anchor.tx_hash);
+    info!("批次更新交易哈希: {tx_hash_str}");
+
+    // 多项式已改变, 只为本次涉及的学生在其分片上打开新承诺
+    let mut processed_records = Vec::with_capacity(records.len());
+    for ((record, origin_cid), (shard, index)) in records.iter().zip(origin_cids).zip(positions) {
+        let domain_size = batch_record.shards[shard].domain_size();
+        let proofs = school.open(index, &shard_evals[&shard], domain_size);
+        processed_records.push(Certificate {
+            id: record.id.clone(),
+            original_data_cid: origin_cid,
+            proof: hex::encode(encode_proofs(&proofs)),
+            tx_hash: tx_hash_str.clone(),
+            issuer: format!("{:?}", anchor.issuer),
+            batch_id: format!("{:?}", anchor.batch_id),
+            chain_id: network.chain_id,
+            log_inclusion: anchor.inclusion.clone(),
+            supersedes: None,
+            shard: shard as u32,
+            shards: batch_record.shards.len() as u32,
+            index: index as u32,
+            domain_size: domain_size as u32,
+        });
+    }
+
+    batch_record.tx_hash = tx_hash_str;
+    batch_record.images.extend(record_images(&records));
+    batch_record
+        .original_data
+        .extend(processed_records.iter().map(|certificate| {
+            (
+                certificate.id.clone(),
+                certificate.original_data_cid.clone(),
+            )
+        }));
+    // 没有槽位登记就无法追加、撤销或删除该批次的证书, 登记失败时请求失败, 由运维按日志补登
+    record_batch(&format!("{:?}", anchor.batch_id), batch_record).map_err(|e| {
+        error!(
+            "批次已锚定但槽位登记失败, 批次: {:?}, 交易: {:?}: {e}",
+            anchor.batch_id, anchor.tx_hash
+        );
+        error::ErrorInternalServerError(format!(
+            "Batch {:?} was anchored in {:?} but its slots could not be recorded",
+            anchor.batch_id, anchor.tx_hash
+        ))
+    })?;
+
+    let (superseded, accumulator_error) = supersede_certificates(
+        &networks,
+        network,
+        signer,
+        &tx_hash,
+        parent_batch_id,
+        anchor.batch_id,
+        &mut processed_records,
+        updated,
+    )
+    .await;
+
+    let certificate_filename = generate_certificate(&processed_records)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    info!("追加证书文件名: {certificate_filename:?}");
+
+    Ok(web::Json(UploadResponse {
+        success: accumulator_error.is_none() && superseded.iter().all(|r| r.error.is_none()),
+        certificate_file: certificate_filename,
+        superseded,
+        accumulator_error,
+    }))
+}
+
+pub async fn upload_images(
+    mut payload: Multipart,
+    ipfs_client: web::Data<IpfsClient>,
+) -> Result<web::Json<Vec<ImageUploadResult>>> {
+    info!("收到图片上传请求，开始处理...");
+
+    let policy = *IMAGE_POLICY;
+    let mut results = Vec::new();
+
+    while let Some(mut field) = payload.try_next().await? {
+        if results.len() >= policy.max_files {
+            return Err(error::ErrorPayloadTooLarge(format!(
+                "at most {} images per request",
+                policy.max_files
+            )));
+        }
+        let filename = field
+            .content_disposition()
+            .get_filename()
+            .unwrap_or_default()
+            .to_string();
+
+        // 超过大小上限后不再缓存数据, 但仍需读完该字段
+        let mut data = Vec::new();
+        let mut too_large = false;
+        while let Some(chunk) = field.try_next().await? {
+            if data.len() + chunk.len() > policy.max_bytes {
+                too_large = true;
+                data.clear();
+            }
+            if !too_large {
+                data.extend_from_slice(&chunk);
+            }
+        }
+
+        let normalized = if too_large {
+            Err(anyhow::anyhow!(
+                "image too large, limit {} bytes",
+                policy.max_bytes
+            ))
+        } else {
+            normalize_image(&data, &policy)
+        };
+
+        let result = match normalized {
+            Ok(image) => match upload_to_ipfs(&ipfs_client, image.data).await {
+                Ok(cid) => ImageUploadResult {
+                    filename,
+                    success: true,
+                    ipfs_cid: Some(cid),
+                    mime: Some(image.mime.to_string()),
+                    width: Some(image.width),
+                    height: Some(image.height),
+                    size: Some(image.size),
+                    error: None,
+                },
+                Err(e) => {
+                    error!("图片上传IPFS失败 {filename}: {e}");
+                    ImageUploadResult {
+                        filename,
+                        error: Some(e.to_string()),
+                        ..Default::default()
+                    }
+                }
+            },
+            Err(e) => {
+                info!("图片校验失败 {filename}: {e:#}");
+                ImageUploadResult {
+                    filename,
+                    error: Some(format!("{e:#}")),
+                    ..Default::default()
+                }
+            }
+        };
+        results.push(result);
+    }
+
+    info!("图片上传处理完成, 共 {} 张", results.len());
+    Ok(web::Json(results))
+}
+
 pub async fn download_certificate(filename: web::Path<String>) -> Result<HttpResponse> {
     let file_path = format!("./certificates/{filename}");
 
@@ -200,20 +867,304 @@ pub async fn download_certificate(filename: web::Path<String>) -> Result<HttpRes
     }
 }
 
+/// 学校更正已签发的证书: 为部分学生签发新批次, 并在账本上以新证书取代旧证书
+pub async fn correct_certificates(
+    req: HttpRequest,
+    request: web::Json<CorrectionRequest>,
+    query: web::Query<UploadQuery>,
+    networks: web::Data<NetworkRegistry>,
+    ipfs_client: web::Data<IpfsClient>,
+) -> Result<web::Json<CorrectionResponse>> {
+    info!("收到更正请求, 取代批次: {}", request.supersedes);
+
+    let signer = issuer_signer(&req)?;
+    let network = networks
+        .network(query.chain_id)
+        .map_err(error::ErrorBadRequest)?;
+    if request.records.is_empty() {
+        return Err(error::ErrorBadRequest("No records to correct"));
+    }
+    check_batch_size(request.records.len())?;
+
+    // 签发新批次前检查旧证书, 避免锚定后才发现无法取代
+    let batch = network
+        .ledger
+        .fetch_batch(&request.supersedes)
+        .await
+        .map_err(error::ErrorNotFound)?;
+    let info = network
+        .ledger
+        .anchor_info(&request.supersedes)
+        .await
+        .map_err(error::ErrorNotFound)?;
+    if info.sender != signer.address() {
+        return Err(error::ErrorForbidden(
+            "Superseded batch was anchored by another issuer",
+        ));
+    }
+    let old_batch_id = batch.batch_id;
+    let old_record = get_batch(&format!("{old_batch_id:?}"))
+        .ok_or_else(|| error::ErrorNotFound("Batch slots not recorded"))?;
+    let old_certificates = superseded_certificates(
+        network,
+        &ipfs_client,
+        info.sender,
+        &batch,
+        &old_record,
+        request.records.iter().map(|record| record.id.as_str()),
+        &request.original_data_cids,
+    )
+    .await?;
+
+    let mut processed_records =
+        issue_batch(request.records.clone(), signer, network, &ipfs_client).await?;
+    let new_batch_id = processed_records[0]
+        .batch_id
+        .parse()
+        .map_err(error::ErrorInternalServerError)?;
+
+    // 旧证书按其在原批次中的槽位取代, 更正证书按编号在新批次的登记中查找
+    let (superseded, accumulator_error) = supersede_certificates(
+        &networks,
+        network,
+        signer,
+        &request.supersedes,
+        old_batch_id,
+        new_batch_id,
+        &mut processed_records,
+        old_certificates,
+    )
+    .await;
+
+    let certificate_filename = generate_certificate(&processed_records)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    info!("更正证书文件名: {certificate_filename:?}");
+
+    Ok(web::Json(CorrectionResponse {
+        success: accumulator_error.is_none() && superseded.iter().all(|r| r.error.is_none()),
+        certificate_file: certificate_filename,
+        superseded,
+        accumulator_error,
+    }))
+}
+
+/// 学校撤销已签发的证书, 如学位被撤销或信息录入错误
+pub async fn revoke_certificate(
+    req: HttpRequest,
+    request: web::Json<RevokeRequest>,
+    query: web::Query<UploadQuery>,
+    networks: web::Data<NetworkRegistry>,
+    ipfs_client: web::Data<IpfsClient>,
+) -> Result<web::Json<RevokeResponse>> {
+    info!("收到撤销请求: {request:?}");
+
+    let signer = issuer_signer(&req)?;
+    let network = networks
+        .network(query.chain_id)
+        .map_err(error::ErrorBadRequest)?;
+
+    // 批次ID取自锚定事件, 账本只允许锚定该批次的学校撤销.
+    // 槽位取自该批次的登记, 不在批次中的证书不能撤销
+    let batch = network
+        .ledger
+        .fetch_batch(&request.tx_hash)
+        .await
+        .map_err(error::ErrorNotFound)?;
+    let batch_record = get_batch(&format!("{:?}", batch.batch_id))
+        .ok_or_else(|| error::ErrorNotFound("Batch slots not recorded"))?;
+    let index = batch_record
+        .revocation_index(&request.id)
+        .ok_or_else(|| error::ErrorNotFound("Certificate not issued in this batch"))?;
+
+    // 零知识展示不暴露槽位, 需将各条记录加入撤销累加器. 累加器由各学校共用,
+    // 记录须先在该证书的槽位上打开锚定的承诺, 学校不能撤销其他证书的记录
+    let records = match &request.original_data_cid {
+        Some(original_data_cid) => {
+            let records = certificate_records(&ipfs_client, original_data_cid).await?;
+            check_certificate_records(&batch, &batch_record, &request.id, &records)?;
+            Some(records)
+        }
+        None => None,
+    };
+
+    let revocation_tx_hash = network
+        .ledger
+        .revoke(signer, batch.batch_id, index, request.reason)
+        .await
+        .map_err(error::ErrorBadRequest)?;
+    info!("证书 {} 已撤销, 交易: {revocation_tx_hash:?}", request.id);
+
+    if let Some(records) = records {
+        let inserted = revoke_records(&networks, &records)
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        info!("证书 {} 的 {inserted} 条记录已加入撤销累加器", request.id);
+    }
+
+    Ok(web::Json(RevokeResponse {
+        success: true,
+        id: request.id.clone(),
+        revocation_tx_hash: format!("{revocation_tx_hash:?}"),
+        reason: request.reason,
+    }))
+}
+
 #[cfg(test)]
 mod tests {
     use ipfs_api_backend_hyper::TryFromUri;
 
     use super::*;
+    use crate::handler::{certificate_opening, disclosed_values, verify_opening, OPENING_SIZE};
+    use crate::models::BatchShard;
     use crate::services::{
+        bindings::VerifyDataCall,
         commit::verify,
         ethereum::{create_web3_connection, get_transaction_data},
+        evm::LocalVerifier,
+        inclusion::AnchorStatus,
     };
 
+    #[test]
+    fn test_record_images() {
+        let records = vec![RecordData {
+            id: "D202501".to_string(),
+            data: vec![
+                BTreeMap::from([("images".to_string(), json!(["QmA", "QmB"]))]),
+                BTreeMap::from([("姓名".to_string(), json!("张三"))]),
+                BTreeMap::from([("images".to_string(), json!(["QmA"]))]),
+            ],
+        }];
+        assert_eq!(
+            record_images(&records),
+            BTreeSet::from(["QmA".to_string(), "QmB".to_string()])
+        );
+    }
+
+    // 按签发流程承诺与打开一个批次, 按验证方的方式构造调用, 由编译的合约在内存 EVM 中验证
+    #[test]
+    fn test_issue_and_verify_on_evm() {
+        // 第二个学生只有一类学历
+        let records = (0..3)
+            .map(|i| RecordData {
+                id: format!("D20250{i}"),
+                data: (0..2 - i % 2)
+                    .map(|edu_type| {
+                        BTreeMap::from([
+                            ("姓名".to_string(), json!(format!("学生{i}"))),
+                            ("学历".to_string(), json!(format!("类型{edu_type}"))),
+                        ])
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+
+        let school = School::new();
+        let evals = school.handle_edu_data(&records, 2);
+        let size = domain_size(records.len());
+        let commitments = school
+            .commit(&evals, size)
+            .iter()
+            .flat_map(|commitment| commitment.0.to_raw_bytes())
+            .collect::<Vec<_>>();
+
+        let openings = records
+            .iter()
+            .enumerate()
+            .map(|(index, record)| {
+                let proof = encode_proofs(&school.open(index, &evals, size));
+                let edu_types = (0..record.data.len() as u32).collect::<Vec<_>>();
+                let slot = BatchShard {
+                    shard: 0,
+                    shards: 1,
+                    index: index as u32,
+                    domain_size: size as u32,
+                };
+                let mut opening = certificate_opening(
+                    &commitments,
+                    &proof[..edu_types.len() * OPENING_SIZE],
+                    &edu_types,
+                    &slot,
+                )
+                .unwrap();
+                opening.values = disclosed_values(&record.data, &edu_types).unwrap();
+                opening
+            })
+            .collect::<Vec<_>>();
+
+        // 服务端的检查 (删除请求的持有证明) 与合约一致
+        assert!(openings.iter().all(verify_opening));
+
+        let mut verifier = LocalVerifier::deploy().unwrap();
+        let verify = |verifier: &mut LocalVerifier, openings: &[_]| {
+            verifier
+                .verify_data(VerifyDataCall::from_openings(openings, Vec::new()))
+                .unwrap()
+        };
+        assert!(verify(&mut verifier, &openings));
+        assert!(verify(&mut verifier, &openings[1..2]));
+
+        // 篡改的数据
+        let mut tampered = openings.clone();
+        tampered[2].values[1] += Fr::from(1);
+        assert!(!verify_opening(&tampered[2]));
+        assert!(!verify(&mut verifier, &tampered));
+
+        // 在其他学生的槽位打开
+        let mut tampered = openings.clone();
+        tampered[0].point = openings[2].point;
+        assert!(!verify(&mut verifier, &tampered));
+    }
+
+    // 撤销时加入累加器的记录须是该证书槽位上承诺的数据
+    #[test]
+    fn test_check_certificate_records() {
+        let records = (0..2)
+            .map(|i| RecordData {
+                id: format!("D20250{i}"),
+                data: vec![BTreeMap::from([(
+                    "姓名".to_string(),
+                    json!(format!("学生{i}")),
+                )])],
+            })
+            .collect::<Vec<_>>();
+
+        let school = School::new();
+        let evals = school.handle_edu_data(&records, 1);
+        let size = domain_size(records.len());
+        let batch = FetchedBatch {
+            issuer: Default::default(),
+            batch_id: Default::default(),
+            commitments: school
+                .commit(&evals, size)
+                .iter()
+                .flat_map(|commitment| commitment.0.to_raw_bytes())
+                .collect(),
+            anchor: AnchorStatus::Asserted,
+        };
+        let batch_record = BatchRecord {
+            shards: vec![ShardRecord::new(
+                records.iter().map(|record| record.id.clone()).collect(),
+                &evals,
+            )],
+            ..Default::default()
+        };
+
+        assert!(
+            check_certificate_records(&batch, &batch_record, "D202500", &records[0].data).is_ok()
+        );
+        // 其他证书的记录
+        assert!(
+            check_certificate_records(&batch, &batch_record, "D202500", &records[1].data).is_err()
+        );
+        assert!(
+            check_certificate_records(&batch, &batch_record, "D202502", &records[0].data).is_err()
+        );
+    }
+
     #[tokio::test]
     async fn test_proof() {
         use crate::models::Certificate;
-        use crate::services::ipfs::get_json_from_ipfs;
 
         let ipfs_url = "http://localhost:5001";
         let ipfs_client = IpfsClient::from_str(ipfs_url).unwrap();
@@ -225,6 +1176,15 @@ mod tests {
             proof: "1ba2cb311e95d71f735040527e83043cdf6d5611fa49b6f638806fcee6809508".to_string(),
             tx_hash: "0xc6b00aa31d19f36b0824a86b4fa34a3fe8db941997f10a75f69a8a578b9047ee"
                 .to_string(),
+            issuer: String::new(),
+            batch_id: String::new(),
+            chain_id: 0,
+            log_inclusion: None,
+            supersedes: None,
+            shard: 0,
+            shards: 0,
+            index: 0,
+            domain_size: 0,
         };
         println!("证书信息: {certificate:#?}");
 
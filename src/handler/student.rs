@@ -2,22 +2,32 @@ use std::collections::BTreeMap;
 
 use actix_web::{error, web, Result};
 use ipfs_api_backend_hyper::IpfsClient;
-use log::{error, info};
+use log::{error, info, warn};
 use serde_json::Value;
-use snark_verifier_sdk::snark_verifier::halo2_base::halo2_proofs::halo2curves::bn256::Fr;
-use snark_verifier_sdk::snark_verifier::halo2_base::halo2_proofs::halo2curves::serde::SerdeObject;
 use snark_verifier_sdk::snark_verifier::halo2_base::utils::fs::gen_srs;
 
-use crate::handler::{decompse_edu_data, RANDOM};
+use crate::handler::content::content_url;
+use crate::handler::{
+    certificate_opening, certificate_slot, check_erasure, decompse_edu_data, disclosed_values,
+    verify_opening, OPENING_SIZE, RANDOM,
+};
 use crate::models::{
-    AuthenticationData, Certificate, GenerateAuthenticationData, VerifiedData, VerifiedImage,
+    AuthenticationData, BatchShard, Certificate, ErasureRequest, ErasureResponse,
+    GenerateAuthenticationData, VerifiedData, VerifiedImage,
 };
+use crate::services::batches::{revocation_index, shard_commitments};
 use crate::services::circuit::CircuitParams;
 use crate::services::circuit::CircuitProver;
-use crate::services::ipfs::get_image_from_ipfs;
-use crate::services::ipfs::{get_json_from_ipfs, upload_to_ipfs};
+use crate::services::compress_fr;
+use crate::services::erasure::{is_erased_content, record_erasure, ErasureRecord};
+use crate::services::ipfs::{
+    get_json_from_ipfs, try_join_ordered, unpin_from_ipfs, upload_record_copy_to_ipfs,
+    upload_to_ipfs,
+};
+use crate::services::network::NetworkRegistry;
 use crate::services::poseidon::poseidon;
-use crate::services::{compress_fr, compress_g1};
+use crate::services::revocation::REVOCATION_TREE;
+use crate::services::shredding::destroy_record_keys;
 
 lazy_static::lazy_static! {
     static ref PROVER: CircuitProver = CircuitProver::new(CircuitParams {
@@ -29,81 +39,205 @@ lazy_static::lazy_static! {
     }, gen_srs(16));
 }
 
-pub async fn get_images(
-    ipfs_client: &IpfsClient,
-    data: &BTreeMap<String, Value>,
-) -> Result<Vec<VerifiedImage>> {
-    let mut verified_images = Vec::new();
-
-    if let Some(images) = data.get("images") {
-        if let Some(images_array) = images.as_array() {
-            for image in images_array {
-                let cid = image.as_str().unwrap();
-                match get_image_from_ipfs(ipfs_client, cid).await {
-                    Ok(image_data) => {
-                        let base64_data = base64::encode(&image_data);
-                        verified_images.push(VerifiedImage {
-                            ipfs_cid: cid.to_string(),
-                            data: base64_data,
-                        });
-                    }
-                    Err(e) => {
-                        error!("获取图片失败 {cid}: {e}");
-                    }
-                }
-            }
-        }
-    }
-
-    Ok(verified_images)
+pub fn get_images(data: &BTreeMap<String, Value>) -> Vec<VerifiedImage> {
+    data.get("images")
+        .and_then(Value::as_array)
+        .map(|images| {
+            images
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|cid| VerifiedImage {
+                    ipfs_cid: cid.to_string(),
+                    url: content_url(cid),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
 }
 
 pub async fn upload(
     certs: web::Json<Vec<Certificate>>,
     ipfs_client: web::Data<IpfsClient>,
 ) -> Result<web::Json<Vec<VerifiedData>>> {
-    let mut verified_data_vec = Vec::new();
+    if let Some(cert) = certs
+        .iter()
+        .find(|cert| is_erased_content(&cert.original_data_cid))
+    {
+        info!("证书已按数据主体请求删除: {}", cert.id);
+        return Err(error::ErrorGone(format!(
+            "Certificate {} erased by subject request",
+            cert.id
+        )));
+    }
 
-    for cert in certs.iter() {
-        let edu_type_cids = get_json_from_ipfs(&ipfs_client, &cert.original_data_cid)
+    let ipfs_client: &IpfsClient = &ipfs_client;
+    let verified_data_vec_vec = try_join_ordered(certs.iter(), |cert| async move {
+        let edu_type_cids = get_json_from_ipfs(ipfs_client, &cert.original_data_cid)
             .await
             .map_err(error::ErrorInternalServerError)?;
 
         let str_cids = serde_json::from_str::<Vec<String>>(&edu_type_cids).unwrap();
 
         let proof = hex::decode(cert.proof.clone()).unwrap();
-        let proof_chunk = proof.chunks(128).collect::<Vec<_>>();
+        let proof_chunk = &proof.chunks(128).collect::<Vec<_>>();
 
-        for (i, cid) in str_cids.iter().enumerate() {
-            let original_data = get_json_from_ipfs(&ipfs_client, cid)
+        try_join_ordered(str_cids.iter().enumerate(), |(i, cid)| async move {
+            let original_data = get_json_from_ipfs(ipfs_client, cid)
                 .await
                 .map_err(error::ErrorInternalServerError)?;
 
             let original_data_obj = serde_json::from_str::<BTreeMap<String, Value>>(&original_data)
                 .map_err(error::ErrorInternalServerError)?;
 
-            let images = get_images(&ipfs_client, &original_data_obj).await?;
+            let images = get_images(&original_data_obj);
 
-            let verified_data = VerifiedData {
+            Ok::<_, error::Error>(VerifiedData {
                 original_data: original_data_obj,
                 images,
                 tx_hash: cert.tx_hash.clone(),
                 cid: cid.to_string(),
                 proof: hex::encode(proof_chunk[i]),
-            };
+                shard: cert.shard,
+                shards: cert.shards,
+                chain_id: cert.chain_id,
+            })
+        })
+        .await
+    })
+    .await?;
 
-            verified_data_vec.push(verified_data);
+    info!("数据收集成功");
+    Ok(web::Json(
+        verified_data_vec_vec.into_iter().flatten().collect(),
+    ))
+}
+
+/// 数据主体删除: 销毁证书各条记录的密钥, 取消固定其在IPFS上的全部内容并登记,
+/// 之后不再展示或验证该证书. 请求须附上完整的证书, 其原始数据在锚定的承诺上打开通过才执行,
+/// 以此确认请求方持有该证书; 删除按锚定批次的发证方、批次ID及槽位登记.
+/// 记录以密文存储, 销毁密钥后其他节点或缓存中的副本也无法解密 (crypto-shredding);
+/// 图片与早期以明文上传的记录没有密钥, 只能取消固定
+pub async fn erase(
+    req: web::Json<ErasureRequest>,
+    ipfs_client: web::Data<IpfsClient>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<ErasureResponse>> {
+    let cert = &req.certificate;
+    info!("收到删除请求, 证书编号: {}", cert.id);
+
+    // 发证方与批次ID取自锚定事件, 槽位取自批次登记
+    let ledger = &networks
+        .network(Some(cert.chain_id))
+        .map_err(error::ErrorBadRequest)?
+        .ledger;
+    let batch = ledger
+        .fetch_batch(&cert.tx_hash)
+        .await
+        .map_err(error::ErrorBadRequest)?;
+    let slot = certificate_slot(
+        &batch,
+        &cert.id,
+        BatchShard {
+            shard: cert.shard,
+            shards: cert.shards,
+            index: cert.index,
+            domain_size: cert.domain_size,
+        },
+    )?;
+    if let Some(erasure) = check_erasure(&batch, &slot) {
+        return Err(error::ErrorGone(format!(
+            "Certificate {} already erased by subject request at {}",
+            cert.id, erasure.erased_at
+        )));
+    }
+
+    // 收集该证书在IPFS上的全部内容: 索引、各类学历数据及图片
+    let edu_type_cids = get_json_from_ipfs(&ipfs_client, &cert.original_data_cid)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    let str_cids =
+        serde_json::from_str::<Vec<String>>(&edu_type_cids).map_err(error::ErrorBadRequest)?;
+
+    let mut cids = vec![cert.original_data_cid.clone()];
+    let mut records = Vec::with_capacity(str_cids.len());
+    for cid in str_cids.iter().cloned() {
+        let original_data = get_json_from_ipfs(&ipfs_client, &cid)
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+        let original_data_obj = serde_json::from_str::<BTreeMap<String, Value>>(&original_data)
+            .map_err(error::ErrorInternalServerError)?;
+
+        if let Some(images) = original_data_obj.get("images").and_then(Value::as_array) {
+            cids.extend(images.iter().filter_map(Value::as_str).map(str::to_string));
         }
+        cids.push(cid);
+        records.push(original_data_obj);
     }
 
-    info!("数据收集成功");
-    Ok(web::Json(verified_data_vec))
+    // 持有证明: 证书的原始数据须在其槽位上打开为锚定的承诺
+    let edu_types = (0..records.len() as u32).collect::<Vec<_>>();
+    let proof = hex::decode(&cert.proof).map_err(error::ErrorBadRequest)?;
+    let proof = proof
+        .get(..edu_types.len() * OPENING_SIZE)
+        .ok_or_else(|| error::ErrorBadRequest("Invalid certificate proof"))?;
+    let commitment = shard_commitments(&batch.commitments, slot.shard, slot.shards)
+        .map_err(error::ErrorBadRequest)?;
+    let mut opening = certificate_opening(commitment, proof, &edu_types, &slot)?;
+    opening.values = disclosed_values(&records, &edu_types)?;
+    if !verify_opening(&opening) {
+        warn!("删除请求的证书与锚定批次不符: {}", cert.id);
+        return Err(error::ErrorForbidden(
+            "Certificate does not match its anchored batch",
+        ));
+    }
+
+    let shredded_cids = destroy_record_keys(&str_cids).map_err(error::ErrorInternalServerError)?;
+
+    let mut unpinned_cids = Vec::with_capacity(cids.len());
+    for cid in cids {
+        match unpin_from_ipfs(&ipfs_client, &cid).await {
+            Ok(_) => unpinned_cids.push(cid),
+            Err(e) => error!("取消固定失败 {cid}: {e}"),
+        }
+    }
+
+    let record = record_erasure(ErasureRecord {
+        id: cert.id.clone(),
+        issuer: format!("{:?}", batch.issuer),
+        batch_id: format!("{:?}", batch.batch_id),
+        index: revocation_index(slot.shard as usize, slot.index as usize),
+        original_data_cid: cert.original_data_cid.clone(),
+        unpinned_cids,
+        shredded_cids,
+        reason: req.reason.clone(),
+        ..Default::default()
+    })
+    .map_err(error::ErrorInternalServerError)?;
+
+    info!("删除完成, 证书编号: {}", record.id);
+    Ok(web::Json(ErasureResponse {
+        success: true,
+        id: record.id,
+        unpinned_cids: record.unpinned_cids,
+        shredded_cids: record.shredded_cids,
+        erased_at: record.erased_at,
+    }))
 }
 
 pub async fn generate_authentication(
     auths: web::Json<Vec<GenerateAuthenticationData>>,
     ipfs_client: web::Data<IpfsClient>,
 ) -> Result<web::Json<AuthenticationData>> {
+    if let Some(auth) = auths
+        .iter()
+        .find(|auth| auth.cid.iter().any(|cid| is_erased_content(cid)))
+    {
+        return Err(error::ErrorGone(format!(
+            "Certificate {} erased by subject request",
+            auth.id
+        )));
+    }
+
     let mut origin_data_vec_vec = Vec::new();
     let mut selected_fields_vec_vec = Vec::new();
     for auth in auths.iter() {
@@ -143,61 +277,58 @@ pub async fn generate_authentication(
             .collect::<Vec<_>>(),
     );
 
-    let mut final_compress_data_vec = Vec::new();
-    let mut compress_proof_vec = Vec::new();
-    let mut compress_value_vec = Vec::new();
-    for (compress_data_vec, auth) in compress_data_vec_vec.iter().zip(auths.iter()) {
-        let compress_data = compress_fr(compress_data_vec, xi);
-
-        let (proofs, values) = auth
-            .proof
-            .iter()
-            .map(|proof| {
-                let proof_bytes = hex::decode(proof).unwrap();
-                let proof = proof_bytes[..96].to_vec();
-                let value = Fr::from_raw_bytes(&proof_bytes[96..128]).expect("value 格式错误");
-                (proof, value)
-            })
-            .collect::<Vec<_>>()
-            .into_iter()
-            .unzip::<Vec<u8>, Fr, Vec<_>, Vec<_>>();
-
-        let compress_proof = compress_g1(&proofs, xi);
-        let compress_value = compress_fr(&values, xi);
+    // 每张证书展示的学历类型与依次的 证明 || 值 块, 由验证方按类型取承诺逐一打开
+    let mut proof = Vec::with_capacity(auths.len());
+    let mut edu_types = Vec::with_capacity(auths.len());
+    for auth in auths.iter() {
+        let types = match auth.edu_types.is_empty() {
+            true => (0..auth.cid.len() as u32).collect(),
+            false => auth.edu_types.clone(),
+        };
+        if types.len() != auth.cid.len() || auth.proof.len() != auth.cid.len() {
+            return Err(error::ErrorBadRequest(format!(
+                "Certificate {}: cid, proof and edu_types must match",
+                auth.id
+            )));
+        }
 
-        compress_proof_vec.push(compress_proof);
-        compress_value_vec.push(compress_value);
-        final_compress_data_vec.push(compress_data);
+        let mut chunks = Vec::with_capacity(auth.proof.len() * OPENING_SIZE);
+        for chunk in auth.proof.iter() {
+            let chunk = hex::decode(chunk).map_err(error::ErrorBadRequest)?;
+            if chunk.len() != OPENING_SIZE {
+                return Err(error::ErrorBadRequest(format!(
+                    "Certificate {}: invalid proof",
+                    auth.id
+                )));
+            }
+            chunks.extend(chunk);
+        }
+        proof.push(hex::encode(chunks));
+        edu_types.push(types);
     }
 
-    let delta = poseidon(&final_compress_data_vec);
-    let final_compress_proof = compress_g1(&compress_proof_vec, delta);
-    let final_compress_value = compress_fr(&compress_value_vec, delta);
-
-    let proof = hex::encode(
-        [
-            final_compress_proof.clone(),
-            final_compress_value.to_raw_bytes(),
-        ]
-        .concat(),
-    );
-
     // if have private data, then we need gen zk proof
     let is_zk = auths.iter().any(|auth| auth.is_zk);
     let zk_proof = if is_zk {
-        let zk_proof_bytes = (*PROVER).gen_proof(&origin_data_vec_vec, &selected_fields_vec_vec);
+        // 取快照, 证明期间不阻塞撤销
+        let revocations = REVOCATION_TREE.lock().unwrap().clone();
+        let zk_proof_bytes = (*PROVER)
+            .gen_calldata(&origin_data_vec_vec, &selected_fields_vec_vec, &revocations)
+            .map_err(error::ErrorGone)?;
         // TODO: remove
         println!("size:{}", zk_proof_bytes.len());
 
-        hex::encode([zk_proof_bytes, compress_proof_vec.concat()].concat())
+        hex::encode(zk_proof_bytes)
     } else {
         String::new()
     };
 
+    // 隐去部分字段的副本沿用原记录的密钥, 原证书删除后副本同样不可读
     let mut new_origin_data_cid = Vec::new();
-    for (origin_data_vec, selected_fields) in origin_data_vec_vec
+    for ((origin_data_vec, selected_fields), auth) in origin_data_vec_vec
         .iter()
         .zip(selected_fields_vec_vec.iter())
+        .zip(auths.iter())
     {
         let mut new_origin_data_vec = Vec::new();
         for (i, origin_data) in origin_data_vec.iter().enumerate() {
@@ -207,8 +338,9 @@ pub async fn generate_authentication(
                     new_origin_data_obj.insert(key.to_string(), Value::Null);
                 }
             }
-            let new_cid = upload_to_ipfs(
+            let new_cid = upload_record_copy_to_ipfs(
                 &ipfs_client,
+                &auth.cid[i],
                 serde_json::to_string(&new_origin_data_obj)
                     .unwrap()
                     .as_bytes()
@@ -236,7 +368,18 @@ pub async fn generate_authentication(
         id: auths.iter().map(|auth| auth.id.clone()).collect(),
         data_cid: new_origin_data_cid,
         tx_hash: auths.iter().map(|auth| auth.tx_hash.clone()).collect(),
+        shards: auths
+            .iter()
+            .map(|auth| BatchShard {
+                shard: auth.shard,
+                shards: auth.shards,
+                index: auth.index,
+                domain_size: auth.domain_size,
+            })
+            .collect(),
+        chain_ids: auths.iter().map(|auth| auth.chain_id).collect(),
         proof,
+        edu_types,
         zk_proof,
         random: if is_zk {
             hex::encode(xi.to_bytes())
@@ -255,7 +398,8 @@ mod tests {
     use ipfs_api_backend_hyper::TryFromUri;
     use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
     use snark_verifier_sdk::snark_verifier::{
-        halo2_base::halo2_proofs::halo2curves::bn256::G1, util::arithmetic::Group,
+        halo2_base::halo2_proofs::halo2curves::{bn256::G1, serde::SerdeObject},
+        util::arithmetic::Group,
     };
 
     use super::*;
@@ -279,10 +423,15 @@ mod tests {
             tx_hash: vec![
                 "0xc6b00aa31d19f36b0824a86b4fa34a3fe8db941997f10a75f69a8a578b9047ee".to_string(),
             ],
-            proof: "eac9cf9af6438f8a0b82323285f063840c4e98fce3cff86b456958ce50479c7f".to_string(),
+            proof: vec![
+                "eac9cf9af6438f8a0b82323285f063840c4e98fce3cff86b456958ce50479c7f".to_string(),
+            ],
+            edu_types: Vec::new(),
             zk_proof: "130bd08c2c842588d4be476747459815d9547277c750b183e041cb3c59395bbd"
                 .to_string(),
             random: "693f0c0000000000".to_string(),
+            shards: Vec::new(),
+            chain_ids: Vec::new(),
         };
         println!("认证文件: {auth_data:#?}");
 
@@ -303,7 +452,7 @@ mod tests {
         let res = verify_proof(
             &data_vec,
             &commit_vec,
-            auth_data.proof,
+            auth_data.proof.concat(),
             auth_data.zk_proof,
             auth_data.random,
         );
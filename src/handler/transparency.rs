@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use actix_web::{error, web, Result};
+use serde::{Deserialize, Serialize};
+use web3::types::{Address, H256};
+
+use crate::services::network::NetworkRegistry;
+use crate::services::translog::{LogEntry, SignedTreeHead, TransparencyLog};
+
+// 单次最多返回的条目数
+const MAX_ENTRIES: u64 = 256;
+
+#[derive(Debug, Serialize)]
+pub struct TreeHeadResponse {
+    // 树头签名地址
+    pub log_address: Address,
+    #[serde(flatten)]
+    pub tree_head: SignedTreeHead,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConsistencyQuery {
+    pub first: u64,
+    pub second: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConsistencyResponse {
+    pub first: u64,
+    pub second: u64,
+    pub proof: Vec<H256>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InclusionQuery {
+    pub hash: H256,
+    pub tree_size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InclusionResponse {
+    pub leaf_index: u64,
+    pub tree_size: u64,
+    pub audit_path: Vec<H256>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EntriesQuery {
+    pub start: u64,
+    pub end: u64,
+}
+
+fn transparency_log(networks: &NetworkRegistry, chain_id: u64) -> Result<Arc<TransparencyLog>> {
+    networks
+        .network(Some(chain_id))
+        .map_err(error::ErrorNotFound)?
+        .log
+        .clone()
+        .ok_or_else(|| error::ErrorNotFound("Network is not a transparency log"))
+}
+
+/// 当前签名树头
+pub async fn tree_head(
+    chain_id: web::Path<u64>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<TreeHeadResponse>> {
+    let log = transparency_log(&networks, *chain_id)?;
+    Ok(web::Json(TreeHeadResponse {
+        log_address: log.address(),
+        tree_head: log.tree_head().map_err(error::ErrorInternalServerError)?,
+    }))
+}
+
+/// 两个树大小之间的一致性证明, 审计方据此发现日志分叉
+pub async fn consistency(
+    chain_id: web::Path<u64>,
+    query: web::Query<ConsistencyQuery>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<ConsistencyResponse>> {
+    let log = transparency_log(&networks, *chain_id)?;
+    let proof = log
+        .consistency(query.first, query.second)
+        .map_err(error::ErrorBadRequest)?;
+    Ok(web::Json(ConsistencyResponse {
+        first: query.first,
+        second: query.second,
+        proof,
+    }))
+}
+
+/// 叶子在指定树大小下的审计路径
+pub async fn inclusion(
+    chain_id: web::Path<u64>,
+    query: web::Query<InclusionQuery>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<InclusionResponse>> {
+    let log = transparency_log(&networks, *chain_id)?;
+    let (leaf_index, audit_path) = log
+        .inclusion(query.hash, query.tree_size)
+        .map_err(error::ErrorNotFound)?;
+    Ok(web::Json(InclusionResponse {
+        leaf_index,
+        tree_size: query.tree_size,
+        audit_path,
+    }))
+}
+
+/// 区间 `[start, end)` 内的条目
+pub async fn entries(
+    chain_id: web::Path<u64>,
+    query: web::Query<EntriesQuery>,
+    networks: web::Data<NetworkRegistry>,
+) -> Result<web::Json<Vec<LogEntry>>> {
+    let log = transparency_log(&networks, *chain_id)?;
+    let end = query.end.min(query.start.saturating_add(MAX_ENTRIES));
+    Ok(web::Json(log.entries(query.start, end)))
+}
@@ -1,16 +1,24 @@
 use actix_cors::Cors;
 use actix_web::{web, App, HttpServer};
 use dotenv::dotenv;
-use handler::{company, school, student, verify_hash};
+use handler::{
+    cache_metrics, company, content, issuers, school, student, transparency, verify_hash,
+};
 use ipfs_api_backend_hyper::{IpfsClient, TryFromUri};
-use log::{error, info};
+use log::{error, info, warn};
 use std::env;
+use std::sync::Mutex;
 
 mod handler;
 mod models;
 mod services;
 
-use services::ethereum::{create_web3_connection, deploy_contract, CONTRACT_ADDRESS};
+use services::deployment::deploy_and_record;
+use services::evm::{LocalVerifier, VerifyBackend, LOCAL_VERIFIER, VERIFY_BACKEND};
+use services::ipfs::IPFS_EXECUTOR_CONFIG;
+use services::network::{Backend, NetworkRegistry, NetworksConfig};
+use services::revocation::publish_revocation_root;
+use services::wallet::{Wallet, WALLET};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -24,27 +32,92 @@ async fn main() -> std::io::Result<()> {
     info!("Starting server at http://127.0.0.1:3000");
 
     // 检查环境变量
-    let eth_url =
-        env::var("ETHEREUM_NODE_URL").unwrap_or_else(|_| "http://127.0.0.1:8545".to_string());
     let ipfs_url = env::var("IPFS_API_URL").unwrap_or_else(|_| "http://localhost:5001".to_string());
 
     info!("配置信息:");
     info!("IPFS endpoint: {ipfs_url}");
-    info!("Ethereum endpoint: {eth_url}");
+    info!("IPFS executor: {:?}", *IPFS_EXECUTOR_CONFIG);
+    info!("Verify backend: {:?}", *VERIFY_BACKEND);
 
-    // 初始化Web3连接并部署合约
+    // 读取网络配置并连接默认网络
     info!("正在连接以太坊网络...");
-    let web3 = match create_web3_connection().await {
-        Ok(web3) => {
-            info!("成功连接到以太坊网络");
-            web3
+    let networks_config = match NetworksConfig::load().await {
+        Ok(config) => config,
+        Err(e) => {
+            error!("加载网络配置失败: {e}");
+            panic!("加载网络配置失败");
         }
+    };
+    for network in &networks_config.networks {
+        info!(
+            "Network {}: chain {} at {}",
+            network.name, network.chain_id, network.rpc_url
+        );
+    }
+    // 默认网络为透明日志时不连接节点
+    let web3 = match networks_config.default_network() {
+        Ok(network) if matches!(network.backend, Backend::TransparencyLog { .. }) => None,
+        Ok(network) => match network.connect().await {
+            Ok(web3) => {
+                info!("成功连接到以太坊网络");
+                Some(web3)
+            }
+            Err(e) => {
+                error!("连接以太坊网络失败: {e}");
+                panic!("无法连接到以太坊网络");
+            }
+        },
         Err(e) => {
-            error!("连接以太坊网络失败: {e}");
+            error!("没有可用的网络配置: {e}");
             panic!("无法连接到以太坊网络");
         }
     };
 
+    // 加载发证方签名账户
+    let wallet = match Wallet::load(web3.as_ref()).await {
+        Ok(wallet) => wallet,
+        Err(e) => {
+            error!("加载签名账户失败: {e}");
+            panic!("加载签名账户失败");
+        }
+    };
+    let wallet = WALLET.get_or_init(|| wallet);
+
+    // 管理命令: 部署合约并写入部署记录, 不启动服务.
+    // `edu-verify deploy [chain_id]`, 未指定链ID时部署到默认网络
+    if env::args().nth(1).as_deref() == Some("deploy") {
+        let web3 = match env::args().nth(2) {
+            Some(chain_id) => {
+                let network = chain_id
+                    .parse()
+                    .map_err(anyhow::Error::from)
+                    .and_then(|chain_id| networks_config.network(chain_id));
+                match network {
+                    Ok(network) => network.connect().await,
+                    Err(e) => Err(e),
+                }
+            }
+            None => web3.ok_or_else(|| anyhow::anyhow!("默认网络不是以太坊网络")),
+        };
+        let web3 = match web3 {
+            Ok(web3) => web3,
+            Err(e) => {
+                error!("连接部署网络失败: {e}");
+                panic!("连接部署网络失败");
+            }
+        };
+
+        info!("正在部署合约...");
+        match deploy_and_record(&web3, wallet.default_signer()).await {
+            Ok(address) => info!("合约部署成功，地址: {address}"),
+            Err(e) => {
+                error!("合约部署失败: {e}");
+                panic!("合约部署失败");
+            }
+        }
+        return Ok(());
+    }
+
     // 初始化IPFS客户端
     let ipfs_client = match IpfsClient::from_str(&ipfs_url) {
         Ok(ipfs_client) => {
@@ -57,24 +130,37 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
-    info!("正在部署合约...");
-    let contract_address = match deploy_contract(&web3).await {
-        Ok(address) => {
-            info!("合约部署成功，地址: {address}");
-            address
-        }
+    info!("正在加载合约部署...");
+    let networks = match NetworkRegistry::connect(&networks_config).await {
+        Ok(networks) => web::Data::new(networks),
         Err(e) => {
-            error!("合约部署失败: {e}");
-            panic!("合约部署失败");
+            error!("加载合约失败: {e}");
+            panic!("加载合约失败");
         }
     };
 
-    match CONTRACT_ADDRESS.set(contract_address.clone()) {
-        Ok(_) => info!("合约地址已保存"),
-        Err(_) => error!("无法保存合约地址"),
+    // 透明日志后端没有链上合约, 同样使用本地EVM验证
+    let has_log = networks_config
+        .networks
+        .iter()
+        .any(|network| matches!(network.backend, Backend::TransparencyLog { .. }));
+    if *VERIFY_BACKEND == VerifyBackend::Evm || has_log {
+        info!("正在初始化本地EVM验证合约...");
+        match LocalVerifier::deploy() {
+            Ok(verifier) => {
+                let _ = LOCAL_VERIFIER.set(Mutex::new(verifier));
+            }
+            Err(e) => {
+                error!("本地EVM初始化失败: {e}");
+                panic!("本地EVM初始化失败");
+            }
+        }
     }
 
-    info!("Contract deployed at: {contract_address}");
+    // 合约中的撤销累加器根可能落后于本地累加器
+    if let Err(e) = publish_revocation_root(&networks).await {
+        warn!("撤销累加器根发布失败: {e}");
+    }
 
     HttpServer::new(move || {
         let cors = Cors::default()
@@ -84,17 +170,37 @@ async fn main() -> std::io::Result<()> {
 
         App::new()
             .wrap(cors)
-            .app_data(web::Data::new(web3.clone()))
+            .app_data(networks.clone())
             .app_data(web::Data::new(ipfs_client.clone()))
             .service(
                 web::resource("/api/school/upload")
                     .route(web::post().to(school::upload_and_gen_cert)),
             )
+            .service(
+                web::resource("/api/school/upload-images")
+                    .route(web::post().to(school::upload_images)),
+            )
             .service(
                 web::resource("/api/school/download/{filename}")
                     .route(web::get().to(school::download_certificate)),
             )
+            .service(
+                web::resource("/api/school/batches/{tx_hash}/records")
+                    .route(web::post().to(school::append_records)),
+            )
+            .service(
+                web::resource("/api/school/correct")
+                    .route(web::post().to(school::correct_certificates)),
+            )
+            .service(
+                web::resource("/api/school/revoke")
+                    .route(web::post().to(school::revoke_certificate)),
+            )
+            .service(
+                web::resource("/api/school/keys/rotate").route(web::post().to(issuers::rotate_key)),
+            )
             .service(web::resource("/api/student/upload").route(web::post().to(student::upload)))
+            .service(web::resource("/api/student/erase").route(web::post().to(student::erase)))
             .service(
                 web::resource("/api/student/generate-authentication")
                     .route(web::post().to(student::generate_authentication)),
@@ -104,6 +210,43 @@ async fn main() -> std::io::Result<()> {
                 web::resource("/api/company/verify-auth-data")
                     .route(web::post().to(company::verify_auth_data)),
             )
+            .service(web::resource("/api/content/{cid}").route(web::get().to(content::get_content)))
+            .service(web::resource("/api/metrics/cache").route(web::get().to(cache_metrics)))
+            .service(
+                web::resource("/api/log/{chain_id}/sth")
+                    .route(web::get().to(transparency::tree_head)),
+            )
+            .service(
+                web::resource("/api/log/{chain_id}/consistency")
+                    .route(web::get().to(transparency::consistency)),
+            )
+            .service(
+                web::resource("/api/log/{chain_id}/inclusion")
+                    .route(web::get().to(transparency::inclusion)),
+            )
+            .service(
+                web::resource("/api/log/{chain_id}/entries")
+                    .route(web::get().to(transparency::entries)),
+            )
+            .service(
+                web::resource("/api/admin/issuers").route(web::post().to(issuers::register_issuer)),
+            )
+            .service(
+                web::resource("/api/admin/issuers/{code}/validity")
+                    .route(web::put().to(issuers::set_validity)),
+            )
+            .service(
+                web::resource("/api/admin/issuers/{code}/keys")
+                    .route(web::post().to(issuers::add_key)),
+            )
+            .service(
+                web::resource("/api/admin/keys/{address}/retire")
+                    .route(web::post().to(issuers::retire_key)),
+            )
+            .service(
+                web::resource("/api/issuers/keys/{address}").route(web::get().to(issuers::get_key)),
+            )
+            .service(web::resource("/api/issuers/{code}").route(web::get().to(issuers::get_issuer)))
             // only for test
             .service(web::resource("/verify").route(web::post().to(verify_hash)))
     })
@@ -2,6 +2,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::BTreeMap;
 
+use crate::services::ledger::RevocationReason;
+use crate::services::translog::LogInclusion;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ImageData {
     pub name: String,
@@ -26,7 +29,34 @@ pub struct GenerateAuthenticationData {
     pub tx_hash: String,
     pub cid: Vec<String>,
     pub proof: Vec<String>,
+    // 与 cid 一一对应的学历类型, 缺省时为 0..cid.len()
+    #[serde(default)]
+    pub edu_types: Vec<u32>,
     pub is_zk: bool,
+    // 证书所在分片与批次的分片数
+    #[serde(default)]
+    pub shard: u32,
+    #[serde(default)]
+    pub shards: u32,
+    // 证书在分片中的槽位与分片的定义域大小, 打开点为 ω^index
+    #[serde(default)]
+    pub index: u32,
+    #[serde(default)]
+    pub domain_size: u32,
+    // 证书锚定所在链, 同 Certificate.chain_id
+    #[serde(default)]
+    pub chain_id: u64,
+}
+
+// 证书在批次中的分片与槽位, 锚定的承诺按分片依次排列. 旧证书 shards 为 0, 即整个批次
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchShard {
+    pub shard: u32,
+    pub shards: u32,
+    #[serde(default)]
+    pub index: u32,
+    #[serde(default)]
+    pub domain_size: u32,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -34,15 +64,103 @@ pub struct AuthenticationData {
     pub id: Vec<String>,
     pub data_cid: Vec<String>,
     pub tx_hash: Vec<String>,
-    pub proof: String,
+    // 与 tx_hash 一一对应: 展示的各学历类型的 证明(96bytes) || 值(32bytes) 及其类型
+    pub proof: Vec<String>,
+    #[serde(default)]
+    pub edu_types: Vec<Vec<u32>>,
     pub zk_proof: String,
     pub random: String,
+    // 与 tx_hash 一一对应
+    #[serde(default)]
+    pub shards: Vec<BatchShard>,
+    // 与 tx_hash 一一对应的链ID, 0 为默认网络
+    #[serde(default)]
+    pub chain_ids: Vec<u64>,
+}
+
+#[derive(Debug, Serialize, Default)]
+pub struct ImageUploadResult {
+    pub filename: String,
+    pub success: bool,
+    pub ipfs_cid: Option<String>,
+    pub mime: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub size: Option<usize>,
+    pub error: Option<String>,
+}
+
+// 上传参数: 链ID对应 networks.json 中的网络; 发证学校由请求的接口令牌确定, 不由参数指定
+#[derive(Debug, Deserialize)]
+pub struct UploadQuery {
+    pub chain_id: Option<u64>,
+}
+
+// 企业提交认证文件的参数: 显式要求时验证结果在链上记录, 随 AuthVerifyData 转交验证
+#[derive(Debug, Deserialize)]
+pub struct CompanyUploadQuery {
+    #[serde(default)]
+    pub attest: bool,
+}
+
+// 撤销请求: 证书编号与其锚定交易, 学校由接口令牌确定, 链由 UploadQuery 指定
+#[derive(Debug, Deserialize)]
+pub struct RevokeRequest {
+    pub id: String,
+    pub tx_hash: String,
+    pub reason: RevocationReason,
+    // 证书的原始数据索引, 提供时同时加入撤销累加器, 使零知识展示也无法使用该证书
+    #[serde(default)]
+    pub original_data_cid: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevokeResponse {
+    pub success: bool,
+    pub id: String,
+    // 撤销交易哈希, 透明日志后端为撤销条目的叶子哈希
+    pub revocation_tx_hash: String,
+    pub reason: RevocationReason,
 }
 
 #[derive(Debug, Serialize)]
 pub struct UploadResponse {
     pub success: bool,
     pub certificate_file: String,
+    // 更新批次时被新证书取代的原证书, success 为 false 时部分原证书未被取代或其记录未加入撤销累加器
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub superseded: Vec<SupersessionResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accumulator_error: Option<String>,
+}
+
+// 更正请求: 为部分学生重新签发证书, 取代 supersedes 交易锚定的批次中编号相同的旧证书
+#[derive(Debug, Deserialize)]
+pub struct CorrectionRequest {
+    pub supersedes: String,
+    pub records: Vec<RecordData>,
+    // 旧证书的原始数据索引, 按证书编号; 批次登记中没有时须提供, 旧记录取代后加入撤销累加器
+    #[serde(default)]
+    pub original_data_cids: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SupersessionResult {
+    pub id: String,
+    // 取代交易哈希, 透明日志后端为取代条目的叶子哈希; 取代失败时为空
+    pub supersession_tx_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// 更正证书在新批次锚定后总会返回, success 为 false 时部分旧证书未被取代或其记录未加入撤销累加器
+#[derive(Debug, Serialize)]
+pub struct CorrectionResponse {
+    pub success: bool,
+    pub certificate_file: String,
+    pub superseded: Vec<SupersessionResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accumulator_error: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -51,6 +169,30 @@ pub struct Certificate {
     pub original_data_cid: String,
     pub proof: String,
     pub tx_hash: String,
+    // anchorBatch 的发证方与批次ID, 旧证书没有这两个字段
+    #[serde(default)]
+    pub issuer: String,
+    #[serde(default)]
+    pub batch_id: String,
+    // 锚定所在链, 旧证书为0, 按默认网络解析
+    #[serde(default)]
+    pub chain_id: u64,
+    // 透明日志后端的包含证明与签名树头
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_inclusion: Option<LogInclusion>,
+    // 更正证书或更新批次中的证书所取代的旧证书的锚定交易
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub supersedes: Option<String>,
+    // 超过 2^16 个学生的批次分为多个分片: 所在分片、分片数及在分片中的位置
+    #[serde(default)]
+    pub shard: u32,
+    #[serde(default)]
+    pub shards: u32,
+    #[serde(default)]
+    pub index: u32,
+    // 分片多项式补零后的定义域大小, 2 的幂
+    #[serde(default)]
+    pub domain_size: u32,
 }
 
 #[derive(Debug, Serialize)]
@@ -58,6 +200,44 @@ pub struct VerifyResponse {
     pub verified: bool,
     pub message: String,
     pub data: Option<VerifiedData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anchor: Option<AnchorReport>,
+    // 证书已被发证方撤销时返回撤销原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revocation: Option<RevocationReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RevocationReport {
+    pub id: String,
+    pub tx_hash: String,
+    pub reason: RevocationReason,
+    pub reason_code: u8,
+    // 被更正证书取代时, 更正证书所在的批次ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub superseded_by: Option<String>,
+}
+
+// 证书何时、由谁锚定.
+// status 为 proven 表示存储证明已对照可信区块头验证, asserted 表示直接采信节点返回
+#[derive(Debug, Serialize)]
+pub struct AnchorReport {
+    pub status: String,
+    pub block_number: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<String>,
+    // 区块时间, RFC 3339
+    pub issued_at: String,
+    pub issuer_address: String,
+    // 发证方目录中的学校名称, 未登记时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer_name: Option<String>,
+    // 登记合约中的学校编码
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer_code: Option<String>,
+    // 登记合约校验结果: authorized / unregistered / unauthorized, 未配置时为 unchecked
+    pub issuer_status: String,
+    pub confirmations: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -67,26 +247,77 @@ pub struct VerifiedData {
     pub images: Vec<VerifiedImage>,
     pub cid: String,
     pub proof: String,
+    pub shard: u32,
+    pub shards: u32,
+    pub chain_id: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthVerifyData {
+    #[serde(default)]
+    pub id: Vec<String>,
     pub data: Vec<Vec<BTreeMap<String, Value>>>,
     pub images: Vec<Vec<Vec<VerifiedImage>>>,
     pub tx_hashs: Vec<String>,
-    pub proof: String,
+    // 与 tx_hashs 一一对应的链ID, 缺省时使用默认网络
+    #[serde(default)]
+    pub chain_ids: Vec<u64>,
+    // 与 tx_hashs 一一对应, 缺省时取整个批次
+    #[serde(default)]
+    pub shards: Vec<BatchShard>,
+    // 与 tx_hashs 一一对应, 同 AuthenticationData
+    pub proof: Vec<String>,
+    #[serde(default)]
+    pub edu_types: Vec<Vec<u32>>,
     pub zk_proof: String,
     pub random: String,
+    // 显式要求在链上记录验证结果, 默认只做 eth_call
+    #[serde(default)]
+    pub attest: bool,
 }
 
 #[derive(Debug, Serialize)]
 pub struct AuthVerifyResultData {
     pub verified: bool,
+    // 链上记录的交易哈希, 未记录时为空
     pub tx_hash: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestation: Option<AttestationReceipt>,
+    // 与 tx_hashs 一一对应
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub anchors: Vec<AnchorReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revocation: Option<RevocationReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AttestationReceipt {
+    pub tx_hash: String,
+    pub block_number: u64,
+    pub request_hash: String,
+    pub verified: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VerifiedImage {
     pub ipfs_cid: String,
-    pub data: String, // base64 encoded image data
+    pub url: String, // GET /api/content/{cid}
+}
+
+// 删除请求须附上完整的证书: 原始数据索引与证明只在持有人 (及签发学校) 手中,
+// 按锚定的承诺校验通过才视为持有人本人的请求
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErasureRequest {
+    pub certificate: Certificate,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErasureResponse {
+    pub success: bool,
+    pub id: String,
+    pub unpinned_cids: Vec<String>,
+    // 密钥已销毁的记录
+    pub shredded_cids: Vec<String>,
+    pub erased_at: String,
 }
@@ -0,0 +1,215 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use anyhow::{anyhow, bail, Context};
+use log::{info, warn};
+use serde_json::Value;
+use sha3::{Digest, Keccak256};
+use web3::transports::Http;
+use web3::types::{Address, BlockId, H256};
+use web3::Web3;
+
+use crate::services::bindings::CertificateVerifier;
+use crate::services::ethereum::get_transaction_data;
+use crate::services::inclusion::AnchorStatus;
+use crate::services::ledger::FetchedBatch;
+use crate::services::translog::LogInclusion;
+use crate::services::wallet::Signer;
+
+/// 一次上传在链上的锚定位置
+#[derive(Debug, Clone)]
+pub struct Anchor {
+    pub tx_hash: H256,
+    pub issuer: Address,
+    pub batch_id: H256,
+    // 透明日志后端的包含证明, 写入证书
+    pub inclusion: Option<LogInclusion>,
+}
+
+/// 锚定交易所在区块、时间与发送方
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnchorInfo {
+    pub block_number: u64,
+    // 区块时间, 秒
+    pub timestamp: u64,
+    pub sender: Address,
+    pub confirmations: u64,
+}
+
+lazy_static::lazy_static! {
+    // 以合约创建交易的 calldata 存证的旧版本锚定交易, 逗号分隔
+    static ref LEGACY_ANCHOR_TXS: HashSet<H256> = std::env::var("LEGACY_ANCHOR_TXS")
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|tx_hash| tx_hash.trim().parse().ok())
+        .collect();
+}
+
+/// 新批次的ID: 承诺字节与随机盐的 keccak256. 加盐后同一学校可以再次签发内容相同的批次,
+/// 因此批次ID不能由承诺重新计算, 一律取自锚定事件或证书
+pub fn new_batch_id(commitments: &[u8]) -> H256 {
+    let salt: [u8; 32] = rand::random();
+    H256::from_slice(&Keccak256::digest([commitments, &salt].concat()))
+}
+
+/// 数据结构哈希: 按学历类别排列的字段名集合
+pub fn schema_hash(edu_types: &[Vec<BTreeMap<String, Value>>]) -> H256 {
+    let schema = edu_types
+        .iter()
+        .map(|records| {
+            records
+                .iter()
+                .flat_map(|record| record.keys().cloned())
+                .collect::<BTreeSet<_>>()
+        })
+        .collect::<Vec<_>>();
+    H256::from_slice(&Keccak256::digest(
+        serde_json::to_vec(&schema).expect("schema is serializable"),
+    ))
+}
+
+/// 以学校的签名账户调用 `anchorBatch` 将承诺写入合约存储, 交易发送方即发证学校
+pub async fn anchor_commitments(
+    verifier: &CertificateVerifier,
+    signer: &Signer,
+    schema_hash: H256,
+    commitments: Vec<u8>,
+    students: u64,
+) -> anyhow::Result<Anchor> {
+    let issuer = signer.address();
+
+    let batch_id = new_batch_id(&commitments);
+    let tx_hash = verifier
+        .anchor_batch(batch_id, schema_hash, commitments, students, signer)
+        .await?;
+    info!("批次承诺已锚定, 学校: {issuer:?}, 批次: {batch_id:?}, 交易: {tx_hash:?}");
+
+    Ok(Anchor {
+        tx_hash,
+        issuer,
+        batch_id,
+        inclusion: None,
+    })
+}
+
+/// 以学校的签名账户调用 `updateBatch`, 锚定在已有批次上追加或更新学生后的承诺
+pub async fn anchor_update(
+    verifier: &CertificateVerifier,
+    signer: &Signer,
+    parent_batch_id: H256,
+    schema_hash: H256,
+    commitments: Vec<u8>,
+    delta: Vec<u8>,
+    students: u64,
+) -> anyhow::Result<Anchor> {
+    let issuer = signer.address();
+
+    let batch_id = new_batch_id(&commitments);
+    let tx_hash = verifier
+        .update_batch(
+            parent_batch_id,
+            batch_id,
+            schema_hash,
+            commitments,
+            delta,
+            students,
+            signer,
+        )
+        .await?;
+    info!(
+        "批次更新已锚定, 学校: {issuer:?}, 原批次: {parent_batch_id:?}, 新批次: {batch_id:?}, 交易: {tx_hash:?}"
+    );
+
+    Ok(Anchor {
+        tx_hash,
+        issuer,
+        batch_id,
+        inclusion: None,
+    })
+}
+
+/// 读取交易锚定的批次: 由回执中的 `BatchAnchored` 事件定位批次, 再读取合约存储.
+/// 没有该事件的交易只有在 LEGACY_ANCHOR_TXS 中时才按旧格式读取 calldata
+pub async fn load_batch(
+    web3: &Web3<Http>,
+    verifier: &CertificateVerifier,
+    tx_hash: &str,
+) -> anyhow::Result<FetchedBatch> {
+    let hash: H256 = tx_hash.parse().context("invalid transaction hash")?;
+    let receipt = web3
+        .eth()
+        .transaction_receipt(hash)
+        .await?
+        .ok_or_else(|| anyhow!("Transaction receipt not found"))?;
+    if receipt.status == Some(0.into()) {
+        bail!("anchor transaction {tx_hash} reverted");
+    }
+
+    match verifier.parse_batch_anchored(&receipt.logs) {
+        Some(event) => Ok(FetchedBatch {
+            issuer: event.issuer,
+            batch_id: event.batch_id,
+            commitments: verifier
+                .get_batch(event.issuer, event.batch_id)
+                .await?
+                .commitments,
+            anchor: AnchorStatus::Asserted,
+        }),
+        None if LEGACY_ANCHOR_TXS.contains(&hash) => {
+            // 旧版本以合约创建交易的 calldata 存证, 没有批次ID, 也无法撤销
+            warn!("交易 {tx_hash} 为旧格式锚定, 读取交易数据");
+            let data = get_transaction_data(web3, tx_hash).await?;
+            Ok(FetchedBatch {
+                issuer: receipt.from,
+                batch_id: H256::zero(),
+                commitments: hex::decode(data).context("invalid transaction data")?,
+                anchor: AnchorStatus::Asserted,
+            })
+        }
+        None => bail!("transaction {tx_hash} has no BatchAnchored event"),
+    }
+}
+
+/// 读取锚定交易的区块时间、发送方与当前确认数
+pub async fn load_anchor_info(web3: &Web3<Http>, tx_hash: &str) -> anyhow::Result<AnchorInfo> {
+    let hash: H256 = tx_hash.parse().context("invalid transaction hash")?;
+    let receipt = web3
+        .eth()
+        .transaction_receipt(hash)
+        .await?
+        .ok_or_else(|| anyhow!("Transaction receipt not found"))?;
+    let (Some(block_hash), Some(block_number)) = (receipt.block_hash, receipt.block_number) else {
+        bail!("transaction {tx_hash} is pending");
+    };
+    let block = web3
+        .eth()
+        .block(BlockId::Hash(block_hash))
+        .await?
+        .ok_or_else(|| anyhow!("block {block_hash:?} not found"))?;
+    let latest = web3.eth().block_number().await?.as_u64();
+
+    Ok(AnchorInfo {
+        block_number: block_number.as_u64(),
+        timestamp: block.timestamp.as_u64(),
+        sender: receipt.from,
+        confirmations: (latest + 1).saturating_sub(block_number.as_u64()),
+    })
+}
+
+#[test]
+fn test_schema_hash_ignores_values_and_order() {
+    let record = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), Value::String(v.to_string())))
+            .collect::<BTreeMap<_, _>>()
+    };
+
+    let a = vec![vec![record(&[("姓名", "张三"), ("专业", "软件工程")])]];
+    let b = vec![vec![record(&[("专业", "计算机"), ("姓名", "李四")])]];
+    let c = vec![vec![record(&[("姓名", "张三")])]];
+
+    assert_eq!(schema_hash(&a), schema_hash(&b));
+    assert_ne!(schema_hash(&a), schema_hash(&c));
+    // 相同的承诺每次得到不同的批次ID
+    assert_ne!(new_batch_id(&[1; 96]), new_batch_id(&[1; 96]));
+}
@@ -0,0 +1,371 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, ensure, Context};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use snark_verifier_sdk::snark_verifier::halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+
+use crate::services::shplonk::{domain_size, MAX_DEGREE};
+
+// 已签发批次的槽位登记文件路径
+const DEFAULT_BATCH_REGISTRY_PATH: &str = "batches/registry.json";
+// 单个批次默认最多的分片数
+const DEFAULT_MAX_BATCH_SHARDS: usize = 16;
+pub const G1_SIZE: usize = 96;
+
+/// 已签发批次的槽位与求值. 承诺对求值线性, 追加或更新学生时据此只计算增量
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct BatchRecord {
+    pub issuer: String,
+    pub chain_id: u64,
+    pub tx_hash: String,
+    // 每个分片最多 MAX_DEGREE 个学生, 锚定的承诺按分片依次排列
+    pub shards: Vec<ShardRecord>,
+    // 批次中证书引用的图片 CID, /api/content 只提供这些内容
+    #[serde(default)]
+    pub images: BTreeSet<String>,
+    // 证书编号 -> 原始数据索引 CID, 证书被取代或更新时据此将旧记录加入撤销累加器
+    #[serde(default)]
+    pub original_data: BTreeMap<String, String>,
+}
+
+/// 一个分片内各学历类型的多项式
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ShardRecord {
+    // 分片内的学生编号, 位置即证书中的 index, 也是各学历类型多项式中的槽位
+    pub students: Vec<String>,
+    // 各学历类型的压缩求值, Fr 原始字节的十六进制, 学生没有该类学历时为 0
+    pub evals: Vec<Vec<String>>,
+}
+
+/// 某分片某学历类型的某个槽位的求值增量
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotDelta {
+    pub shard: usize,
+    pub edu_type: usize,
+    pub slot: usize,
+    pub delta: Fr,
+}
+
+fn fr_to_hex(value: Fr) -> String {
+    hex::encode(value.to_bytes())
+}
+
+fn fr_from_hex(value: &str) -> anyhow::Result<Fr> {
+    let bytes: [u8; 32] = hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow!("invalid eval {value}"))?;
+    Option::from(Fr::from_bytes(&bytes)).ok_or_else(|| anyhow!("invalid eval {value}"))
+}
+
+/// 证书在批次中的撤销槽位: 第 shard 个分片的第 index 个学生, 即 shard * MAX_DEGREE + index
+pub fn revocation_index(shard: usize, index: usize) -> u64 {
+    (shard * MAX_DEGREE + index) as u64
+}
+
+/// 单个批次可签发的学生数上限
+pub fn max_batch_size() -> usize {
+    *MAX_BATCH_SHARDS * MAX_DEGREE
+}
+
+/// 证书所在分片在锚定承诺中的区间, 旧证书 shards 为 0, 取整个批次
+pub fn shard_commitments(commitments: &[u8], shard: u32, shards: u32) -> anyhow::Result<&[u8]> {
+    let shards = shards.max(1) as usize;
+    let width = commitments.len() / shards;
+    ensure!(
+        (shard as usize) < shards
+            && width * shards == commitments.len()
+            && width.is_multiple_of(G1_SIZE),
+        "invalid shard {shard} of {shards}"
+    );
+    Ok(&commitments[shard as usize * width..(shard as usize + 1) * width])
+}
+
+impl ShardRecord {
+    pub fn new(students: Vec<String>, evals: &[Vec<Fr>]) -> Self {
+        Self {
+            students,
+            evals: evals
+                .iter()
+                .map(|evals| evals.iter().copied().map(fr_to_hex).collect())
+                .collect(),
+        }
+    }
+
+    /// 分片内各多项式共用的定义域大小, 随学生数增长
+    pub fn domain_size(&self) -> usize {
+        domain_size(self.students.len())
+    }
+
+    pub fn evals(&self) -> anyhow::Result<Vec<Vec<Fr>>> {
+        self.evals
+            .iter()
+            .map(|evals| evals.iter().map(|eval| fr_from_hex(eval)).collect())
+            .collect()
+    }
+}
+
+impl BatchRecord {
+    /// 学历类型数, 各分片一致
+    pub fn edu_types(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.evals.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 学生所在的分片及其在分片中的位置
+    pub fn position(&self, id: &str) -> Option<(usize, usize)> {
+        self.shards.iter().enumerate().find_map(|(shard, record)| {
+            record
+                .students
+                .iter()
+                .position(|student| student == id)
+                .map(|index| (shard, index))
+        })
+    }
+
+    /// 学生证书的撤销槽位, 不在批次中时为空
+    pub fn revocation_index(&self, id: &str) -> Option<u64> {
+        self.position(id)
+            .map(|(shard, index)| revocation_index(shard, index))
+    }
+
+    /// 写入学生各学历类型的求值: 已有学生原地更新, 新学生追加到最后一个分片, 已满时新开分片.
+    /// 槽位即学生在分片中的位置, 学生不再具有的学历类型置零, 返回各槽位的增量
+    pub fn upsert(&mut self, id: &str, values: &[Fr]) -> anyhow::Result<Vec<SlotDelta>> {
+        let (shard, slot) = match self.position(id) {
+            Some(position) => position,
+            None => {
+                if !matches!(self.shards.last(), Some(last) if last.students.len() < MAX_DEGREE) {
+                    ensure!(
+                        self.shards.len() < *MAX_BATCH_SHARDS,
+                        "batch exceeds {} records",
+                        max_batch_size()
+                    );
+                    self.shards.push(ShardRecord::default());
+                }
+                let shard = self.shards.len() - 1;
+                self.shards[shard].students.push(id.to_string());
+                (shard, self.shards[shard].students.len() - 1)
+            }
+        };
+
+        let edu_types = self.edu_types().max(values.len());
+        for record in self.shards.iter_mut() {
+            record.evals.resize_with(edu_types, Vec::new);
+        }
+
+        let record = &mut self.shards[shard];
+        let mut deltas = Vec::new();
+        for edu_type in 0..edu_types {
+            let value = values.get(edu_type).copied().unwrap_or(Fr::zero());
+            let evals = &mut record.evals[edu_type];
+            if evals.len() <= slot {
+                evals.resize(slot + 1, fr_to_hex(Fr::zero()));
+            }
+
+            let old = fr_from_hex(&evals[slot])?;
+            if value != old {
+                evals[slot] = fr_to_hex(value);
+                deltas.push(SlotDelta {
+                    shard,
+                    edu_type,
+                    slot,
+                    delta: value - old,
+                });
+            }
+        }
+        Ok(deltas)
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref MAX_BATCH_SHARDS: usize = std::env::var("MAX_BATCH_SHARDS")
+        .ok()
+        .and_then(|shards| shards.parse().ok())
+        .filter(|shards| *shards > 0)
+        .unwrap_or(DEFAULT_MAX_BATCH_SHARDS);
+
+    // 批次ID -> 槽位登记
+    static ref BATCH_REGISTRY: Mutex<BTreeMap<String, BatchRecord>> =
+        Mutex::new(load_registry());
+}
+
+fn registry_path() -> String {
+    std::env::var("BATCH_REGISTRY_PATH").unwrap_or_else(|_| DEFAULT_BATCH_REGISTRY_PATH.to_string())
+}
+
+fn load_registry() -> BTreeMap<String, BatchRecord> {
+    let path = registry_path();
+    match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            warn!("批次登记文件格式错误 {path}: {e}");
+            BTreeMap::new()
+        }),
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+fn save_registry(registry: &BTreeMap<String, BatchRecord>) -> anyhow::Result<()> {
+    let path = registry_path();
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(registry)?)
+        .with_context(|| format!("write batch registry {path}"))?;
+    Ok(())
+}
+
+pub fn get_batch(batch_id: &str) -> Option<BatchRecord> {
+    BATCH_REGISTRY.lock().unwrap().get(batch_id).cloned()
+}
+
+pub fn is_issued_image(cid: &str) -> bool {
+    BATCH_REGISTRY
+        .lock()
+        .unwrap()
+        .values()
+        .any(|record| record.images.contains(cid))
+}
+
+/// 登记批次槽位, 写盘失败时撤回内存中的登记, 使内存与登记文件一致
+pub fn record_batch(batch_id: &str, record: BatchRecord) -> anyhow::Result<()> {
+    let mut registry = BATCH_REGISTRY.lock().unwrap();
+    let previous = registry.insert(batch_id.to_string(), record);
+    if let Err(e) = save_registry(&registry) {
+        match previous {
+            Some(previous) => registry.insert(batch_id.to_string(), previous),
+            None => registry.remove(batch_id),
+        };
+        return Err(e);
+    }
+    info!("已登记批次槽位, 批次: {batch_id}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upsert() {
+        // b 没有第二项学历, 该槽位为 0
+        let evals = vec![
+            vec![Fr::from(1), Fr::from(2)],
+            vec![Fr::from(3), Fr::zero()],
+        ];
+        let mut record = BatchRecord {
+            shards: vec![ShardRecord::new(
+                vec!["a".to_string(), "b".to_string()],
+                &evals,
+            )],
+            ..Default::default()
+        };
+        assert_eq!(record.shards[0].evals().unwrap(), evals);
+
+        // 原值不变时没有增量
+        assert!(record
+            .upsert("a", &[Fr::from(1), Fr::from(3)])
+            .unwrap()
+            .is_empty());
+
+        // 更新 b 的第一项并新增第二项
+        let deltas = record.upsert("b", &[Fr::from(5), Fr::from(7)]).unwrap();
+        assert_eq!(
+            deltas,
+            vec![
+                SlotDelta {
+                    shard: 0,
+                    edu_type: 0,
+                    slot: 1,
+                    delta: Fr::from(3)
+                },
+                SlotDelta {
+                    shard: 0,
+                    edu_type: 1,
+                    slot: 1,
+                    delta: Fr::from(7)
+                },
+            ]
+        );
+
+        // a 不再具有第二项时置零, 新学生 c 追加到末尾并带来新的学历类型
+        let deltas = record.upsert("a", &[Fr::from(1)]).unwrap();
+        assert_eq!(
+            deltas,
+            vec![SlotDelta {
+                shard: 0,
+                edu_type: 1,
+                slot: 0,
+                delta: -Fr::from(3)
+            }]
+        );
+        let deltas = record
+            .upsert("c", &[Fr::from(4), Fr::zero(), Fr::from(6)])
+            .unwrap();
+        assert_eq!(record.position("c"), Some((0, 2)));
+        // 各学历类型中 c 的槽位都是其在分片中的位置
+        assert_eq!(
+            deltas,
+            vec![
+                SlotDelta {
+                    shard: 0,
+                    edu_type: 0,
+                    slot: 2,
+                    delta: Fr::from(4)
+                },
+                SlotDelta {
+                    shard: 0,
+                    edu_type: 2,
+                    slot: 2,
+                    delta: Fr::from(6)
+                },
+            ]
+        );
+        assert_eq!(
+            record.shards[0].evals().unwrap(),
+            vec![
+                vec![Fr::from(1), Fr::from(5), Fr::from(4)],
+                vec![Fr::zero(), Fr::from(7), Fr::zero()],
+                vec![Fr::zero(), Fr::zero(), Fr::from(6)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shards() {
+        // 分片已满时新学生进入新分片, 新分片的学历类型数与已有分片一致
+        let mut record = BatchRecord {
+            shards: vec![ShardRecord {
+                students: vec![String::new(); MAX_DEGREE],
+                evals: vec![Vec::new(); 2],
+            }],
+            ..Default::default()
+        };
+        let deltas = record.upsert("d", &[Fr::from(2)]).unwrap();
+        assert_eq!(deltas[0].shard, 1);
+        assert_eq!(record.position("d"), Some((1, 0)));
+        assert_eq!(record.revocation_index("d"), Some(MAX_DEGREE as u64));
+        assert_eq!(record.revocation_index("e"), None);
+        assert_eq!(record.shards[0].domain_size(), MAX_DEGREE);
+        assert_eq!(record.shards[1].domain_size(), 1);
+        assert_eq!(record.shards[1].evals.len(), 2);
+        assert_eq!(max_batch_size(), *MAX_BATCH_SHARDS * MAX_DEGREE);
+
+        let commitments = (0..6).flat_map(|i| [i; G1_SIZE]).collect::<Vec<u8>>();
+        assert_eq!(
+            shard_commitments(&commitments, 1, 3).unwrap(),
+            &commitments[2 * G1_SIZE..4 * G1_SIZE]
+        );
+        assert_eq!(
+            shard_commitments(&commitments, 0, 0).unwrap(),
+            &commitments[..]
+        );
+        assert!(shard_commitments(&commitments, 3, 3).is_err());
+        assert!(shard_commitments(&commitments, 0, 4).is_err());
+    }
+}
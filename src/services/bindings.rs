@@ -0,0 +1,1001 @@
+use anyhow::Context;
+use sha3::{Digest, Keccak256};
+use snark_verifier_sdk::snark_verifier::halo2_base::halo2_proofs::halo2curves::bn256::{Fr, G1};
+use web3::api::Eth;
+use web3::contract::tokens::{Detokenize, Tokenize};
+use web3::contract::{Contract, Options};
+use web3::ethabi::{encode, ParamType, RawLog, Token};
+use web3::transports::Http;
+use web3::types::{Address, Log, TransactionReceipt, H256, U256};
+
+use crate::services::ethereum::{CONTRACT_ABI, ISSUER_REGISTRY_ABI};
+use crate::services::revocation::fr_to_h256;
+use crate::services::shplonk::g1_to_evm;
+use crate::services::transaction::TX_MANAGER;
+use crate::services::wallet::Signer;
+
+/// 合约函数的调用参数, 由 build.rs 按 ABI 为每个函数生成
+pub trait ContractCall: Tokenize + Sized {
+    const NAME: &'static str;
+    const SIGNATURE: &'static str;
+    const SELECTOR: [u8; 4];
+
+    /// 调用的 calldata: 选择器加按 ABI 编码的参数
+    fn encode(self) -> Vec<u8> {
+        [&Self::SELECTOR[..], &encode(&self.into_tokens())].concat()
+    }
+}
+
+// 未用到的函数也生成了绑定
+#[allow(dead_code)]
+pub mod certificate_verifier {
+    include!(concat!(env!("OUT_DIR"), "/CertificateVerifier.rs"));
+}
+
+#[allow(dead_code)]
+pub mod issuer_registry {
+    include!(concat!(env!("OUT_DIR"), "/IssuerRegistry.rs"));
+}
+
+use certificate_verifier::{
+    AnchorBatchCall, AttestVerificationCall, GetBatchCall, GetHashCall, GetRevocationCall,
+    GetSupersessionCall, Halo2VerifierCall, RevocationRootCall, RevokeCertificateCall,
+    ShplonkVerifierCall, SupersedeCertificateCall, UpdateBatchCall, UpdateRevocationRootCall,
+};
+use issuer_registry::{
+    AddKeyCall, AuthorityCall, GetIssuerCall, GetKeyCall, IsAuthorizedCall, RegisterIssuerCall,
+    RetireKeyCall, RotateKeyCall, SetValidityCall,
+};
+
+/// `verifyData(bytes jsonData, bytes commitment, bytes proof, bytes halo2Proof, bytes index, bytes random, bool is_zk)`
+/// 的参数, 一般由 `from_openings` 构造
+pub use certificate_verifier::VerifyDataCall;
+
+/// 同一打开点上的一组打开, 即一张证书展示的各学历类型:
+/// 各承诺在 point 处的求值为 values, proofs 为对应的打开证明
+#[derive(Debug, Clone, Default)]
+pub struct Opening {
+    pub point: Fr,
+    pub commitments: Vec<G1>,
+    pub proofs: Vec<G1>,
+    pub values: Vec<Fr>,
+}
+
+impl VerifyDataCall {
+    /// 按合约的约定编码各组打开: json_data 为各求值的 32 字节大端编码, commitment 为各承诺的
+    /// x || y || 32 字节 0, proof 为 `abi.encode(G1Point[])`, index 为
+    /// `abi.encode(uint256[] points, uint256[] sizes)`. halo2_proof 为空时不做零知识验证
+    pub fn from_openings(openings: &[Opening], halo2_proof: Vec<u8>) -> Self {
+        let mut call = Self {
+            is_zk: !halo2_proof.is_empty(),
+            halo2_proof,
+            ..Default::default()
+        };
+        let (mut proofs, mut points, mut sizes) = (Vec::new(), Vec::new(), Vec::new());
+        for opening in openings {
+            for value in &opening.values {
+                call.json_data.extend(fr_to_h256(*value).as_bytes());
+            }
+            for commitment in &opening.commitments {
+                call.commitment.extend(g1_to_evm(commitment));
+                call.commitment.extend([0; 32]);
+            }
+            proofs.extend(opening.proofs.iter().map(|proof| {
+                let proof = g1_to_evm(proof);
+                Token::Tuple(vec![
+                    Token::Uint(U256::from_big_endian(&proof[..32])),
+                    Token::Uint(U256::from_big_endian(&proof[32..])),
+                ])
+            }));
+            points.push(Token::Uint(U256::from_big_endian(
+                fr_to_h256(opening.point).as_bytes(),
+            )));
+            sizes.push(Token::Uint(opening.values.len().into()));
+        }
+        call.proof = encode(&[Token::Array(proofs)]);
+        call.index = encode(&[Token::Array(points), Token::Array(sizes)]);
+        call
+    }
+}
+
+/// `getBatch(issuer, batchId)` 的返回值
+#[derive(Debug, Clone)]
+pub struct AnchoredBatch {
+    pub schema_hash: H256,
+    pub commitments: Vec<u8>,
+    pub anchored_at: U256,
+}
+
+/// `BatchAnchored` 事件
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchAnchored {
+    pub issuer: Address,
+    pub batch_id: H256,
+    pub schema_hash: H256,
+}
+
+/// `VerificationAttested` 事件, `request_hash` 为 `attestVerification` calldata 的 keccak256
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationAttested {
+    pub verifier: Address,
+    pub request_hash: H256,
+    pub verified: bool,
+}
+
+/// 由交易管理器签名发送并等待确认, 返回交易回执
+async fn send<C: ContractCall>(
+    eth: &Eth<Http>,
+    contract: &Contract<Http>,
+    call: C,
+    signer: &Signer,
+    confirmations: Option<u64>,
+) -> anyhow::Result<TransactionReceipt> {
+    TX_MANAGER
+        .send(
+            eth,
+            signer,
+            Some(contract.address()),
+            call.encode(),
+            confirmations,
+        )
+        .await
+        .with_context(|| format!("{} transaction failed", C::NAME))
+}
+
+/// 以 eth_call 执行只读调用, 返回值按 R 解码
+async fn query<C: ContractCall, R: Detokenize>(
+    contract: &Contract<Http>,
+    call: C,
+    from: Option<Address>,
+) -> anyhow::Result<R> {
+    contract
+        .query(C::NAME, call, from, Options::default(), None)
+        .await
+        .with_context(|| format!("{} call failed", C::NAME))
+}
+
+/// 解析回执日志中该合约发出的第一个 `name` 事件, 返回按声明顺序排列的参数
+fn parse_event(contract: &Contract<Http>, name: &str, logs: &[Log]) -> Option<Vec<Token>> {
+    let event = contract.abi().event(name).ok()?;
+    logs.iter()
+        .filter(|log| log.address == contract.address())
+        .find_map(|log| {
+            let log = event
+                .parse_log(RawLog {
+                    topics: log.topics.clone(),
+                    data: log.data.0.clone(),
+                })
+                .ok()?;
+            Some(log.params.into_iter().map(|param| param.value).collect())
+        })
+}
+
+/// `CertificateVerifier` 合约的强类型绑定, 参数错误在编译期暴露
+pub struct CertificateVerifier {
+    eth: Eth<Http>,
+    contract: Contract<Http>,
+    // 该网络要求的确认数, 为空时使用 TX_CONFIRMATIONS
+    confirmations: Option<u64>,
+}
+
+impl CertificateVerifier {
+    pub const BATCH_ANCHORED: &'static str = "BatchAnchored";
+    pub const BATCH_UPDATED: &'static str = "BatchUpdated";
+    pub const VERIFICATION_ATTESTED: &'static str = "VerificationAttested";
+
+    pub fn new(eth: Eth<Http>, address: Address) -> anyhow::Result<Self> {
+        let contract = Contract::from_json(eth.clone(), address, CONTRACT_ABI.as_bytes())
+            .context("invalid CertificateVerifier ABI")?;
+        Ok(Self {
+            eth,
+            contract,
+            confirmations: None,
+        })
+    }
+
+    pub fn with_confirmations(mut self, confirmations: Option<u64>) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    pub fn address(&self) -> Address {
+        self.contract.address()
+    }
+
+    /// `verifyData` 的 calldata, 用于本地 EVM 执行
+    pub fn encode_verify_data(call: VerifyDataCall) -> Vec<u8> {
+        call.encode()
+    }
+
+    /// `attestVerification` 的 calldata, 其 keccak256 即事件中的 requestHash
+    pub fn encode_attest_verification(call: VerifyDataCall) -> Vec<u8> {
+        attest_verification_call(call).encode()
+    }
+
+    /// `updateRevocationRoot` 的 calldata, 供本地 EVM 使用
+    pub fn encode_update_revocation_root(root: H256) -> Vec<u8> {
+        UpdateRevocationRootCall { root }.encode()
+    }
+
+    pub fn decode_verify_data(output: &[u8]) -> anyhow::Result<bool> {
+        web3::ethabi::decode(&[ParamType::Bool], output)?
+            .pop()
+            .and_then(Token::into_bool)
+            .ok_or_else(|| anyhow::anyhow!("invalid verifyData output"))
+    }
+
+    pub async fn verify_data(&self, call: VerifyDataCall, from: Address) -> anyhow::Result<bool> {
+        query(&self.contract, call, Some(from)).await
+    }
+
+    async fn send<C: ContractCall>(
+        &self,
+        call: C,
+        signer: &Signer,
+    ) -> anyhow::Result<TransactionReceipt> {
+        send(&self.eth, &self.contract, call, signer, self.confirmations).await
+    }
+
+    /// 发送 `attestVerification` 交易, 在链上记录一次验证结果
+    pub async fn attest_verification(
+        &self,
+        call: VerifyDataCall,
+        signer: &Signer,
+    ) -> anyhow::Result<(TransactionReceipt, VerificationAttested)> {
+        let receipt = self.send(attest_verification_call(call), signer).await?;
+        let event = self
+            .parse_verification_attested(&receipt.logs)
+            .ok_or_else(|| anyhow::anyhow!("VerificationAttested event not found"))?;
+        Ok((receipt, event))
+    }
+
+    /// 发送 `anchorBatch` 交易, 确认后返回交易哈希
+    pub async fn anchor_batch(
+        &self,
+        batch_id: H256,
+        schema_hash: H256,
+        commitments: Vec<u8>,
+        students: u64,
+        signer: &Signer,
+    ) -> anyhow::Result<H256> {
+        let call = AnchorBatchCall {
+            batch_id,
+            schema_hash,
+            commitments,
+            students: students.into(),
+        };
+        Ok(self.send(call, signer).await?.transaction_hash)
+    }
+
+    /// 发送 `updateBatch` 交易, 锚定 parent_batch_id 的更新批次, 承诺增量 delta 只随事件记录
+    #[allow(clippy::too_many_arguments)]
+    pub async fn update_batch(
+        &self,
+        parent_batch_id: H256,
+        batch_id: H256,
+        schema_hash: H256,
+        commitments: Vec<u8>,
+        delta: Vec<u8>,
+        students: u64,
+        signer: &Signer,
+    ) -> anyhow::Result<H256> {
+        let call = UpdateBatchCall {
+            parent_batch_id,
+            batch_id,
+            schema_hash,
+            commitments,
+            delta,
+            students: students.into(),
+        };
+        Ok(self.send(call, signer).await?.transaction_hash)
+    }
+
+    pub async fn get_batch(
+        &self,
+        issuer: Address,
+        batch_id: H256,
+    ) -> anyhow::Result<AnchoredBatch> {
+        let (schema_hash, commitments, anchored_at) =
+            query(&self.contract, GetBatchCall { issuer, batch_id }, None).await?;
+        Ok(AnchoredBatch {
+            schema_hash,
+            commitments,
+            anchored_at,
+        })
+    }
+
+    /// 发送 `revokeCertificate` 交易, 撤销批次中槽位为 index 的证书
+    pub async fn revoke_certificate(
+        &self,
+        batch_id: H256,
+        index: u64,
+        reason: u8,
+        signer: &Signer,
+    ) -> anyhow::Result<H256> {
+        let call = RevokeCertificateCall {
+            batch_id,
+            index: index.into(),
+            reason: reason.into(),
+        };
+        Ok(self.send(call, signer).await?.transaction_hash)
+    }
+
+    /// 证书的撤销原因码, 未撤销时为空
+    pub async fn get_revocation(
+        &self,
+        issuer: Address,
+        batch_id: H256,
+        index: u64,
+    ) -> anyhow::Result<Option<u8>> {
+        let call = GetRevocationCall {
+            issuer,
+            batch_id,
+            index: index.into(),
+        };
+        let (revoked, reason): (bool, U256) = query(&self.contract, call, None).await?;
+        Ok(revoked.then_some(reason.low_u32() as u8))
+    }
+
+    /// 发送 `supersedeCertificate` 交易, 以更正批次中的证书取代旧证书
+    pub async fn supersede_certificate(
+        &self,
+        batch_id: H256,
+        index: u64,
+        new_batch_id: H256,
+        signer: &Signer,
+    ) -> anyhow::Result<H256> {
+        let call = SupersedeCertificateCall {
+            batch_id,
+            index: index.into(),
+            new_batch_id,
+        };
+        Ok(self.send(call, signer).await?.transaction_hash)
+    }
+
+    /// 取代该证书的更正批次, 未被取代时为空
+    pub async fn get_supersession(
+        &self,
+        issuer: Address,
+        batch_id: H256,
+        index: u64,
+    ) -> anyhow::Result<Option<H256>> {
+        let call = GetSupersessionCall {
+            issuer,
+            batch_id,
+            index: index.into(),
+        };
+        let new_batch_id: H256 = query(&self.contract, call, None).await?;
+        Ok((!new_batch_id.is_zero()).then_some(new_batch_id))
+    }
+
+    /// 零知识展示须对照的撤销累加器根
+    pub async fn revocation_root(&self) -> anyhow::Result<H256> {
+        query(&self.contract, RevocationRootCall {}, None).await
+    }
+
+    /// 发送 `updateRevocationRoot` 交易, 只有部署账户可以更新
+    pub async fn update_revocation_root(
+        &self,
+        root: H256,
+        signer: &Signer,
+    ) -> anyhow::Result<H256> {
+        let call = UpdateRevocationRootCall { root };
+        Ok(self.send(call, signer).await?.transaction_hash)
+    }
+
+    fn parse_event(&self, name: &str, logs: &[Log]) -> Option<Vec<Token>> {
+        parse_event(&self.contract, name, logs)
+    }
+
+    /// 从交易回执日志中解析本合约发出的 `BatchAnchored` 事件
+    pub fn parse_batch_anchored(&self, logs: &[Log]) -> Option<BatchAnchored> {
+        let mut params = self.parse_event(Self::BATCH_ANCHORED, logs)?.into_iter();
+        Some(BatchAnchored {
+            issuer: params.next()?.into_address()?,
+            batch_id: H256::from_slice(&params.next()?.into_fixed_bytes()?),
+            schema_hash: H256::from_slice(&params.next()?.into_fixed_bytes()?),
+        })
+    }
+
+    pub fn parse_verification_attested(&self, logs: &[Log]) -> Option<VerificationAttested> {
+        let mut params = self
+            .parse_event(Self::VERIFICATION_ATTESTED, logs)?
+            .into_iter();
+        Some(VerificationAttested {
+            verifier: params.next()?.into_address()?,
+            request_hash: H256::from_slice(&params.next()?.into_fixed_bytes()?),
+            verified: params.next()?.into_bool()?,
+        })
+    }
+
+    pub async fn get_hash(&self, data: Vec<u8>) -> anyhow::Result<H256> {
+        query(&self.contract, GetHashCall { data }, None).await
+    }
+
+    pub async fn halo2_verifier(&self) -> anyhow::Result<Address> {
+        query(&self.contract, Halo2VerifierCall {}, None).await
+    }
+
+    pub async fn shplonk_verifier(&self) -> anyhow::Result<Address> {
+        query(&self.contract, ShplonkVerifierCall {}, None).await
+    }
+}
+
+// attestVerification 与 verifyData 参数相同
+fn attest_verification_call(call: VerifyDataCall) -> AttestVerificationCall {
+    AttestVerificationCall {
+        json_data: call.json_data,
+        commitment: call.commitment,
+        proof: call.proof,
+        halo2_proof: call.halo2_proof,
+        index: call.index,
+        random: call.random,
+        is_zk: call.is_zk,
+    }
+}
+
+/// `getIssuer(issuerId)` 的返回值
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisteredIssuer {
+    pub id: H256,
+    pub name: String,
+    pub code: String,
+    pub valid_from: u64,
+    // 0 表示长期有效
+    pub valid_until: u64,
+}
+
+/// `getKey(key)` 的返回值, issuer_id 为零表示未登记
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegisteredKey {
+    pub issuer_id: H256,
+    pub added_at: u64,
+    // 0 表示仍在使用
+    pub retired_at: u64,
+}
+
+/// `IssuerRegistry` 合约的强类型绑定: 认证机构登记学校, 学校维护自己的签名密钥
+pub struct IssuerRegistry {
+    eth: Eth<Http>,
+    contract: Contract<Http>,
+    confirmations: Option<u64>,
+}
+
+impl IssuerRegistry {
+    pub fn new(eth: Eth<Http>, address: Address) -> anyhow::Result<Self> {
+        let contract = Contract::from_json(eth.clone(), address, ISSUER_REGISTRY_ABI.as_bytes())
+            .context("invalid IssuerRegistry ABI")?;
+        Ok(Self {
+            eth,
+            contract,
+            confirmations: None,
+        })
+    }
+
+    pub fn with_confirmations(mut self, confirmations: Option<u64>) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// 学校编码对应的 issuerId, 与合约中的 `keccak256(bytes(code))` 一致
+    pub fn issuer_id(code: &str) -> H256 {
+        H256::from_slice(&Keccak256::digest(code.as_bytes()))
+    }
+
+    async fn send<C: ContractCall>(&self, call: C, signer: &Signer) -> anyhow::Result<H256> {
+        let receipt = send(&self.eth, &self.contract, call, signer, self.confirmations).await?;
+        Ok(receipt.transaction_hash)
+    }
+
+    pub async fn authority(&self) -> anyhow::Result<Address> {
+        query(&self.contract, AuthorityCall {}, None).await
+    }
+
+    /// 认证机构登记学校及其初始密钥
+    pub async fn register_issuer(
+        &self,
+        name: &str,
+        code: &str,
+        keys: Vec<Address>,
+        valid_from: u64,
+        valid_until: u64,
+        signer: &Signer,
+    ) -> anyhow::Result<H256> {
+        let call = RegisterIssuerCall {
+            name: name.to_string(),
+            code: code.to_string(),
+            initial_keys: keys,
+            valid_from: valid_from.into(),
+            valid_until: valid_until.into(),
+        };
+        self.send(call, signer).await
+    }
+
+    /// 认证机构调整学校的认证有效期
+    pub async fn set_validity(
+        &self,
+        issuer_id: H256,
+        valid_from: u64,
+        valid_until: u64,
+        signer: &Signer,
+    ) -> anyhow::Result<H256> {
+        let call = SetValidityCall {
+            id: issuer_id,
+            valid_from: valid_from.into(),
+            valid_until: valid_until.into(),
+        };
+        self.send(call, signer).await
+    }
+
+    /// 认证机构或该学校的有效密钥添加新密钥
+    pub async fn add_key(
+        &self,
+        issuer_id: H256,
+        key: Address,
+        signer: &Signer,
+    ) -> anyhow::Result<H256> {
+        self.send(AddKeyCall { id: issuer_id, key }, signer).await
+    }
+
+    /// 停用密钥, 停用前锚定的批次仍然有效
+    pub async fn retire_key(&self, key: Address, signer: &Signer) -> anyhow::Result<H256> {
+        self.send(RetireKeyCall { key }, signer).await
+    }
+
+    /// 以 signer 的身份换用新密钥, 同一交易内停用 signer
+    pub async fn rotate_key(&self, new_key: Address, signer: &Signer) -> anyhow::Result<H256> {
+        self.send(RotateKeyCall { new_key }, signer).await
+    }
+
+    pub async fn get_issuer(&self, issuer_id: H256) -> anyhow::Result<RegisteredIssuer> {
+        let (name, code, valid_from, valid_until): (String, String, U256, U256) =
+            query(&self.contract, GetIssuerCall { id: issuer_id }, None).await?;
+        Ok(RegisteredIssuer {
+            id: issuer_id,
+            name,
+            code,
+            valid_from: valid_from.low_u64(),
+            valid_until: valid_until.low_u64(),
+        })
+    }
+
+    pub async fn get_key(&self, key: Address) -> anyhow::Result<RegisteredKey> {
+        let (issuer_id, added_at, retired_at): (H256, U256, U256) =
+            query(&self.contract, GetKeyCall { key }, None).await?;
+        Ok(RegisteredKey {
+            issuer_id,
+            added_at: added_at.low_u64(),
+            retired_at: retired_at.low_u64(),
+        })
+    }
+
+    /// key 在 timestamp 时刻能否代表其学校锚定, 同时返回学校的 issuerId
+    pub async fn is_authorized(
+        &self,
+        key: Address,
+        timestamp: u64,
+    ) -> anyhow::Result<(bool, H256)> {
+        let call = IsAuthorizedCall {
+            key,
+            timestamp: timestamp.into(),
+        };
+        query(&self.contract, call, None).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::deployment::decode_bytecode;
+    use crate::services::ethereum::{CONTRACT_RUNTIME_BYTECODE, ISSUER_REGISTRY_RUNTIME_BYTECODE};
+    use snark_verifier_sdk::snark_verifier::util::arithmetic::Group;
+
+    fn abi() -> web3::ethabi::Contract {
+        web3::ethabi::Contract::load(CONTRACT_ABI.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_verify_data_matches_abi() {
+        let function = abi().function(VerifyDataCall::NAME).unwrap().clone();
+        assert_eq!(
+            function.signature(),
+            "verifyData(bytes,bytes,bytes,bytes,bytes,bytes,bool):(bool)"
+        );
+        assert_eq!(
+            function.short_signature(),
+            Keccak256::digest(b"verifyData(bytes,bytes,bytes,bytes,bytes,bytes,bool)")[..4]
+        );
+
+        // 两张证书: 第一张展示两个学历类型, 第二张一个
+        let g1 = G1::generator();
+        let opening = |point: u64, n: u64| Opening {
+            point: Fr::from(point),
+            commitments: (1..=n).map(|i| g1 * Fr::from(i)).collect(),
+            proofs: (1..=n).map(|i| g1 * Fr::from(10 + i)).collect(),
+            values: (1..=n).map(|i| Fr::from(100 + i)).collect(),
+        };
+        let openings = [opening(7, 2), opening(9, 1)];
+        let call = VerifyDataCall::from_openings(&openings, Vec::new());
+        assert!(!call.is_zk);
+        assert!(VerifyDataCall::from_openings(&openings, vec![1; 32]).is_zk);
+
+        // 求值为 32 字节大端, 承诺为 x || y || 32 字节 0
+        assert_eq!(call.json_data.len(), 3 * 32);
+        assert_eq!(
+            U256::from_big_endian(&call.json_data[32..64]),
+            U256::from(102)
+        );
+        assert_eq!(call.commitment.len(), 3 * 96);
+        assert_eq!(call.commitment[..64], g1_to_evm(&g1));
+        assert_eq!(call.commitment[64..96], [0; 32]);
+
+        // proof 为 abi.encode(G1Point[]), index 为 abi.encode(uint256[], uint256[])
+        let point = ParamType::Tuple(vec![ParamType::Uint(256), ParamType::Uint(256)]);
+        let proofs = web3::ethabi::decode(&[ParamType::Array(Box::new(point))], &call.proof)
+            .unwrap()
+            .pop()
+            .and_then(Token::into_array)
+            .unwrap();
+        assert_eq!(proofs.len(), 3);
+        let proof = g1_to_evm(&(g1 * Fr::from(11)));
+        assert_eq!(
+            proofs[0],
+            Token::Tuple(vec![
+                Token::Uint(U256::from_big_endian(&proof[..32])),
+                Token::Uint(U256::from_big_endian(&proof[32..])),
+            ])
+        );
+        let uints = ParamType::Array(Box::new(ParamType::Uint(256)));
+        let index = web3::ethabi::decode(&[uints.clone(), uints], &call.index).unwrap();
+        assert_eq!(
+            index,
+            vec![
+                Token::Array(vec![Token::Uint(7.into()), Token::Uint(9.into())]),
+                Token::Array(vec![Token::Uint(2.into()), Token::Uint(1.into())]),
+            ]
+        );
+
+        // 编码后再按 ABI 解码, 字段顺序保持不变
+        let tokens = call.clone().into_tokens();
+        let kinds = function
+            .inputs
+            .iter()
+            .map(|param| param.kind.clone())
+            .collect::<Vec<_>>();
+        assert!(Token::types_check(&tokens, &kinds));
+        let data = function.encode_input(&tokens).unwrap();
+        assert_eq!(web3::ethabi::decode(&kinds, &data[4..]).unwrap(), tokens);
+        assert_eq!(CertificateVerifier::encode_verify_data(call), data);
+
+        let mut output = [0u8; 32];
+        output[31] = 1;
+        assert!(CertificateVerifier::decode_verify_data(&output).unwrap());
+    }
+
+    // 生成的调用与 ABI 中的函数签名、选择器与参数编码一致
+    fn assert_call_matches_abi<C: ContractCall + Default>(abi: &web3::ethabi::Contract) {
+        let function = abi.function(C::NAME).unwrap();
+        assert!(function.signature().starts_with(C::SIGNATURE));
+        assert_eq!(function.short_signature(), C::SELECTOR);
+        assert_eq!(Keccak256::digest(C::SIGNATURE)[..4], C::SELECTOR);
+
+        let tokens = C::default().into_tokens();
+        let kinds = function
+            .inputs
+            .iter()
+            .map(|param| param.kind.clone())
+            .collect::<Vec<_>>();
+        assert!(Token::types_check(&tokens, &kinds), "{}", C::SIGNATURE);
+        assert_eq!(
+            C::default().encode(),
+            function.encode_input(&tokens).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_generated_calls_match_abi() {
+        let abi = abi();
+        assert_call_matches_abi::<VerifyDataCall>(&abi);
+        assert_call_matches_abi::<AttestVerificationCall>(&abi);
+        assert_call_matches_abi::<AnchorBatchCall>(&abi);
+        assert_call_matches_abi::<UpdateBatchCall>(&abi);
+        assert_call_matches_abi::<GetBatchCall>(&abi);
+        assert_call_matches_abi::<RevokeCertificateCall>(&abi);
+        assert_call_matches_abi::<GetRevocationCall>(&abi);
+        assert_call_matches_abi::<SupersedeCertificateCall>(&abi);
+        assert_call_matches_abi::<GetSupersessionCall>(&abi);
+        assert_call_matches_abi::<RevocationRootCall>(&abi);
+        assert_call_matches_abi::<UpdateRevocationRootCall>(&abi);
+        assert_call_matches_abi::<GetHashCall>(&abi);
+        assert_call_matches_abi::<Halo2VerifierCall>(&abi);
+        assert_call_matches_abi::<ShplonkVerifierCall>(&abi);
+
+        let abi = web3::ethabi::Contract::load(ISSUER_REGISTRY_ABI.as_bytes()).unwrap();
+        assert_call_matches_abi::<AuthorityCall>(&abi);
+        assert_call_matches_abi::<RegisterIssuerCall>(&abi);
+        assert_call_matches_abi::<SetValidityCall>(&abi);
+        assert_call_matches_abi::<AddKeyCall>(&abi);
+        assert_call_matches_abi::<RetireKeyCall>(&abi);
+        assert_call_matches_abi::<RotateKeyCall>(&abi);
+        assert_call_matches_abi::<GetIssuerCall>(&abi);
+        assert_call_matches_abi::<GetKeyCall>(&abi);
+        assert_call_matches_abi::<IsAuthorizedCall>(&abi);
+    }
+
+    #[test]
+    fn test_constructor_and_getters_match_abi() {
+        let abi = abi();
+        let constructor = abi.constructor().unwrap();
+        assert_eq!(
+            constructor
+                .inputs
+                .iter()
+                .map(|param| param.kind.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                ParamType::Address,
+                ParamType::Address,
+                ParamType::FixedBytes(32)
+            ]
+        );
+
+        for name in [Halo2VerifierCall::NAME, ShplonkVerifierCall::NAME] {
+            let function = abi.function(name).unwrap();
+            assert!(function.inputs.is_empty());
+            assert_eq!(function.outputs[0].kind, ParamType::Address);
+        }
+
+        let get_hash = abi.function(GetHashCall::NAME).unwrap();
+        assert_eq!(get_hash.signature(), "getHash(bytes):(bytes32)");
+    }
+
+    #[test]
+    fn test_batch_anchoring_matches_abi() {
+        let abi = abi();
+        assert_eq!(
+            abi.function(AnchorBatchCall::NAME).unwrap().signature(),
+            "anchorBatch(bytes32,bytes32,bytes,uint256)"
+        );
+        assert_eq!(
+            abi.function(GetBatchCall::NAME).unwrap().signature(),
+            "getBatch(address,bytes32):(bytes32,bytes,uint256)"
+        );
+
+        let event = abi.event(CertificateVerifier::BATCH_ANCHORED).unwrap();
+        assert_eq!(
+            event.signature().as_bytes(),
+            &Keccak256::digest(b"BatchAnchored(address,bytes32,bytes32,bytes)")[..]
+        );
+        assert_eq!(
+            abi.function(UpdateBatchCall::NAME).unwrap().signature(),
+            "updateBatch(bytes32,bytes32,bytes32,bytes,bytes,uint256)"
+        );
+        assert_eq!(
+            abi.event(CertificateVerifier::BATCH_UPDATED)
+                .unwrap()
+                .signature()
+                .as_bytes(),
+            &Keccak256::digest(b"BatchUpdated(address,bytes32,bytes32,bytes)")[..]
+        );
+
+        // 构造一条事件日志并解析
+        let issuer = Address::from_low_u64_be(0x1234);
+        let batch_id = H256::repeat_byte(1);
+        let schema_hash = H256::repeat_byte(2);
+        let contract = Address::from_low_u64_be(0xc0ffee);
+        let log = Log {
+            address: contract,
+            topics: vec![event.signature(), H256::from(issuer), batch_id],
+            data: encode(&[
+                Token::FixedBytes(schema_hash.as_bytes().to_vec()),
+                Token::Bytes(vec![7; 96]),
+            ])
+            .into(),
+            block_hash: None,
+            block_number: None,
+            transaction_hash: None,
+            transaction_index: None,
+            log_index: None,
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        };
+
+        let transport = Http::new("http://127.0.0.1:8545").unwrap();
+        let verifier =
+            CertificateVerifier::new(web3::Web3::new(transport).eth(), contract).unwrap();
+        assert_eq!(
+            verifier.parse_batch_anchored(std::slice::from_ref(&log)),
+            Some(BatchAnchored {
+                issuer,
+                batch_id,
+                schema_hash
+            })
+        );
+
+        let other = Log {
+            address: Address::zero(),
+            ..log
+        };
+        assert_eq!(verifier.parse_batch_anchored(&[other]), None);
+    }
+
+    #[test]
+    fn test_revocation_matches_abi() {
+        let abi = abi();
+        assert_eq!(
+            abi.function(RevokeCertificateCall::NAME)
+                .unwrap()
+                .signature(),
+            "revokeCertificate(bytes32,uint256,uint8)"
+        );
+        assert_eq!(
+            abi.function(GetRevocationCall::NAME).unwrap().signature(),
+            "getRevocation(address,bytes32,uint256):(bool,uint8)"
+        );
+        assert_eq!(
+            abi.event("CertificateRevoked")
+                .unwrap()
+                .signature()
+                .as_bytes(),
+            &Keccak256::digest(b"CertificateRevoked(address,bytes32,uint256,uint8)")[..]
+        );
+        assert_eq!(
+            abi.function(SupersedeCertificateCall::NAME)
+                .unwrap()
+                .signature(),
+            "supersedeCertificate(bytes32,uint256,bytes32)"
+        );
+        assert_eq!(
+            abi.function(GetSupersessionCall::NAME).unwrap().signature(),
+            "getSupersession(address,bytes32,uint256):(bytes32)"
+        );
+        assert_eq!(
+            abi.event("CertificateSuperseded")
+                .unwrap()
+                .signature()
+                .as_bytes(),
+            &Keccak256::digest(b"CertificateSuperseded(address,bytes32,uint256,bytes32)")[..]
+        );
+        assert_eq!(
+            abi.function(RevocationRootCall::NAME).unwrap().signature(),
+            "revocationRoot():(bytes32)"
+        );
+        assert_eq!(
+            abi.function(UpdateRevocationRootCall::NAME)
+                .unwrap()
+                .signature(),
+            "updateRevocationRoot(bytes32)"
+        );
+    }
+
+    #[test]
+    fn test_verification_attestation_matches_abi() {
+        let abi = abi();
+        let function = abi.function(AttestVerificationCall::NAME).unwrap();
+        assert_eq!(
+            function.signature(),
+            "attestVerification(bytes,bytes,bytes,bytes,bytes,bytes,bool):(bool)"
+        );
+        // 与 verifyData 使用同一组参数
+        assert_eq!(
+            function.inputs,
+            abi.function(VerifyDataCall::NAME).unwrap().inputs
+        );
+
+        let event = abi
+            .event(CertificateVerifier::VERIFICATION_ATTESTED)
+            .unwrap();
+        assert_eq!(
+            event.signature().as_bytes(),
+            &Keccak256::digest(b"VerificationAttested(address,bytes32,bool)")[..]
+        );
+
+        let verifier_address = Address::from_low_u64_be(0xbeef);
+        let request_hash = H256::repeat_byte(3);
+        let contract = Address::from_low_u64_be(0xc0ffee);
+        let log = Log {
+            address: contract,
+            topics: vec![
+                event.signature(),
+                H256::from(verifier_address),
+                request_hash,
+            ],
+            data: encode(&[Token::Bool(true)]).into(),
+            block_hash: None,
+            block_number: None,
+            transaction_hash: None,
+            transaction_index: None,
+            log_index: None,
+            transaction_log_index: None,
+            log_type: None,
+            removed: None,
+        };
+
+        let transport = Http::new("http://127.0.0.1:8545").unwrap();
+        let verifier =
+            CertificateVerifier::new(web3::Web3::new(transport).eth(), contract).unwrap();
+        assert_eq!(
+            verifier.parse_verification_attested(std::slice::from_ref(&log)),
+            Some(VerificationAttested {
+                verifier: verifier_address,
+                request_hash,
+                verified: true,
+            })
+        );
+        assert_eq!(verifier.parse_batch_anchored(&[log]), None);
+    }
+
+    // solc 的函数分发以 PUSHn 载入选择器, 选择器的前导零字节被省略
+    fn dispatches(code: &[u8], selector: [u8; 4]) -> bool {
+        let selector = selector
+            .iter()
+            .skip_while(|byte| **byte == 0)
+            .copied()
+            .collect::<Vec<_>>();
+        let push = [&[0x5f + selector.len() as u8][..], &selector].concat();
+        code.windows(push.len()).any(|window| window == push)
+    }
+
+    #[test]
+    fn test_bytecode_dispatches_abi_selectors() {
+        for (abi, runtime) in [
+            (CONTRACT_ABI, CONTRACT_RUNTIME_BYTECODE),
+            (ISSUER_REGISTRY_ABI, ISSUER_REGISTRY_RUNTIME_BYTECODE),
+        ] {
+            let abi = web3::ethabi::Contract::load(abi.as_bytes()).unwrap();
+            let code = decode_bytecode(runtime).unwrap();
+            for function in abi.functions() {
+                assert!(
+                    dispatches(&code, function.short_signature()),
+                    "{} is not in the compiled bytecode",
+                    function.signature()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_issuer_registry_matches_abi() {
+        let abi = web3::ethabi::Contract::load(ISSUER_REGISTRY_ABI.as_bytes()).unwrap();
+        for (name, signature) in [
+            (
+                RegisterIssuerCall::NAME,
+                "registerIssuer(string,string,address[],uint64,uint64):(bytes32)",
+            ),
+            (SetValidityCall::NAME, "setValidity(bytes32,uint64,uint64)"),
+            (AddKeyCall::NAME, "addKey(bytes32,address)"),
+            (RetireKeyCall::NAME, "retireKey(address)"),
+            (RotateKeyCall::NAME, "rotateKey(address)"),
+            (
+                GetIssuerCall::NAME,
+                "getIssuer(bytes32):(string,string,uint64,uint64)",
+            ),
+            (GetKeyCall::NAME, "getKey(address):(bytes32,uint64,uint64)"),
+            (
+                IsAuthorizedCall::NAME,
+                "isAuthorized(address,uint64):(bool,bytes32)",
+            ),
+            (AuthorityCall::NAME, "authority():(address)"),
+        ] {
+            assert_eq!(abi.function(name).unwrap().signature(), signature);
+        }
+
+        // 登记参数按 ABI 编码
+        let function = abi.function(RegisterIssuerCall::NAME).unwrap();
+        let tokens = (
+            "Example University".to_string(),
+            "10001".to_string(),
+            vec![Address::from_low_u64_be(1)],
+            Token::Uint(0.into()),
+            Token::Uint(0.into()),
+        )
+            .into_tokens();
+        assert!(function.encode_input(&tokens).is_ok());
+
+        assert_eq!(
+            IssuerRegistry::issuer_id("10001").as_bytes(),
+            &Keccak256::digest(b"10001")[..]
+        );
+    }
+}
@@ -0,0 +1,418 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use indexmap::IndexMap;
+use log::{debug, warn};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::services::config::env_or;
+
+const DEFAULT_MEMORY_MAX_BYTES: usize = 64 << 20;
+const DEFAULT_DISK_MAX_BYTES: usize = 1 << 30;
+// ipfs add 默认的分块大小与 balanced 布局中每个节点的最大链接数
+const UNIXFS_CHUNK_SIZE: usize = 256 << 10;
+const UNIXFS_MAX_LINKS: usize = 174;
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// 按字节数限制大小的LRU, 队首为最久未使用的条目
+struct Lru<V> {
+    entries: IndexMap<String, (V, usize)>,
+    max_bytes: usize,
+    bytes: usize,
+}
+
+impl<V> Lru<V> {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            entries: IndexMap::new(),
+            max_bytes,
+            bytes: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<&V> {
+        let idx = self.entries.get_index_of(key)?;
+        let last = self.entries.len() - 1;
+        self.entries.move_index(idx, last);
+        self.entries.get_index(last).map(|(_, (v, _))| v)
+    }
+
+    fn remove(&mut self, key: &str) -> bool {
+        match self.entries.shift_remove(key) {
+            Some((_, size)) => {
+                self.bytes -= size;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 插入条目并返回被淘汰的键
+    fn insert(&mut self, key: String, value: V, size: usize) -> Vec<String> {
+        if size > self.max_bytes {
+            return Vec::new();
+        }
+        self.remove(&key);
+
+        let mut evicted = Vec::new();
+        while self.bytes + size > self.max_bytes {
+            match self.entries.shift_remove_index(0) {
+                Some((k, (_, s))) => {
+                    self.bytes -= s;
+                    evicted.push(k);
+                }
+                None => break,
+            }
+        }
+
+        self.bytes += size;
+        self.entries.insert(key, (value, size));
+        evicted
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CacheMetrics {
+    pub memory_hits: u64,
+    pub disk_hits: u64,
+    pub misses: u64,
+    pub memory_entries: usize,
+    pub memory_bytes: usize,
+    pub disk_entries: usize,
+    pub disk_bytes: usize,
+}
+
+/// 以CID为键的IPFS内容缓存. CID内容不可变, 因此缓存无需失效
+pub struct ContentCache {
+    memory: Mutex<Lru<Arc<Vec<u8>>>>,
+    disk: Option<(PathBuf, Mutex<Lru<()>>)>,
+    memory_hits: AtomicU64,
+    disk_hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ContentCache {
+    pub fn new(memory_max_bytes: usize, disk: Option<(PathBuf, usize)>) -> Self {
+        let disk = disk.map(|(dir, max_bytes)| {
+            let mut lru = Lru::new(max_bytes);
+            if let Err(e) = std::fs::create_dir_all(&dir) {
+                warn!("无法创建缓存目录 {dir:?}: {e}");
+            }
+            // 载入已有的磁盘缓存条目
+            if let Ok(read_dir) = std::fs::read_dir(&dir) {
+                for entry in read_dir.flatten() {
+                    let (Some(cid), Ok(meta)) = (
+                        entry.file_name().to_str().map(str::to_string),
+                        entry.metadata(),
+                    ) else {
+                        continue;
+                    };
+                    if is_valid_cid(&cid) {
+                        for evicted in lru.insert(cid, (), meta.len() as usize) {
+                            let _ = std::fs::remove_file(dir.join(evicted));
+                        }
+                    }
+                }
+            }
+            (dir, Mutex::new(lru))
+        });
+
+        Self {
+            memory: Mutex::new(Lru::new(memory_max_bytes)),
+            disk,
+            memory_hits: AtomicU64::new(0),
+            disk_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let memory_max_bytes = env_or("IPFS_CACHE_MAX_BYTES", DEFAULT_MEMORY_MAX_BYTES);
+        let disk = std::env::var("IPFS_CACHE_DIR").ok().map(|dir| {
+            (
+                PathBuf::from(dir),
+                env_or("IPFS_CACHE_DISK_MAX_BYTES", DEFAULT_DISK_MAX_BYTES),
+            )
+        });
+
+        Self::new(memory_max_bytes, disk)
+    }
+
+    pub fn get(&self, cid: &str) -> Option<Arc<Vec<u8>>> {
+        if let Some(data) = self.memory.lock().unwrap().get(cid) {
+            self.memory_hits.fetch_add(1, Ordering::Relaxed);
+            debug!("缓存命中(内存), CID: {cid}");
+            return Some(data.clone());
+        }
+
+        if let Some((dir, lru)) = &self.disk {
+            if is_valid_cid(cid) && lru.lock().unwrap().get(cid).is_some() {
+                match std::fs::read(dir.join(cid)) {
+                    Ok(data) if verify_cid(cid, &data) => {
+                        self.disk_hits.fetch_add(1, Ordering::Relaxed);
+                        debug!("缓存命中(磁盘), CID: {cid}");
+                        let data = Arc::new(data);
+                        self.insert_memory(cid, data.clone());
+                        return Some(data);
+                    }
+                    Ok(_) => {
+                        // 磁盘文件可被篡改, 与CID不符的内容丢弃后回源
+                        warn!("磁盘缓存内容与CID不符, 已删除: {cid}");
+                        lru.lock().unwrap().remove(cid);
+                        let _ = std::fs::remove_file(dir.join(cid));
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    pub fn insert(&self, cid: &str, data: Arc<Vec<u8>>) {
+        if let Some((dir, lru)) = &self.disk {
+            if is_valid_cid(cid) && verify_cid(cid, &data) {
+                match std::fs::write(dir.join(cid), data.as_slice()) {
+                    Ok(_) => {
+                        for evicted in lru.lock().unwrap().insert(cid.to_string(), (), data.len()) {
+                            let _ = std::fs::remove_file(dir.join(evicted));
+                        }
+                    }
+                    Err(e) => warn!("写入磁盘缓存失败 {cid}: {e}"),
+                }
+            }
+        }
+
+        self.insert_memory(cid, data);
+    }
+
+    /// 删除缓存内容, 用于数据主体删除请求
+    pub fn remove(&self, cid: &str) {
+        self.memory.lock().unwrap().remove(cid);
+        if let Some((dir, lru)) = &self.disk {
+            if is_valid_cid(cid) && lru.lock().unwrap().remove(cid) {
+                let _ = std::fs::remove_file(dir.join(cid));
+            }
+        }
+    }
+
+    fn insert_memory(&self, cid: &str, data: Arc<Vec<u8>>) {
+        let size = data.len();
+        self.memory
+            .lock()
+            .unwrap()
+            .insert(cid.to_string(), data, size);
+    }
+
+    pub fn metrics(&self) -> CacheMetrics {
+        let memory = self.memory.lock().unwrap();
+        let (disk_entries, disk_bytes) = self
+            .disk
+            .as_ref()
+            .map(|(_, lru)| {
+                let lru = lru.lock().unwrap();
+                (lru.entries.len(), lru.bytes)
+            })
+            .unwrap_or_default();
+
+        CacheMetrics {
+            memory_hits: self.memory_hits.load(Ordering::Relaxed),
+            disk_hits: self.disk_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            memory_entries: memory.entries.len(),
+            memory_bytes: memory.bytes,
+            disk_entries,
+            disk_bytes,
+        }
+    }
+}
+
+// CID 用作磁盘文件名, 只允许 base32/base58 字符
+pub fn is_valid_cid(cid: &str) -> bool {
+    !cid.is_empty() && cid.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+fn base58_decode(value: &str) -> Option<Vec<u8>> {
+    // 小端累加, 最后反转
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in value.bytes() {
+        let mut carry = BASE58_ALPHABET.iter().position(|a| *a == c)? as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    bytes.extend(value.bytes().take_while(|c| *c == b'1').map(|_| 0));
+    bytes.reverse();
+    Some(bytes)
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_bytes(out: &mut Vec<u8>, field: u8, bytes: &[u8]) {
+    out.push(field << 3 | 2);
+    put_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// UnixFS DAG 中的一个 dag-pb 块
+struct DagBlock {
+    // sha2-256 multihash
+    hash: Vec<u8>,
+    file_size: u64,
+    // 块及其全部子块的编码长度
+    tsize: u64,
+}
+
+impl DagBlock {
+    // PBNode: Links(2) 在 Data(1) 之前; Data 为 UnixFS File(2)
+    fn encode(links: &[DagBlock], data: Option<&[u8]>, file_size: u64) -> Self {
+        let mut unixfs = vec![1 << 3, 2];
+        if let Some(data) = data.filter(|data| !data.is_empty()) {
+            put_bytes(&mut unixfs, 2, data);
+        }
+        unixfs.push(3 << 3);
+        put_varint(&mut unixfs, file_size);
+        for link in links {
+            unixfs.push(4 << 3);
+            put_varint(&mut unixfs, link.file_size);
+        }
+
+        let mut node = Vec::new();
+        for link in links {
+            let mut encoded = Vec::new();
+            put_bytes(&mut encoded, 1, &link.hash);
+            // go-ipfs 总是写入空的链接名
+            put_bytes(&mut encoded, 2, &[]);
+            encoded.push(3 << 3);
+            put_varint(&mut encoded, link.tsize);
+            put_bytes(&mut node, 2, &encoded);
+        }
+        put_bytes(&mut node, 1, &unixfs);
+
+        let mut hash = vec![0x12, 0x20];
+        hash.extend_from_slice(&Sha256::digest(&node));
+        Self {
+            hash,
+            file_size,
+            tsize: node.len() as u64 + links.iter().map(|link| link.tsize).sum::<u64>(),
+        }
+    }
+
+    // balanced 布局: 深度为 depth 的节点依次填满各子树
+    fn build(chunks: &[&[u8]], depth: u32) -> Self {
+        if depth == 0 {
+            return Self::encode(&[], Some(chunks[0]), chunks[0].len() as u64);
+        }
+        let links = chunks
+            .chunks(UNIXFS_MAX_LINKS.pow(depth - 1))
+            .map(|chunks| Self::build(chunks, depth - 1))
+            .collect::<Vec<_>>();
+        let file_size = links.iter().map(|link| link.file_size).sum();
+        Self::encode(&links, None, file_size)
+    }
+}
+
+/// 按 ipfs add 的默认参数(CIDv0, 256KiB 分块, balanced 布局)重建 DAG, 校验内容与CID一致.
+/// 其他格式的CID无法由内容重算, 视为不一致
+pub fn verify_cid(cid: &str, data: &[u8]) -> bool {
+    if cid.len() != 46 || !cid.starts_with("Qm") {
+        return false;
+    }
+    let Some(multihash) = base58_decode(cid) else {
+        return false;
+    };
+    let chunks = match data.is_empty() {
+        true => vec![data],
+        false => data.chunks(UNIXFS_CHUNK_SIZE).collect::<Vec<_>>(),
+    };
+    let mut depth = 0;
+    while UNIXFS_MAX_LINKS.pow(depth) < chunks.len() {
+        depth += 1;
+    }
+    DagBlock::build(&chunks, depth).hash == multihash
+}
+
+lazy_static::lazy_static! {
+    pub static ref CONTENT_CACHE: ContentCache = ContentCache::from_env();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ipfs add 空文件与 "hello world\n" 得到的CID
+    const EMPTY_CID: &str = "QmbFMke1KXqnYyBBWxB74N4c5SBnJMVAiMNRcGu6x1AwQH";
+    const HELLO_CID: &str = "QmT78zSuBmuS4z925WZfrqQ1qHaJ56DQaTfyMUF7F8ff5o";
+
+    #[test]
+    fn test_verify_cid() {
+        assert!(verify_cid(EMPTY_CID, b""));
+        assert!(verify_cid(HELLO_CID, b"hello world\n"));
+        assert!(!verify_cid(HELLO_CID, b"hello world"));
+        assert!(!verify_cid("QmA", b""));
+    }
+
+    #[test]
+    fn test_memory_lru_eviction() {
+        let cache = ContentCache::new(10, None);
+
+        cache.insert("a", Arc::new(vec![0; 4]));
+        cache.insert("b", Arc::new(vec![1; 4]));
+        // 访问 a, 使 b 成为最久未使用
+        assert!(cache.get("a").is_some());
+        cache.insert("c", Arc::new(vec![2; 4]));
+
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("a").unwrap().as_slice(), &[0; 4]);
+        assert_eq!(cache.get("c").unwrap().as_slice(), &[2; 4]);
+
+        let metrics = cache.metrics();
+        assert_eq!(metrics.memory_hits, 3);
+        assert_eq!(metrics.misses, 1);
+        assert_eq!(metrics.memory_entries, 2);
+        assert_eq!(metrics.memory_bytes, 8);
+    }
+
+    #[test]
+    fn test_disk_tier() {
+        let dir = std::env::temp_dir().join(format!("edu-verify-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let cache = ContentCache::new(4, Some((dir.clone(), 1024)));
+        cache.insert(HELLO_CID, Arc::new(b"hello world\n".to_vec()));
+        // 与CID不符的内容不写入磁盘
+        cache.insert(EMPTY_CID, Arc::new(vec![7; 8]));
+        assert!(!dir.join(EMPTY_CID).exists());
+
+        // 超过内存上限, 只能从磁盘读取
+        assert_eq!(cache.get(HELLO_CID).unwrap().as_slice(), b"hello world\n");
+        assert_eq!(cache.metrics().disk_hits, 1);
+
+        // 重启后仍能命中磁盘缓存
+        let cache = ContentCache::new(4, Some((dir.clone(), 1024)));
+        assert!(cache.get(HELLO_CID).is_some());
+        assert!(cache.get("../etc").is_none());
+
+        // 被篡改的磁盘文件不再返回
+        std::fs::write(dir.join(HELLO_CID), b"hello world!").unwrap();
+        assert!(cache.get(HELLO_CID).is_none());
+        assert!(!dir.join(HELLO_CID).exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
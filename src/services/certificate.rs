@@ -4,21 +4,7 @@ use std::fs::File;
 use std::io::Write;
 use zip::{write::FileOptions, ZipWriter};
 
-pub async fn generate_certificate(
-    records: &[(String, String, String, String)],
-) -> anyhow::Result<String> {
-    let mut certificates = Vec::new();
-
-    for (id, origin_data_cid, proof, tx_hash) in records {
-        let certificate = Certificate {
-            id: id.clone(),
-            original_data_cid: origin_data_cid.clone(),
-            proof: proof.clone(),
-            tx_hash: tx_hash.clone(),
-        };
-        certificates.push(certificate);
-    }
-
+pub async fn generate_certificate(certificates: &[Certificate]) -> anyhow::Result<String> {
     // 创建一个ZIP文件来存储所有证书
     let timestamp_str = Local::now().format("%Y%m%d_%H%M%S").to_string();
     let zip_filename = format!("certificates_{timestamp_str}.zip");
@@ -9,11 +9,13 @@ use snark_verifier_sdk::{
         halo2_base::{
             gates::{
                 circuit::{builder::BaseCircuitBuilder, CircuitBuilderStage},
-                GateInstructions,
+                GateInstructions, RangeChip, RangeInstructions,
             },
             AssignedValue, Context,
+            QuantumCell::Constant,
         },
         halo2_ecc::{bn254::FpChip, fields::FieldChip},
+        loader::evm::encode_calldata,
     },
 };
 use snark_verifier_sdk::{
@@ -32,6 +34,7 @@ use crate::{
     handler::{decompse_edu_data, RANDOM},
     services::{
         poseidon::{poseidon, poseidon_chip},
+        revocation::{fr_from_u128, revocation_key, NonMembershipWitness, RevocationTree},
         util::compress_fr,
     },
 };
@@ -40,6 +43,14 @@ const MAX_N_EDU: usize = 2;
 const MAX_N_EDU_MSG: usize = 2;
 const MAX_N_EDU_MSG_DATA: usize = 10;
 
+/// 撤销累加器根在公开输入中的位置, 与 CertificateVerifier.sol 中的常量一致
+pub const REVOCATION_ROOT_INSTANCE: usize =
+    MAX_N_EDU * MAX_N_EDU_MSG * (MAX_N_EDU_MSG_DATA + 1) + 1 + MAX_N_EDU;
+
+// BN254 标量域模数的高、低 128 位
+const MODULUS_HI: u128 = 0x30644e72e131a029b85045b68181585d;
+const MODULUS_LO: u128 = 0x2833e84879b9709143e1f593f0000001;
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct CircuitParams {
     pub degree: u32,
@@ -52,13 +63,15 @@ pub struct CircuitParams {
 // t段学历
 // 每段学历k条信息
 // 每条信息a个公开数据项, b个非公开数据项
-// instance = [m_01, ..., m0k, ..., m_tk, ..., xi, M_0, ..., M_t]
+// 每条信息附带对撤销累加器的非成员证明, 不暴露是哪条记录
+// instance = [m_01, ..., m0k, ..., m_tk, ..., xi, M_0, ..., M_t, root]
 fn create_edu_auth_constraint(
     param: CircuitParams,
     stage: CircuitBuilderStage,
     break_points: Option<MultiPhaseThreadBreakPoints>,
     origin_data_vec_vec: &[Vec<BTreeMap<String, Value>>],
     selected_fields_vec_vec: &[Vec<Vec<String>>],
+    non_membership_vec_vec: &[Vec<NonMembershipWitness>],
 ) -> BaseCircuitBuilder<Fr> {
     let mut builder = BaseCircuitBuilder::<Fr>::from_stage(stage)
         .use_k(param.degree as usize)
@@ -79,28 +92,27 @@ fn create_edu_auth_constraint(
     let mut instances: Vec<AssignedValue<Fr>> = Vec::new();
 
     let mut compress_data_vec_vec = Vec::new();
-    for (origin_data_vec, selected_field_vec) in origin_data_vec_vec
+    let mut revocation_roots = Vec::new();
+    for ((origin_data_vec, selected_field_vec), non_membership_vec) in origin_data_vec_vec
         .iter()
         .zip(selected_fields_vec_vec.iter())
+        .zip(non_membership_vec_vec.iter())
     {
         let mut compress_data_vec = Vec::new();
-        for (origin_data, selected_fields) in origin_data_vec.iter().zip(selected_field_vec.iter())
+        for ((origin_data, selected_fields), non_membership) in origin_data_vec
+            .iter()
+            .zip(selected_field_vec.iter())
+            .zip(non_membership_vec.iter())
         {
-            let decompress_data = decompse_edu_data(origin_data);
+            let decompress_data = record_fields(origin_data);
 
             let assign_decompress_data: Vec<AssignedValue<Fr>> =
                 ctx.assign_witnesses(decompress_data.clone());
 
-            let label = origin_data
-                .keys()
-                .map(|key| {
-                    if selected_fields.contains(key) {
-                        ctx.load_witness(Fr::one())
-                    } else {
-                        ctx.load_witness(Fr::zero())
-                    }
-                })
-                .collect::<Vec<_>>();
+            let key = revocation_key_chip(ctx, &range, &assign_decompress_data);
+            revocation_roots.push(non_membership_chip(ctx, &range, key, non_membership));
+
+            let label = ctx.assign_witnesses(record_labels(origin_data, selected_fields));
 
             let instance = assign_decompress_data
                 .iter()
@@ -132,6 +144,13 @@ fn create_edu_auth_constraint(
         instances.push(compress_data);
     }
 
+    // 所有非成员证明须针对同一个根
+    let revocation_root = revocation_roots[0];
+    for root in &revocation_roots[1..] {
+        ctx.constrain_equal(root, &revocation_root);
+    }
+    instances.push(revocation_root);
+
     let assign_instance = &mut builder.assigned_instances;
     assign_instance[0].extend_from_slice(&instances);
 
@@ -156,6 +175,66 @@ fn compress_chip(
     compress_res
 }
 
+// 记录哈希的规范分解 hash = hi * 2^128 + lo, 低 128 位作为撤销键
+fn revocation_key_chip(
+    ctx: &mut Context<Fr>,
+    range: &RangeChip<Fr>,
+    data: &[AssignedValue<Fr>],
+) -> AssignedValue<Fr> {
+    let gate = range.gate();
+    let hash = poseidon_chip(ctx, data);
+
+    let bytes = hash.value().to_bytes();
+    let lo = ctx.load_witness(fr_from_u128(u128::from_le_bytes(
+        bytes[..16].try_into().unwrap(),
+    )));
+    let hi = ctx.load_witness(fr_from_u128(u128::from_le_bytes(
+        bytes[16..].try_into().unwrap(),
+    )));
+    range.range_check(ctx, lo, 128);
+    range.range_check(ctx, hi, 126);
+    let recompose = gate.mul_add(ctx, hi, Constant(Fr::from_raw([0, 0, 1, 0])), lo);
+    ctx.constrain_equal(&recompose, &hash);
+
+    // hi || lo < p, 否则 hash + p 也是一种分解, 证明方可以换用其他键
+    let hi_lt = range.is_less_than(ctx, hi, Constant(fr_from_u128(MODULUS_HI)), 126);
+    let hi_eq = gate.is_equal(ctx, hi, Constant(fr_from_u128(MODULUS_HI)));
+    let lo_lt = range.is_less_than(ctx, lo, Constant(fr_from_u128(MODULUS_LO)), 128);
+    let canonical = gate.mul_add(ctx, hi_eq, lo_lt, hi_lt);
+    gate.assert_is_const(ctx, &canonical, &Fr::one());
+
+    lo
+}
+
+// 证明 low < key < next 且叶子 (low, next) 在树中, 返回计算出的根
+fn non_membership_chip(
+    ctx: &mut Context<Fr>,
+    range: &RangeChip<Fr>,
+    key: AssignedValue<Fr>,
+    witness: &NonMembershipWitness,
+) -> AssignedValue<Fr> {
+    let gate = range.gate();
+
+    let low = ctx.load_witness(fr_from_u128(witness.low_key));
+    let next = ctx.load_witness(fr_from_u128(witness.next_key));
+    range.range_check(ctx, low, 128);
+    range.range_check(ctx, next, 128);
+    range.check_less_than(ctx, low, key, 128);
+    range.check_less_than(ctx, key, next, 128);
+
+    let mut node = poseidon_chip(ctx, &[low, next]);
+    for (depth, sibling) in witness.siblings.iter().enumerate() {
+        let bit = ctx.load_witness(Fr::from(((witness.leaf_index >> depth) & 1) as u64));
+        gate.assert_bit(ctx, bit);
+        let sibling = ctx.load_witness(*sibling);
+        let left = gate.select(ctx, sibling, node, bit);
+        let right = gate.select(ctx, node, sibling, bit);
+        node = poseidon_chip(ctx, &[left, right]);
+    }
+
+    node
+}
+
 pub struct CircuitProver {
     srs: ParamsKZG<Bn256>,
     pk: ProvingKey<G1Affine>,
@@ -166,6 +245,8 @@ pub struct CircuitProver {
 impl CircuitProver {
     pub fn new(circuit_param: CircuitParams, srs: ParamsKZG<Bn256>) -> Self {
         let (mock_edu_vec, mock_edu_zk_label_vec) = mock_edu_data();
+        let mock_non_membership_vec =
+            non_membership(&RevocationTree::default(), &mock_edu_vec).expect("empty accumulator");
 
         let circuit = create_edu_auth_constraint(
             circuit_param,
@@ -173,6 +254,7 @@ impl CircuitProver {
             None,
             &mock_edu_vec,
             &mock_edu_zk_label_vec,
+            &mock_non_membership_vec,
         );
 
         let pk = gen_pk(&srs, &circuit, None);
@@ -187,18 +269,22 @@ impl CircuitProver {
         }
     }
 
+    /// 生成证明, 返回公开输入和证明; 任一记录已撤销时失败
     pub fn gen_proof(
         &self,
         edu_vec: &[Vec<BTreeMap<String, Value>>],
         edu_label_vec: &[Vec<Vec<String>>],
-    ) -> Vec<u8> {
+        revocations: &RevocationTree,
+    ) -> anyhow::Result<(Vec<Fr>, Vec<u8>)> {
         let (edu_vec, edu_label_vec) = Self::padding(edu_vec, edu_label_vec);
+        let non_membership_vec = non_membership(revocations, &edu_vec)?;
         let circuit = create_edu_auth_constraint(
             self.circuit_param,
             CircuitBuilderStage::Prover,
             Some(self.break_points.clone()),
             &edu_vec,
             &edu_label_vec,
+            &non_membership_vec,
         );
 
         let instances: Vec<Fr> = circuit.assigned_instances[0]
@@ -206,7 +292,19 @@ impl CircuitProver {
             .map(|a| a.value.evaluate())
             .collect();
 
-        gen_evm_proof_shplonk(&self.srs, &self.pk, circuit, vec![instances])
+        let proof = gen_evm_proof_shplonk(&self.srs, &self.pk, circuit, vec![instances.clone()]);
+        Ok((instances, proof))
+    }
+
+    /// Halo2Verifier 的调用数据: 公开输入后接证明
+    pub fn gen_calldata(
+        &self,
+        edu_vec: &[Vec<BTreeMap<String, Value>>],
+        edu_label_vec: &[Vec<Vec<String>>],
+        revocations: &RevocationTree,
+    ) -> anyhow::Result<Vec<u8>> {
+        let (instances, proof) = self.gen_proof(edu_vec, edu_label_vec, revocations)?;
+        Ok(encode_calldata(&[instances], &proof))
     }
 
     fn padding(
@@ -221,19 +319,9 @@ impl CircuitProver {
             .collect();
         let padd_edu_vec = vec![padd_edu.clone(); MAX_N_EDU_MSG];
 
+        // 记录的字段由 record_fields 补零, 此处只补齐记录与证书
         for edu_vec in edu_vec.iter_mut() {
-            for edu in edu_vec.iter_mut() {
-                if edu.len() < MAX_N_EDU_MSG_DATA {
-                    debug!(
-                        "edu 长度不足，进行填充, edu_vec 长度: {}, 需要填充: {}",
-                        edu.len(),
-                        MAX_N_EDU_MSG_DATA - edu.len()
-                    );
-                    (0..MAX_N_EDU_MSG_DATA - edu.len()).for_each(|i| {
-                        edu.insert(format!("padding_{i}"), Value::String(String::from("1")));
-                    });
-                }
-            }
+            debug!("edu_vec 长度: {}, 补齐到 {MAX_N_EDU_MSG}", edu_vec.len());
             edu_vec.resize(MAX_N_EDU_MSG, padd_edu.clone());
         }
         edu_vec.resize(MAX_N_EDU, padd_edu_vec);
@@ -247,6 +335,47 @@ impl CircuitProver {
     }
 }
 
+/// 记录的字段值, 不足 MAX_N_EDU_MSG_DATA 项时在末尾补零.
+/// 末尾的零不改变压缩值, 电路证明的压缩值即学校承诺的求值
+pub fn record_fields(record: &BTreeMap<String, Value>) -> Vec<Fr> {
+    let mut fields = decompse_edu_data(record);
+    if fields.len() < MAX_N_EDU_MSG_DATA {
+        fields.resize(MAX_N_EDU_MSG_DATA, Fr::zero());
+    }
+    fields
+}
+
+// 各字段是否公开, 与 record_fields 一一对应, 补零的字段不公开
+fn record_labels(record: &BTreeMap<String, Value>, selected_fields: &[String]) -> Vec<Fr> {
+    let mut labels = record
+        .keys()
+        .map(|key| Fr::from(selected_fields.contains(key) as u64))
+        .collect::<Vec<_>>();
+    if labels.len() < MAX_N_EDU_MSG_DATA {
+        labels.resize(MAX_N_EDU_MSG_DATA, Fr::zero());
+    }
+    labels
+}
+
+fn non_membership(
+    revocations: &RevocationTree,
+    edu_vec: &[Vec<BTreeMap<String, Value>>],
+) -> anyhow::Result<Vec<Vec<NonMembershipWitness>>> {
+    edu_vec
+        .iter()
+        .map(|edu_vec| {
+            edu_vec
+                .iter()
+                .map(|edu| {
+                    revocations
+                        .non_membership(revocation_key(edu))
+                        .ok_or_else(|| anyhow::anyhow!("certificate has been revoked"))
+                })
+                .collect()
+        })
+        .collect()
+}
+
 #[test]
 fn test_edu_auth_circuit() {
     use snark_verifier_sdk::evm::evm_verify;
@@ -265,11 +394,16 @@ fn test_edu_auth_circuit() {
     let (mock_edu_vec, mock_edu_label_vec) = mock_edu_data();
 
     let prover = CircuitProver::new(circuit_param, srs);
-    let proof = prover.gen_proof(&mock_edu_vec, &mock_edu_label_vec);
+    let revocations = RevocationTree::default();
+    let (proven_instances, proof) = prover
+        .gen_proof(&mock_edu_vec, &mock_edu_label_vec, &revocations)
+        .unwrap();
 
     let verifier_params = prover.srs.verifier_params();
 
-    let instances = gen_instance(&mock_edu_vec, &mock_edu_label_vec);
+    let instances = gen_instance(&mock_edu_vec, &mock_edu_label_vec, revocations.root());
+    assert_eq!(instances, proven_instances);
+    assert_eq!(instances.len(), REVOCATION_ROOT_INSTANCE + 1);
     let num_instances = vec![instances.len()];
     let deployment_code = gen_evm_verifier_shplonk::<BaseCircuitBuilder<Fr>>(
         verifier_params,
@@ -279,6 +413,49 @@ fn test_edu_auth_circuit() {
     );
 
     evm_verify(deployment_code, vec![instances], proof);
+
+    // 撤销字段不足的记录后不能再为其生成证明, 撤销键与电路中按同样补零后的字段计算
+    let edu = BTreeMap::from([("学位".to_string(), Value::String("学士".to_string()))]);
+    let edu_vec = vec![vec![edu.clone()]];
+    let edu_label_vec = vec![vec![Vec::new()]];
+    assert!(prover
+        .gen_proof(&edu_vec, &edu_label_vec, &revocations)
+        .is_ok());
+
+    let mut revocations = RevocationTree::default();
+    revocations.revoke([&edu]).unwrap();
+    assert!(prover
+        .gen_proof(&edu_vec, &edu_label_vec, &revocations)
+        .is_err());
+}
+
+#[test]
+fn test_record_fields() {
+    use crate::handler::compress_edu_data;
+
+    // 字段不足的记录补零后, 电路中的压缩值与学校承诺的求值一致
+    let edu: BTreeMap<String, Value> = BTreeMap::from([
+        ("姓名".to_string(), Value::String("张三".to_string())),
+        ("学位".to_string(), Value::String("学士".to_string())),
+    ]);
+    let fields = record_fields(&edu);
+    assert_eq!(fields.len(), MAX_N_EDU_MSG_DATA);
+    assert_eq!(
+        compress_fr(&fields, *RANDOM),
+        compress_edu_data(&[edu.clone()], *RANDOM)[0]
+    );
+
+    let labels = record_labels(&edu, &["学位".to_string()]);
+    assert_eq!(labels.len(), MAX_N_EDU_MSG_DATA);
+    assert_eq!(
+        labels.iter().filter(|label| **label == Fr::one()).count(),
+        1
+    );
+
+    // 公开输入中第 c 张证书的第 r 条记录位于 c * MAX_N_EDU_MSG + r
+    let (edu_vec, label_vec) = CircuitProver::padding(&[vec![edu]], &[vec![Vec::new()]]);
+    let instances = gen_instance(&edu_vec, &label_vec, Fr::zero());
+    assert_eq!(instances[MAX_N_EDU_MSG_DATA], compress_fr(&fields, *RANDOM));
 }
 
 fn mock_edu_data() -> (Vec<Vec<BTreeMap<String, Value>>>, Vec<Vec<Vec<String>>>) {
@@ -296,6 +473,7 @@ fn mock_edu_data() -> (Vec<Vec<BTreeMap<String, Value>>>, Vec<Vec<Vec<String>>>)
 pub fn gen_instance(
     origin_data_vec_vec: &[Vec<BTreeMap<String, Value>>],
     selected_fields_vec_vec: &[Vec<Vec<String>>],
+    revocation_root: Fr,
 ) -> Vec<Fr> {
     let mut instances = Vec::new();
 
@@ -307,18 +485,8 @@ pub fn gen_instance(
         let mut compress_data_vec = Vec::new();
         for (origin_data, selected_fields) in origin_data_vec.iter().zip(selected_field_vec.iter())
         {
-            let decompress_data = decompse_edu_data(origin_data);
-
-            let label = origin_data
-                .keys()
-                .map(|key| {
-                    if selected_fields.contains(key) {
-                        Fr::one()
-                    } else {
-                        Fr::zero()
-                    }
-                })
-                .collect::<Vec<_>>();
+            let decompress_data = record_fields(origin_data);
+            let label = record_labels(origin_data, selected_fields);
 
             let instance = decompress_data
                 .iter()
@@ -347,6 +515,7 @@ pub fn gen_instance(
         let compress_data = compress_fr(&compress_data_vec, xi);
         instances.push(compress_data);
     }
+    instances.push(revocation_root);
 
     instances
 }
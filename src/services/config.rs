@@ -0,0 +1,9 @@
+use std::str::FromStr;
+
+/// 读取环境变量, 未设置或无法解析时使用默认值
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,343 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Context};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use web3::ethabi::{encode, Token};
+use web3::transports::Http;
+use web3::types::Address;
+use web3::Web3;
+
+use crate::services::ethereum::{
+    deploy_bytecode, CONTRACT_BYTECODE, CONTRACT_RUNTIME_BYTECODE, HALO2_VERIFIER_BYTECODE,
+    SHPLONK_VERIFIER_BYTECODE,
+};
+use crate::services::revocation::{fr_to_h256, RevocationTree};
+use crate::services::shplonk::shplonk_verifier_params;
+use crate::services::wallet::Signer;
+
+// 部署记录文件, 按链ID保存各合约地址
+const DEFAULT_DEPLOYMENTS_PATH: &str = "deployments.json";
+pub const CERTIFICATE_VERIFIER: &str = "CertificateVerifier";
+pub const HALO2_VERIFIER: &str = "Halo2Verifier";
+pub const SHPLONK_VERIFIER: &str = "ShplonkVerifier";
+
+// CertificateVerifier 依赖的合约, 按部署顺序排列. 不再部署和链接 PoseidonT3 库:
+// verifyData 的批量打开挑战 xi 改由 keccak 计算, 合约不依赖任何库
+const DEPENDENCIES: [&str; 2] = [HALO2_VERIFIER, SHPLONK_VERIFIER];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeployedContract {
+    pub address: String,
+    pub code_hash: String,
+    pub bytecode_hash: String,
+    #[serde(default)]
+    pub tx_hash: String,
+    pub deployed_at: String,
+}
+
+// chain_id -> 合约名 -> 部署信息
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct Deployments(pub BTreeMap<String, BTreeMap<String, DeployedContract>>);
+
+impl Deployments {
+    fn path() -> String {
+        std::env::var("DEPLOYMENTS_PATH").unwrap_or_else(|_| DEFAULT_DEPLOYMENTS_PATH.to_string())
+    }
+
+    pub fn load() -> anyhow::Result<Self> {
+        match std::fs::read(Self::path()) {
+            Ok(bytes) => serde_json::from_slice(&bytes).context("invalid deployments file"),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        std::fs::write(Self::path(), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, chain_id: u64, name: &str) -> Option<&DeployedContract> {
+        self.0.get(&chain_id.to_string())?.get(name)
+    }
+
+    pub fn insert(&mut self, chain_id: u64, name: &str, contract: DeployedContract) {
+        self.0
+            .entry(chain_id.to_string())
+            .or_default()
+            .insert(name.to_string(), contract);
+    }
+}
+
+pub fn keccak_hex(data: &[u8]) -> String {
+    format!("0x{}", hex::encode(Keccak256::digest(data)))
+}
+
+pub fn decode_bytecode(bytecode: &str) -> anyhow::Result<Vec<u8>> {
+    if bytecode.contains("__$") {
+        bail!("contract bytecode has unlinked library placeholders");
+    }
+    hex::decode(bytecode.trim().trim_start_matches("0x")).context("invalid contract bytecode")
+}
+
+fn artifact_bytecode(name: &str) -> &'static str {
+    match name {
+        HALO2_VERIFIER => HALO2_VERIFIER_BYTECODE,
+        SHPLONK_VERIFIER => SHPLONK_VERIFIER_BYTECODE,
+        _ => CONTRACT_BYTECODE,
+    }
+}
+
+/// 编译产物的创建字节码 (不含构造参数), 部署记录中保存其哈希
+fn template_bytecode(name: &str) -> anyhow::Result<Vec<u8>> {
+    decode_bytecode(artifact_bytecode(name))
+}
+
+// 运行时代码及 solc 输出的 immutableReferences
+fn artifact_runtime(name: &str) -> (&'static str, &'static str) {
+    match name {
+        HALO2_VERIFIER => (
+            include_str!(concat!(env!("OUT_DIR"), "/Halo2Verifier.bin-runtime")),
+            include_str!(concat!(env!("OUT_DIR"), "/Halo2Verifier.immutables")),
+        ),
+        SHPLONK_VERIFIER => (
+            include_str!(concat!(env!("OUT_DIR"), "/ShplonkVerifier.bin-runtime")),
+            include_str!(concat!(env!("OUT_DIR"), "/ShplonkVerifier.immutables")),
+        ),
+        _ => (
+            CONTRACT_RUNTIME_BYTECODE,
+            include_str!(concat!(env!("OUT_DIR"), "/CertificateVerifier.immutables")),
+        ),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ImmutableReference {
+    start: usize,
+    length: usize,
+}
+
+/// immutableReferences `{astId: [{start, length}]}` 中的全部字节区间
+pub fn immutable_ranges(references: &str) -> anyhow::Result<Vec<std::ops::Range<usize>>> {
+    let references: BTreeMap<String, Vec<ImmutableReference>> =
+        serde_json::from_str(references).context("invalid immutableReferences")?;
+    Ok(references
+        .into_values()
+        .flatten()
+        .map(|reference| reference.start..reference.start + reference.length)
+        .collect())
+}
+
+/// 链上运行时代码是否由编译产物部署而来: 只有 immutable 变量所在的区间可以不同
+pub fn matches_runtime_code(
+    template: &[u8],
+    immutables: &[std::ops::Range<usize>],
+    runtime_code: &[u8],
+) -> bool {
+    if runtime_code.is_empty() || runtime_code.len() != template.len() {
+        return false;
+    }
+
+    template
+        .iter()
+        .zip(runtime_code)
+        .enumerate()
+        .all(|(i, (t, r))| t == r || immutables.iter().any(|range| range.contains(&i)))
+}
+
+/// 按编译产物比对运行时代码
+fn runtime_matches(name: &str, runtime_code: &[u8]) -> anyhow::Result<bool> {
+    let (runtime, immutables) = artifact_runtime(name);
+    Ok(matches_runtime_code(
+        &decode_bytecode(runtime)?,
+        &immutable_ranges(immutables)?,
+        runtime_code,
+    ))
+}
+
+async fn validate_deployment(
+    web3: &Web3<Http>,
+    name: &str,
+    address: Address,
+    record: Option<&DeployedContract>,
+) -> anyhow::Result<()> {
+    let code = web3.eth().code(address, None).await?.0;
+    if code.is_empty() {
+        bail!("no {name} code at {address:?}");
+    }
+
+    if let Some(record) = record {
+        if record.code_hash != keccak_hex(&code) {
+            bail!("{name} code at {address:?} does not match the deployment record");
+        }
+        if record.bytecode_hash != keccak_hex(&template_bytecode(name)?) {
+            bail!("{name} artifact changed since deployment, run `edu-verify deploy`");
+        }
+        return Ok(());
+    }
+
+    if !runtime_matches(name, &code)? {
+        bail!("{name} code at {address:?} does not match the compiled artifact");
+    }
+
+    Ok(())
+}
+
+/// 加载并校验已部署的合约地址: 优先使用配置的地址, 否则读取部署记录
+pub async fn load_contract_address(
+    web3: &Web3<Http>,
+    configured: Option<&str>,
+) -> anyhow::Result<String> {
+    let chain_id = web3.eth().chain_id().await?.as_u64();
+    let deployments = Deployments::load()?;
+    let record = deployments.get(chain_id, CERTIFICATE_VERIFIER);
+
+    let address = match configured {
+        Some(address) => {
+            info!("使用配置的合约地址: {address}");
+            address.to_string()
+        }
+        None => record.map(|record| record.address.clone()).ok_or_else(|| {
+            anyhow!("链 {chain_id} 上没有合约部署记录, 请先运行 `edu-verify deploy`")
+        })?,
+    };
+
+    let parsed: Address = address.parse().context("invalid contract address")?;
+    let record = record.filter(|record| record.address.eq_ignore_ascii_case(&address));
+    match record {
+        Some(_) => {
+            for name in DEPENDENCIES {
+                let dependency = deployments
+                    .get(chain_id, name)
+                    .ok_or_else(|| anyhow!("链 {chain_id} 上缺少 {name} 的部署记录"))?;
+                let dependency_address = dependency
+                    .address
+                    .parse()
+                    .context("invalid contract address")?;
+                validate_deployment(web3, name, dependency_address, Some(dependency)).await?;
+            }
+        }
+        None => warn!("合约地址 {address} 没有部署记录, 仅校验运行时代码"),
+    }
+    validate_deployment(web3, CERTIFICATE_VERIFIER, parsed, record).await?;
+    info!("合约校验通过, 链ID: {chain_id}, 地址: {address}");
+
+    Ok(address)
+}
+
+/// ShplonkVerifier 的创建字节码: 附加与 shplonk::verify 相同的 SRS 作为构造参数
+pub fn shplonk_verifier_code() -> anyhow::Result<Vec<u8>> {
+    let mut code = decode_bytecode(SHPLONK_VERIFIER_BYTECODE)?;
+    code.extend(shplonk_verifier_params());
+    Ok(code)
+}
+
+/// CertificateVerifier 的创建字节码: 附加构造参数
+/// `constructor(address _halo2Verifier, address _shplonkVerifier, bytes32 _revocationRoot)`,
+/// 撤销累加器根初始为空树的根, 之后由 `publish_revocation_root` 更新
+pub fn certificate_verifier_code(
+    halo2_verifier: Address,
+    shplonk_verifier: Address,
+) -> anyhow::Result<Vec<u8>> {
+    let mut code = decode_bytecode(CONTRACT_BYTECODE)?;
+    code.extend(encode(&[
+        Token::Address(halo2_verifier),
+        Token::Address(shplonk_verifier),
+        Token::FixedBytes(
+            fr_to_h256(RevocationTree::default().root())
+                .as_bytes()
+                .to_vec(),
+        ),
+    ]));
+    Ok(code)
+}
+
+async fn deploy_and_insert(
+    web3: &Web3<Http>,
+    signer: &Signer,
+    deployments: &mut Deployments,
+    chain_id: u64,
+    name: &str,
+    code: Vec<u8>,
+) -> anyhow::Result<Address> {
+    let (address, tx_hash) = deploy_bytecode(web3, signer, name, code).await?;
+    let runtime_code = web3.eth().code(address, None).await?.0;
+
+    deployments.insert(
+        chain_id,
+        name,
+        DeployedContract {
+            address: format!("{address:?}"),
+            code_hash: keccak_hex(&runtime_code),
+            bytecode_hash: keccak_hex(&template_bytecode(name)?),
+            tx_hash: format!("{tx_hash:?}"),
+            deployed_at: chrono::Local::now().to_rfc3339(),
+        },
+    );
+
+    Ok(address)
+}
+
+/// 管理命令: 依次部署 Halo2Verifier、Shplonk Verifier,
+/// 以两个验证器地址为构造参数部署 CertificateVerifier, 最后写入部署记录
+pub async fn deploy_and_record(web3: &Web3<Http>, signer: &Signer) -> anyhow::Result<String> {
+    let chain_id = web3.eth().chain_id().await?.as_u64();
+    let mut deployments = Deployments::load()?;
+
+    let halo2_verifier = deploy_and_insert(
+        web3,
+        signer,
+        &mut deployments,
+        chain_id,
+        HALO2_VERIFIER,
+        decode_bytecode(HALO2_VERIFIER_BYTECODE)?,
+    )
+    .await?;
+    let shplonk_verifier = deploy_and_insert(
+        web3,
+        signer,
+        &mut deployments,
+        chain_id,
+        SHPLONK_VERIFIER,
+        shplonk_verifier_code()?,
+    )
+    .await?;
+
+    let code = certificate_verifier_code(halo2_verifier, shplonk_verifier)?;
+    let address = deploy_and_insert(
+        web3,
+        signer,
+        &mut deployments,
+        chain_id,
+        CERTIFICATE_VERIFIER,
+        code,
+    )
+    .await?;
+
+    deployments.save()?;
+    let address = format!("{address:?}");
+    info!("部署记录已保存, 链ID: {chain_id}, 地址: {address}");
+
+    Ok(address)
+}
+
+#[test]
+fn test_matches_runtime_code() {
+    // 运行时模板中 [2, 6) 为 immutable 占位
+    let template = [0x60, 0x80, 0, 0, 0, 0, 0x56, 0x00];
+    let immutables = immutable_ranges(r#"{"12": [{"start": 2, "length": 4}]}"#).unwrap();
+    assert_eq!(immutables, vec![2..6]);
+
+    let runtime = [0x60, 0x80, 1, 2, 3, 4, 0x56, 0x00];
+    assert!(matches_runtime_code(&template, &immutables, &runtime));
+
+    let tampered = [0x60, 0x81, 1, 2, 3, 4, 0x56, 0x00];
+    assert!(!matches_runtime_code(&template, &immutables, &tampered));
+    // immutable 区间之外的0字节不是通配
+    let tampered = [0x60, 0x80, 1, 2, 3, 4, 0x56, 0x01];
+    assert!(!matches_runtime_code(&template, &immutables, &tampered));
+    assert!(!matches_runtime_code(&template, &[], &runtime));
+    assert!(!matches_runtime_code(&template, &immutables, &[]));
+    assert!(!matches_runtime_code(&template, &immutables, &runtime[..7]));
+}
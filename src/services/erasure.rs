@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use chrono::Local;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use web3::types::{Address, H256};
+
+// 数据主体删除登记文件路径
+const DEFAULT_ERASURE_REGISTRY_PATH: &str = "erasures/registry.json";
+
+/// 一条已执行的删除记录（PIPL/GDPR 数据主体删除请求）.
+/// 证书按锚定批次的发证方、批次ID及撤销索引确定, 证书编号只在学校内唯一
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ErasureRecord {
+    pub id: String,
+    #[serde(default)]
+    pub issuer: String,
+    #[serde(default)]
+    pub batch_id: String,
+    #[serde(default)]
+    pub index: u64,
+    pub original_data_cid: String,
+    pub unpinned_cids: Vec<String>,
+    // 密钥已销毁的记录, 早期的明文记录不在其中
+    #[serde(default)]
+    pub shredded_cids: Vec<String>,
+    pub reason: Option<String>,
+    pub erased_at: String,
+}
+
+impl ErasureRecord {
+    pub fn key(&self) -> String {
+        format!("{}/{}/{}", self.issuer, self.batch_id, self.index)
+    }
+}
+
+pub fn erasure_key(issuer: Address, batch_id: H256, index: u64) -> String {
+    format!("{issuer:?}/{batch_id:?}/{index}")
+}
+
+/// (发证方, 批次ID, 撤销索引) -> 删除记录
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ErasureRegistry(BTreeMap<String, ErasureRecord>);
+
+impl ErasureRegistry {
+    pub fn get(&self, issuer: Address, batch_id: H256, index: u64) -> Option<&ErasureRecord> {
+        self.0.get(&erasure_key(issuer, batch_id, index))
+    }
+
+    pub fn insert(&mut self, record: ErasureRecord) {
+        self.0.insert(record.key(), record);
+    }
+
+    /// 内容是否属于已删除的证书: 其索引、各类学历数据或图片
+    pub fn contains_content(&self, cid: &str) -> bool {
+        self.0.values().any(|record| {
+            record.original_data_cid == cid
+                || record.unpinned_cids.iter().any(|c| c == cid)
+                || record.shredded_cids.iter().any(|c| c == cid)
+        })
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref ERASURE_REGISTRY: Mutex<ErasureRegistry> = Mutex::new(load_registry());
+}
+
+fn registry_path() -> String {
+    std::env::var("ERASURE_REGISTRY_PATH")
+        .unwrap_or_else(|_| DEFAULT_ERASURE_REGISTRY_PATH.to_string())
+}
+
+fn load_registry() -> ErasureRegistry {
+    let path = registry_path();
+    match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            warn!("删除登记文件格式错误 {path}: {e}");
+            ErasureRegistry::default()
+        }),
+        Err(_) => ErasureRegistry::default(),
+    }
+}
+
+fn save_registry(registry: &ErasureRegistry) -> anyhow::Result<()> {
+    let path = registry_path();
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(registry)?)?;
+    Ok(())
+}
+
+pub fn get_erasure(issuer: Address, batch_id: H256, index: u64) -> Option<ErasureRecord> {
+    ERASURE_REGISTRY
+        .lock()
+        .unwrap()
+        .get(issuer, batch_id, index)
+        .cloned()
+}
+
+pub fn is_erased_content(cid: &str) -> bool {
+    ERASURE_REGISTRY.lock().unwrap().contains_content(cid)
+}
+
+/// 登记删除记录, erased_at 取登记时间
+pub fn record_erasure(mut record: ErasureRecord) -> anyhow::Result<ErasureRecord> {
+    record.erased_at = Local::now().to_rfc3339();
+
+    let mut registry = ERASURE_REGISTRY.lock().unwrap();
+    registry.insert(record.clone());
+    save_registry(&registry)?;
+    info!(
+        "已登记删除请求, 证书编号: {}, 批次: {}",
+        record.id, record.batch_id
+    );
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_erasure_registry() {
+        let issuer = Address::from_low_u64_be(1);
+        let batch_id = H256::from_low_u64_be(2);
+        let mut registry = ErasureRegistry::default();
+        registry.insert(ErasureRecord {
+            id: "D202501".to_string(),
+            issuer: format!("{issuer:?}"),
+            batch_id: format!("{batch_id:?}"),
+            index: 3,
+            original_data_cid: "QmIndex".to_string(),
+            unpinned_cids: vec!["QmIndex".to_string(), "QmImage".to_string()],
+            shredded_cids: vec!["QmRecord".to_string()],
+            ..Default::default()
+        });
+
+        assert_eq!(registry.get(issuer, batch_id, 3).unwrap().id, "D202501");
+        // 其他学校或批次中编号相同的证书不受影响
+        assert!(registry
+            .get(Address::from_low_u64_be(9), batch_id, 3)
+            .is_none());
+        assert!(registry.get(issuer, H256::from_low_u64_be(9), 3).is_none());
+        assert!(registry.get(issuer, batch_id, 4).is_none());
+
+        assert!(registry.contains_content("QmImage"));
+        // 取消固定失败但密钥已销毁的记录
+        assert!(registry.contains_content("QmRecord"));
+        assert!(!registry.contains_content("QmOther"));
+    }
+}
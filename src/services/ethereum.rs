@@ -1,29 +1,41 @@
+use anyhow::bail;
 use log::{error, info};
 use reqwest::Url;
-use std::sync::OnceLock;
-use web3::contract::{Contract, Options};
 use web3::transports::Http;
-use web3::types::{TransactionRequest, H256, U256};
+use web3::types::{Address, H256};
 use web3::Web3;
 
-// 添加合约ABI和字节码
-pub const CONTRACT_ABI: &str = include_str!("../../contracts/CertificateVerifier.abi");
-pub const CONTRACT_BYTECODE: &str = include_str!("../../contracts/CertificateVerifier.bin");
-
-// 添加合约地址存储
-pub static CONTRACT_ADDRESS: OnceLock<String> = OnceLock::new();
+use crate::services::transaction::TX_MANAGER;
+use crate::services::wallet::Signer;
+
+// 合约ABI和字节码, 由 build.rs 调用 solc 在同一次编译中生成
+pub const CONTRACT_ABI: &str = include_str!(concat!(env!("OUT_DIR"), "/CertificateVerifier.abi"));
+pub const CONTRACT_BYTECODE: &str =
+    include_str!(concat!(env!("OUT_DIR"), "/CertificateVerifier.bin"));
+pub const CONTRACT_RUNTIME_BYTECODE: &str =
+    include_str!(concat!(env!("OUT_DIR"), "/CertificateVerifier.bin-runtime"));
+pub const HALO2_VERIFIER_BYTECODE: &str =
+    include_str!(concat!(env!("OUT_DIR"), "/Halo2Verifier.bin"));
+pub const SHPLONK_VERIFIER_BYTECODE: &str =
+    include_str!(concat!(env!("OUT_DIR"), "/ShplonkVerifier.bin"));
+pub const ISSUER_REGISTRY_ABI: &str = include_str!(concat!(env!("OUT_DIR"), "/IssuerRegistry.abi"));
+pub const ISSUER_REGISTRY_RUNTIME_BYTECODE: &str =
+    include_str!(concat!(env!("OUT_DIR"), "/IssuerRegistry.bin-runtime"));
 
 pub async fn create_web3_connection() -> anyhow::Result<Web3<Http>> {
-    info!("创建以太坊连接...");
-
     let eth_url =
         std::env::var("ETHEREUM_NODE_URL").unwrap_or_else(|_| "http://127.0.0.1:8545".to_string());
+    connect_web3(&eth_url)
+}
+
+pub fn connect_web3(eth_url: &str) -> anyhow::Result<Web3<Http>> {
+    info!("创建以太坊连接...");
     info!("使用以太坊节点地址: {eth_url}");
 
     // 创建不使用代理的 HTTP 客户端
     let http_client = reqwest::Client::builder().no_proxy().build()?;
 
-    let url = Url::parse(&eth_url)?;
+    let url = Url::parse(eth_url)?;
     let http = Http::with_client(http_client, url);
 
     let web3 = Web3::new(http);
@@ -32,75 +44,34 @@ pub async fn create_web3_connection() -> anyhow::Result<Web3<Http>> {
     Ok(web3)
 }
 
-pub async fn deploy_contract(web3: &Web3<Http>) -> anyhow::Result<String> {
-    info!("开始部署合约...");
-
-    // 获取账户列表
-    let accounts = web3.eth().accounts().await?;
-    info!("获取到的账户列表: {accounts:?}");
-
-    let account = accounts
-        .first()
-        .ok_or_else(|| anyhow::anyhow!("没有找到可用的以太坊账户"))?;
-    info!("使用账户 {account} 部署合约");
-
-    // 获取当前 gas price
-    let gas_price = web3.eth().gas_price().await?;
-    info!("当前 gas price: {gas_price} wei");
-
-    // 打印合约字节码
-    info!("合约字节码: {CONTRACT_BYTECODE}");
-    info!("合约 ABI: {CONTRACT_ABI}");
+/// 部署一段创建字节码(已链接库并附加构造参数), 等待回执并返回合约地址和交易哈希
+pub async fn deploy_bytecode(
+    web3: &Web3<Http>,
+    signer: &Signer,
+    name: &str,
+    code: Vec<u8>,
+) -> anyhow::Result<(Address, H256)> {
+    if code.is_empty() {
+        bail!("{name} 字节码为空, 请先编译合约");
+    }
+    info!("开始部署合约 {name}, 字节码长度: {} bytes", code.len());
+    info!("使用账户 {:?} 部署合约", signer.address());
 
-    // 部署合约
-    info!("开始部署合约...");
-    let contract = Contract::deploy(web3.eth(), CONTRACT_ABI.as_bytes())?
-        .confirmations(0) // 不等待确认
-        .options(Options::with(|opt| {
-            opt.gas = Some(3_000_000.into());
-            opt.gas_price = Some(gas_price);
-        }))
-        .execute(CONTRACT_BYTECODE, (), *account)
+    // 验证器合约体积较大, 由交易管理器按估算值设置 gas
+    let receipt = TX_MANAGER
+        .send(&web3.eth(), signer, None, code, None)
         .await
         .map_err(|e| {
-            error!("合约部署失败: {e}");
-            anyhow::anyhow!("合约部署失败: {}", e)
+            error!("合约 {name} 部署失败: {e}");
+            anyhow::anyhow!("合约 {name} 部署失败: {}", e)
         })?;
 
-    let address = format!("{:?}", contract.address());
-    info!("合约部署成功！");
-    info!("合约地址: {address}");
-
-    Ok(address)
-}
-
-pub async fn send_transaction(web3: &Web3<Http>, data: Vec<u8>) -> anyhow::Result<H256> {
-    let accounts = web3.eth().accounts().await?;
-    let account = accounts
-        .first()
-        .ok_or_else(|| anyhow::anyhow!("No Ethereum account available"))?;
-
-    let gas_price = web3.eth().gas_price().await?;
+    let address = receipt
+        .contract_address
+        .ok_or_else(|| anyhow::anyhow!("合约 {name} 部署回执中没有合约地址"))?;
+    info!("合约 {name} 部署成功, 地址: {address:?}");
 
-    let tx = TransactionRequest {
-        from: *account,
-        to: None,
-        gas: Some(U256::from(3000000)),
-        gas_price: Some(gas_price),
-        value: Some(U256::from(0)),
-        data: Some(web3::types::Bytes(data)),
-        nonce: None,
-        condition: None,
-        transaction_type: None,
-        access_list: None,
-        max_fee_per_gas: None,
-        max_priority_fee_per_gas: None,
-    };
-
-    web3.eth()
-        .send_transaction(tx)
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to send transaction: {}", e))
+    Ok((address, receipt.transaction_hash))
 }
 
 pub async fn get_transaction_data(web3: &Web3<Http>, tx_hash: &str) -> anyhow::Result<String> {
@@ -0,0 +1,290 @@
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{anyhow, bail, Context};
+use log::info;
+use revm::primitives::{
+    CreateScheme, ExecutionResult, Output, ResultAndState, TransactTo, TxEnv, B160,
+};
+use revm::{InMemoryDB, EVM};
+use web3::types::{Address, H256};
+
+use crate::services::bindings::{CertificateVerifier, VerifyDataCall};
+use crate::services::deployment::{
+    certificate_verifier_code, decode_bytecode, shplonk_verifier_code, HALO2_VERIFIER,
+    SHPLONK_VERIFIER,
+};
+use crate::services::ethereum::HALO2_VERIFIER_BYTECODE;
+
+/// 验证合约的执行位置: 以太坊节点或进程内 EVM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyBackend {
+    Node,
+    Evm,
+}
+
+impl VerifyBackend {
+    pub fn from_env() -> Self {
+        match std::env::var("VERIFY_BACKEND").ok().as_deref() {
+            Some("evm") => Self::Evm,
+            _ => Self::Node,
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref VERIFY_BACKEND: VerifyBackend = VerifyBackend::from_env();
+}
+
+// 启动时部署好的本地验证合约
+pub static LOCAL_VERIFIER: OnceLock<Mutex<LocalVerifier>> = OnceLock::new();
+
+/// 基于 revm 的内存 EVM, 不依赖以太坊节点
+pub struct LocalEvm {
+    evm: EVM<InMemoryDB>,
+}
+
+impl LocalEvm {
+    pub fn new() -> Self {
+        Self {
+            evm: EVM {
+                env: Default::default(),
+                db: Some(InMemoryDB::default()),
+            },
+        }
+    }
+
+    /// 执行创建交易并提交状态, 返回合约地址
+    pub fn deploy(&mut self, name: &str, code: Vec<u8>) -> anyhow::Result<Address> {
+        if code.is_empty() {
+            bail!("{name} 字节码为空, 请先编译合约");
+        }
+
+        self.evm.env.tx = TxEnv {
+            gas_limit: u64::MAX,
+            transact_to: TransactTo::Create(CreateScheme::Create),
+            data: code.into(),
+            ..Default::default()
+        };
+        let result = self
+            .evm
+            .transact_commit()
+            .map_err(|e| anyhow!("{name} deployment failed: {e:?}"))?;
+
+        match result {
+            ExecutionResult::Success {
+                output: Output::Create(_, Some(address)),
+                ..
+            } => Ok(Address::from_slice(address.as_bytes())),
+            other => bail!("{name} deployment failed: {other:?}"),
+        }
+    }
+
+    /// 只读调用, 不提交状态变更, 返回调用输出
+    pub fn call(&mut self, address: Address, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        self.evm.env.tx = Self::call_tx(address, data);
+        let ResultAndState { result, .. } = self
+            .evm
+            .transact()
+            .map_err(|e| anyhow!("call to {address:?} failed: {e:?}"))?;
+        Self::call_output(address, result)
+    }
+
+    /// 执行调用并提交状态变更, 调用方与部署方相同
+    pub fn transact(&mut self, address: Address, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        self.evm.env.tx = Self::call_tx(address, data);
+        let result = self
+            .evm
+            .transact_commit()
+            .map_err(|e| anyhow!("call to {address:?} failed: {e:?}"))?;
+        Self::call_output(address, result)
+    }
+
+    fn call_tx(address: Address, data: Vec<u8>) -> TxEnv {
+        TxEnv {
+            gas_limit: u64::MAX,
+            transact_to: TransactTo::Call(B160::from_slice(address.as_bytes())),
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    fn call_output(address: Address, result: ExecutionResult) -> anyhow::Result<Vec<u8>> {
+        match result {
+            ExecutionResult::Success {
+                output: Output::Call(output),
+                ..
+            } => Ok(output.to_vec()),
+            ExecutionResult::Revert { output, .. } => {
+                bail!("call to {address:?} reverted: 0x{}", hex::encode(output))
+            }
+            other => bail!("call to {address:?} failed: {other:?}"),
+        }
+    }
+}
+
+impl Default for LocalEvm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// 在内存 EVM 中部署整套验证合约并执行 `verifyData`.
+/// 只复现编译产物的 `verifyData`, 不加载链上的代码与存储: 链上部署与编译产物一致由
+/// `load_contract_address` 校验, verifyData 读取的唯一状态 revocationRoot 由
+/// `publish_revocation_root` 同步. 锚定、撤销等其余合约状态不在本地 EVM 中
+pub struct LocalVerifier {
+    evm: LocalEvm,
+    certificate_verifier: Address,
+}
+
+impl LocalVerifier {
+    /// 与 `edu-verify deploy` 相同的顺序部署编译产物
+    pub fn deploy() -> anyhow::Result<Self> {
+        let mut evm = LocalEvm::new();
+
+        let halo2_verifier =
+            evm.deploy(HALO2_VERIFIER, decode_bytecode(HALO2_VERIFIER_BYTECODE)?)?;
+        let shplonk_verifier = evm.deploy(SHPLONK_VERIFIER, shplonk_verifier_code()?)?;
+        let certificate_verifier = evm.deploy(
+            "CertificateVerifier",
+            certificate_verifier_code(halo2_verifier, shplonk_verifier)?,
+        )?;
+        info!("本地EVM验证合约部署完成, 地址: {certificate_verifier:?}");
+
+        Ok(Self {
+            evm,
+            certificate_verifier,
+        })
+    }
+
+    pub fn verify_data(&mut self, call: VerifyDataCall) -> anyhow::Result<bool> {
+        let output = self.evm.call(
+            self.certificate_verifier,
+            CertificateVerifier::encode_verify_data(call),
+        )?;
+        CertificateVerifier::decode_verify_data(&output)
+    }
+
+    /// 更新本地合约中的撤销累加器根
+    pub fn set_revocation_root(&mut self, root: H256) -> anyhow::Result<()> {
+        self.evm.transact(
+            self.certificate_verifier,
+            CertificateVerifier::encode_update_revocation_root(root),
+        )?;
+        Ok(())
+    }
+}
+
+fn local_verifier() -> anyhow::Result<&'static Mutex<LocalVerifier>> {
+    LOCAL_VERIFIER
+        .get()
+        .ok_or_else(|| anyhow!("local EVM verifier not initialized"))
+}
+
+/// 使用启动时部署的本地合约执行 `verifyData`, 在调用线程上同步执行
+pub fn local_verify_data_blocking(call: VerifyDataCall) -> anyhow::Result<bool> {
+    local_verifier()?.lock().unwrap().verify_data(call)
+}
+
+/// 在阻塞线程池中执行同步的验证函数, EVM 执行与本地合约的锁不占用 actix 的工作线程
+pub async fn spawn_verify(
+    verifier: fn(VerifyDataCall) -> anyhow::Result<bool>,
+    call: VerifyDataCall,
+) -> anyhow::Result<bool> {
+    tokio::task::spawn_blocking(move || verifier(call))
+        .await
+        .context("local EVM verification task failed")?
+}
+
+/// 使用启动时部署的本地合约执行 `verifyData`
+pub async fn local_verify_data(call: VerifyDataCall) -> anyhow::Result<bool> {
+    spawn_verify(local_verify_data_blocking, call).await
+}
+
+/// 更新本地合约中的撤销累加器根, 本地 EVM 未初始化时跳过
+pub async fn set_local_revocation_root(root: H256) -> anyhow::Result<()> {
+    if LOCAL_VERIFIER.get().is_none() {
+        return Ok(());
+    }
+    tokio::task::spawn_blocking(move || local_verifier()?.lock().unwrap().set_revocation_root(root))
+        .await
+        .context("local EVM update task failed")?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::bindings::{certificate_verifier::RevocationRootCall, ContractCall};
+    use crate::services::revocation::{fr_to_h256, RevocationTree};
+
+    // 运行时代码: mstore(0, 1) return(0, 32), 即对任意调用返回 true
+    const RETURN_TRUE: [u8; 10] = [0x60, 0x01, 0x60, 0x00, 0x52, 0x60, 0x20, 0x60, 0x00, 0xf3];
+
+    fn return_true_code() -> Vec<u8> {
+        // codecopy(0, 12, 10) return(0, 10)
+        let init = [
+            0x60, 0x0a, 0x60, 0x0c, 0x60, 0x00, 0x39, 0x60, 0x0a, 0x60, 0x00, 0xf3,
+        ];
+        [&init[..], &RETURN_TRUE].concat()
+    }
+
+    #[test]
+    fn test_local_evm_deploy_and_call() {
+        let mut evm = LocalEvm::new();
+        let first = evm.deploy("ReturnTrue", return_true_code()).unwrap();
+        let second = evm.deploy("ReturnTrue", return_true_code()).unwrap();
+        assert_ne!(first, second);
+
+        let call = VerifyDataCall {
+            json_data: vec![0; 32],
+            ..Default::default()
+        };
+        let output = evm
+            .call(first, CertificateVerifier::encode_verify_data(call))
+            .unwrap();
+        assert!(CertificateVerifier::decode_verify_data(&output).unwrap());
+
+        assert!(evm.deploy("Empty", Vec::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_spawn_verify() {
+        let call = VerifyDataCall {
+            is_zk: true,
+            ..Default::default()
+        };
+        assert!(spawn_verify(|call| Ok(call.is_zk), call).await.unwrap());
+        assert!(
+            spawn_verify(|_| bail!("reverted"), VerifyDataCall::default())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_local_verifier_suite() {
+        let mut verifier = LocalVerifier::deploy().unwrap();
+        // 数据长度不是32的倍数, 合约 require 失败
+        assert!(verifier
+            .verify_data(VerifyDataCall {
+                json_data: vec![1; 31],
+                ..Default::default()
+            })
+            .is_err());
+        // 没有任何打开
+        assert!(verifier.verify_data(VerifyDataCall::default()).is_err());
+
+        // 撤销累加器根初始为空树的根, 而非 0
+        let root = verifier
+            .evm
+            .call(
+                verifier.certificate_verifier,
+                RevocationRootCall::default().encode(),
+            )
+            .unwrap();
+        assert_eq!(
+            H256::from_slice(&root),
+            fr_to_h256(RevocationTree::default().root())
+        );
+    }
+}
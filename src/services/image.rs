@@ -0,0 +1,293 @@
+use std::io::Cursor;
+
+use anyhow::{anyhow, bail, Context};
+use image::{ImageFormat, ImageOutputFormat};
+use serde::Serialize;
+
+use crate::services::config::env_or;
+
+const DEFAULT_MAX_IMAGE_BYTES: usize = 5 << 20;
+const DEFAULT_MAX_IMAGE_DIMENSION: u32 = 4096;
+const DEFAULT_MAX_IMAGE_FILES: usize = 64;
+
+/// 图片上传的校验与规范化策略
+#[derive(Clone, Copy, Debug)]
+pub struct ImagePolicy {
+    pub max_bytes: usize,
+    pub max_width: u32,
+    pub max_height: u32,
+    // 单个上传请求最多的文件数
+    pub max_files: usize,
+    pub reencode: Option<ImageFormat>,
+}
+
+impl ImagePolicy {
+    pub fn from_env() -> Self {
+        let reencode = match std::env::var("IMAGE_REENCODE").ok().as_deref() {
+            Some("png") => Some(ImageFormat::Png),
+            Some("jpeg") | Some("jpg") => Some(ImageFormat::Jpeg),
+            _ => None,
+        };
+
+        Self {
+            max_bytes: env_or("IMAGE_MAX_BYTES", DEFAULT_MAX_IMAGE_BYTES),
+            max_width: env_or("IMAGE_MAX_WIDTH", DEFAULT_MAX_IMAGE_DIMENSION),
+            max_height: env_or("IMAGE_MAX_HEIGHT", DEFAULT_MAX_IMAGE_DIMENSION),
+            max_files: env_or("IMAGE_MAX_FILES", DEFAULT_MAX_IMAGE_FILES),
+            reencode,
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref IMAGE_POLICY: ImagePolicy = ImagePolicy::from_env();
+}
+
+/// 规范化后的图片, `data` 即最终上传到IPFS的字节
+#[derive(Debug, Serialize)]
+pub struct NormalizedImage {
+    #[serde(skip)]
+    pub data: Vec<u8>,
+    pub mime: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub size: usize,
+}
+
+/// 根据文件头识别内容类型
+pub fn sniff_content_type(data: &[u8]) -> &'static str {
+    match data {
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => "image/png",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        [b'B', b'M', ..] => "image/bmp",
+        [b'%', b'P', b'D', b'F', ..] => "application/pdf",
+        [b'{', ..] | [b'[', ..] => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+pub fn normalize_image(data: &[u8], policy: &ImagePolicy) -> anyhow::Result<NormalizedImage> {
+    if data.len() > policy.max_bytes {
+        bail!(
+            "image too large: {} bytes, limit {} bytes",
+            data.len(),
+            policy.max_bytes
+        );
+    }
+
+    // 只信任文件头, 不信任客户端声明的类型
+    let format = match sniff_content_type(data) {
+        "image/jpeg" => ImageFormat::Jpeg,
+        "image/png" => ImageFormat::Png,
+        other => bail!("unsupported image type: {other}"),
+    };
+
+    let (width, height) = image::io::Reader::with_format(Cursor::new(data), format)
+        .into_dimensions()
+        .context("invalid image header")?;
+    if width > policy.max_width || height > policy.max_height {
+        bail!(
+            "image dimensions {width}x{height} exceed limit {}x{}",
+            policy.max_width,
+            policy.max_height
+        );
+    }
+
+    let data = match policy.reencode {
+        Some(target) => {
+            let image =
+                image::load_from_memory_with_format(data, format).context("invalid image data")?;
+            let output = match target {
+                ImageFormat::Jpeg => ImageOutputFormat::Jpeg(90),
+                _ => ImageOutputFormat::Png,
+            };
+            let mut out = Cursor::new(Vec::new());
+            image
+                .write_to(&mut out, output)
+                .context("failed to re-encode image")?;
+            out.into_inner()
+        }
+        None => match format {
+            ImageFormat::Jpeg => strip_jpeg_metadata(data)?,
+            _ => strip_png_metadata(data)?,
+        },
+    };
+
+    Ok(NormalizedImage {
+        mime: sniff_content_type(&data),
+        size: data.len(),
+        data,
+        width,
+        height,
+    })
+}
+
+/// 去除 JPEG 中的 EXIF/XMP (APP1)、IPTC (APP13) 和注释段, 保留 ICC 等其余段
+pub fn strip_jpeg_metadata(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..2]);
+
+    let mut pos = 2;
+    loop {
+        if data.get(pos) != Some(&0xFF) {
+            bail!("malformed JPEG segment at offset {pos}");
+        }
+        // 标记前可以有任意个 0xFF 填充字节, 输出时去掉
+        let mut marker_pos = pos + 1;
+        while data.get(marker_pos) == Some(&0xFF) {
+            marker_pos += 1;
+        }
+        let marker = *data
+            .get(marker_pos)
+            .ok_or_else(|| anyhow!("truncated JPEG marker at offset {pos}"))?;
+        match marker {
+            // SOS 之后为图像数据, 原样保留
+            0xDA => {
+                out.push(0xFF);
+                out.extend_from_slice(&data[marker_pos..]);
+                return Ok(out);
+            }
+            // TEM 与 RSTn 是没有长度字段的独立标记
+            0x01 | 0xD0..=0xD7 => {
+                out.extend_from_slice(&[0xFF, marker]);
+                pos = marker_pos + 1;
+                continue;
+            }
+            0x00 => bail!("malformed JPEG marker at offset {pos}"),
+            _ => {}
+        }
+
+        let len = data
+            .get(marker_pos + 1..marker_pos + 3)
+            .map(|len| u16::from_be_bytes([len[0], len[1]]) as usize)
+            .ok_or_else(|| anyhow!("truncated JPEG segment at offset {pos}"))?;
+        let end = marker_pos + 1 + len;
+        if len < 2 || end > data.len() {
+            bail!("malformed JPEG segment length at offset {pos}");
+        }
+
+        if !matches!(marker, 0xE1 | 0xED | 0xFE) {
+            out.push(0xFF);
+            out.extend_from_slice(&data[marker_pos..end]);
+        }
+        pos = end;
+    }
+}
+
+/// 去除 PNG 中的 eXIf、文本与时间块
+pub fn strip_png_metadata(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..8]);
+
+    let mut pos = 8;
+    while pos < data.len() {
+        let header = data
+            .get(pos..pos + 8)
+            .ok_or_else(|| anyhow!("truncated PNG chunk at offset {pos}"))?;
+        let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+        let end = pos + 12 + len;
+        if end > data.len() {
+            bail!("truncated PNG chunk at offset {pos}");
+        }
+
+        if !matches!(
+            &header[4..8],
+            b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME"
+        ) {
+            out.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ImagePolicy {
+        ImagePolicy {
+            max_bytes: 1 << 20,
+            max_width: 64,
+            max_height: 64,
+            max_files: 4,
+            reencode: None,
+        }
+    }
+
+    fn encode(width: u32, height: u32, format: ImageOutputFormat) -> Vec<u8> {
+        let image = image::DynamicImage::new_rgb8(width, height);
+        let mut out = Cursor::new(Vec::new());
+        image.write_to(&mut out, format).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn test_sniff_content_type() {
+        assert_eq!(sniff_content_type(&[0xFF, 0xD8, 0xFF, 0xE0]), "image/jpeg");
+        assert_eq!(
+            sniff_content_type(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            "image/png"
+        );
+        assert_eq!(sniff_content_type(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(sniff_content_type(br#"{"id":"1"}"#), "application/json");
+        assert_eq!(sniff_content_type(b"hello"), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_strip_jpeg_exif() {
+        let jpeg = encode(8, 8, ImageOutputFormat::Jpeg(90));
+        let exif = [0xFF, 0xE1, 0x00, 0x08, b'E', b'x', b'i', b'f', 0, 0];
+        let with_exif = [&jpeg[..2], &exif[..], &jpeg[2..]].concat();
+
+        let normalized = normalize_image(&with_exif, &policy()).unwrap();
+        assert_eq!(normalized.data, jpeg);
+        assert_eq!((normalized.width, normalized.height), (8, 8));
+        assert_eq!(normalized.mime, "image/jpeg");
+    }
+
+    #[test]
+    fn test_strip_jpeg_fill_bytes() {
+        let jpeg = encode(8, 8, ImageOutputFormat::Jpeg(90));
+        // 0xFF 填充字节、TEM 与 RST0 独立标记
+        let exif = [0xFF, 0xFF, 0xFF, 0xE1, 0x00, 0x04, 0, 0];
+        let marked = [&jpeg[..2], &exif[..], &[0xFF, 0x01, 0xFF, 0xD0], &jpeg[2..]].concat();
+
+        assert_eq!(
+            strip_jpeg_metadata(&marked).unwrap(),
+            [&jpeg[..2], &[0xFF, 0x01, 0xFF, 0xD0], &jpeg[2..]].concat()
+        );
+        assert!(strip_jpeg_metadata(&[0xFF, 0xD8, 0xFF, 0xFF]).is_err());
+    }
+
+    #[test]
+    fn test_reject_invalid_images() {
+        assert!(normalize_image(b"GIF89a", &policy()).is_err());
+        assert!(normalize_image(&encode(65, 8, ImageOutputFormat::Png), &policy()).is_err());
+        assert!(normalize_image(
+            &encode(8, 8, ImageOutputFormat::Png),
+            &ImagePolicy {
+                max_bytes: 16,
+                ..policy()
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_reencode_png() {
+        let jpeg = encode(8, 8, ImageOutputFormat::Jpeg(90));
+        let normalized = normalize_image(
+            &jpeg,
+            &ImagePolicy {
+                reencode: Some(ImageFormat::Png),
+                ..policy()
+            },
+        )
+        .unwrap();
+        assert_eq!(normalized.mime, "image/png");
+    }
+}
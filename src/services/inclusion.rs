@@ -0,0 +1,566 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, ensure, Context};
+use log::info;
+use rlp::{Rlp, RlpStream};
+use serde::Deserialize;
+use sha3::{Digest, Keccak256};
+use web3::transports::Http;
+use web3::types::{Address, BlockNumber, Bytes, H2048, H256, H64, U256, U64};
+use web3::{Transport, Web3};
+
+use crate::services::bindings::CertificateVerifier;
+use crate::services::config::env_or;
+
+// CertificateVerifier 中 `batches` 映射所在的存储槽位, 调整合约状态变量顺序时需同步修改
+const BATCHES_SLOT: u64 = 0;
+// 空树根 keccak256(rlp(""))
+const EMPTY_TRIE_ROOT: &str = "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421";
+const DEFAULT_MAX_HEADER_SPAN: u64 = 1024;
+
+/// 固定的可信区块: 区块号与区块哈希
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct Checkpoint {
+    pub number: u64,
+    pub hash: H256,
+}
+
+impl std::str::FromStr for Checkpoint {
+    type Err = anyhow::Error;
+
+    /// `区块号:区块哈希`
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (number, hash) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("checkpoint must be <number>:<hash>"))?;
+        Ok(Self {
+            number: number.trim().parse().context("invalid checkpoint number")?,
+            hash: hash.trim().parse().context("invalid checkpoint hash")?,
+        })
+    }
+}
+
+/// 包含证明配置, 配置后锚定数据须对照检查点验证
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProofConfig {
+    pub checkpoints: Vec<Checkpoint>,
+    // 为 true 时无法证明的批次直接验证失败, 否则退回节点返回的数据
+    #[serde(default)]
+    pub required: bool,
+    // 从检查点回溯的最大区块数
+    #[serde(default = "default_max_header_span")]
+    pub max_header_span: u64,
+}
+
+fn default_max_header_span() -> u64 {
+    DEFAULT_MAX_HEADER_SPAN
+}
+
+impl ProofConfig {
+    /// 单网络配置: TRUSTED_CHECKPOINTS 为逗号分隔的 `区块号:区块哈希`, 未配置时不启用
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(checkpoints) = std::env::var("TRUSTED_CHECKPOINTS") else {
+            return Ok(None);
+        };
+        let checkpoints = checkpoints
+            .split(',')
+            .filter(|s| !s.trim().is_empty())
+            .map(str::parse)
+            .collect::<anyhow::Result<Vec<Checkpoint>>>()?;
+        Ok(Some(Self {
+            checkpoints,
+            required: std::env::var("INCLUSION_PROOF_REQUIRED")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            max_header_span: env_or("INCLUSION_PROOF_MAX_HEADER_SPAN", DEFAULT_MAX_HEADER_SPAN),
+        }))
+    }
+}
+
+/// 锚定数据的可信程度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnchorStatus {
+    /// 直接采信 RPC 节点返回的数据
+    Asserted,
+    /// 存储证明已对照可信区块头验证
+    Proven { block_number: u64, block_hash: H256 },
+}
+
+/// 区块头, 字段按 RLP 编码顺序排列, 分叉新增的字段存在时依次追加
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Header {
+    pub parent_hash: H256,
+    pub sha3_uncles: H256,
+    pub miner: Address,
+    pub state_root: H256,
+    pub transactions_root: H256,
+    pub receipts_root: H256,
+    pub logs_bloom: H2048,
+    pub difficulty: U256,
+    pub number: U64,
+    pub gas_limit: U256,
+    pub gas_used: U256,
+    pub timestamp: U256,
+    pub extra_data: Bytes,
+    pub mix_hash: H256,
+    pub nonce: H64,
+    #[serde(default)]
+    pub base_fee_per_gas: Option<U256>,
+    #[serde(default)]
+    pub withdrawals_root: Option<H256>,
+    #[serde(default)]
+    pub blob_gas_used: Option<U256>,
+    #[serde(default)]
+    pub excess_blob_gas: Option<U256>,
+    #[serde(default)]
+    pub parent_beacon_block_root: Option<H256>,
+    #[serde(default)]
+    pub requests_hash: Option<H256>,
+}
+
+impl Header {
+    pub fn rlp(&self) -> Vec<u8> {
+        let mut fields = vec![
+            self.parent_hash.as_bytes().to_vec(),
+            self.sha3_uncles.as_bytes().to_vec(),
+            self.miner.as_bytes().to_vec(),
+            self.state_root.as_bytes().to_vec(),
+            self.transactions_root.as_bytes().to_vec(),
+            self.receipts_root.as_bytes().to_vec(),
+            self.logs_bloom.as_bytes().to_vec(),
+            uint(self.difficulty),
+            uint(self.number.as_u64().into()),
+            uint(self.gas_limit),
+            uint(self.gas_used),
+            uint(self.timestamp),
+            self.extra_data.0.clone(),
+            self.mix_hash.as_bytes().to_vec(),
+            self.nonce.as_bytes().to_vec(),
+        ];
+        let optional = [
+            self.base_fee_per_gas.map(uint),
+            self.withdrawals_root.map(|h| h.as_bytes().to_vec()),
+            self.blob_gas_used.map(uint),
+            self.excess_blob_gas.map(uint),
+            self.parent_beacon_block_root.map(|h| h.as_bytes().to_vec()),
+            self.requests_hash.map(|h| h.as_bytes().to_vec()),
+        ];
+        fields.extend(optional.into_iter().map_while(|field| field));
+
+        let mut stream = RlpStream::new_list(fields.len());
+        for field in &fields {
+            stream.append(field);
+        }
+        stream.out().to_vec()
+    }
+
+    pub fn hash(&self) -> H256 {
+        keccak(&self.rlp())
+    }
+}
+
+/// 以检查点为根的可信区块头链: 只接受沿 parentHash 回溯能连到检查点的区块
+pub struct HeaderChain {
+    config: ProofConfig,
+    // 已验证的区块号 -> 区块哈希, 初始为配置的检查点
+    verified: Mutex<BTreeMap<u64, H256>>,
+}
+
+impl HeaderChain {
+    pub fn new(config: ProofConfig) -> Self {
+        let verified = config
+            .checkpoints
+            .iter()
+            .map(|checkpoint| (checkpoint.number, checkpoint.hash))
+            .collect();
+        Self {
+            config,
+            verified: Mutex::new(verified),
+        }
+    }
+
+    pub fn required(&self) -> bool {
+        self.config.required
+    }
+
+    /// 从不早于 `number` 的最近已验证区块回溯, 返回哈希已验证的区块头
+    pub async fn header(&self, web3: &Web3<Http>, number: u64) -> anyhow::Result<Header> {
+        let (mut current, mut expected) = self
+            .verified
+            .lock()
+            .unwrap()
+            .range(number..)
+            .next()
+            .map(|(number, hash)| (*number, *hash))
+            .ok_or_else(|| anyhow!("block {number} is after the latest checkpoint"))?;
+        ensure!(
+            current - number <= self.config.max_header_span,
+            "block {number} is {} blocks before checkpoint {current}",
+            current - number
+        );
+
+        loop {
+            let header = fetch_header(web3, current).await?;
+            ensure!(
+                header.hash() == expected,
+                "header {current} does not match the trusted chain"
+            );
+            self.verified.lock().unwrap().insert(current, expected);
+            if current == number {
+                return Ok(header);
+            }
+            expected = header.parent_hash;
+            current -= 1;
+        }
+    }
+}
+
+async fn fetch_header(web3: &Web3<Http>, number: u64) -> anyhow::Result<Header> {
+    let header = web3
+        .transport()
+        .execute(
+            "eth_getBlockByNumber",
+            vec![
+                serde_json::json!(U64::from(number)),
+                serde_json::json!(false),
+            ],
+        )
+        .await?;
+    ensure!(!header.is_null(), "block {number} not found");
+    serde_json::from_value(header).with_context(|| format!("invalid header {number}"))
+}
+
+/// 经证明的批次
+#[derive(Debug, Clone)]
+pub struct ProvenBatch {
+    pub issuer: Address,
+    pub batch_id: H256,
+    pub schema_hash: H256,
+    pub commitments: Vec<u8>,
+    pub block_number: u64,
+    pub block_hash: H256,
+}
+
+/// 证明锚定交易写入的批次: 由回执定位批次与区块, 再用 `eth_getProof` 对照可信区块头验证合约存储.
+/// 回执本身不可信, 但伪造的发证方或批次ID无法通过存储证明
+pub async fn prove_anchor(
+    web3: &Web3<Http>,
+    verifier: &CertificateVerifier,
+    headers: &HeaderChain,
+    tx_hash: &str,
+) -> anyhow::Result<ProvenBatch> {
+    let hash: H256 = tx_hash.parse().context("invalid transaction hash")?;
+    let receipt = web3
+        .eth()
+        .transaction_receipt(hash)
+        .await?
+        .ok_or_else(|| anyhow!("Transaction receipt not found"))?;
+    let event = verifier
+        .parse_batch_anchored(&receipt.logs)
+        .ok_or_else(|| anyhow!("transaction {tx_hash} has no BatchAnchored event"))?;
+    let block_number = receipt
+        .block_number
+        .ok_or_else(|| anyhow!("transaction {tx_hash} is pending"))?
+        .as_u64();
+
+    let header = headers.header(web3, block_number).await?;
+    let block = BlockNumber::Number(block_number.into());
+    let base = batch_slot(event.issuer, event.batch_id);
+    let fields = prove_storage(
+        web3,
+        &header,
+        verifier.address(),
+        &[base, base + 1, base + 2],
+        block,
+    )
+    .await?;
+    ensure!(
+        !fields[2].is_zero(),
+        "batch {:?} is not anchored at block {block_number}",
+        event.batch_id
+    );
+
+    // 超过31字节的 bytes: 槽位存 len*2+1, 数据从 keccak256(槽位) 开始连续存放
+    ensure!(fields[1].bit(0), "commitments are not stored as long bytes");
+    let len = ((fields[1] - 1) / 2).as_usize();
+    let start = U256::from_big_endian(keccak(&word(base + 1)).as_bytes());
+    let slots = (0..len.div_ceil(32)).map(|i| start + i).collect::<Vec<_>>();
+    let mut commitments = prove_storage(web3, &header, verifier.address(), &slots, block)
+        .await?
+        .into_iter()
+        .flat_map(|value| word(value).to_vec())
+        .collect::<Vec<_>>();
+    commitments.truncate(len);
+
+    let block_hash = header.hash();
+    info!(
+        "批次 {:?} 已在区块 {block_number} ({block_hash:?}) 中证明",
+        event.batch_id
+    );
+    Ok(ProvenBatch {
+        issuer: event.issuer,
+        batch_id: event.batch_id,
+        schema_hash: H256::from(word(fields[0])),
+        commitments,
+        block_number,
+        block_hash,
+    })
+}
+
+/// 对照区块头的 stateRoot 验证合约账户与各存储槽位, 返回验证后的槽位值
+async fn prove_storage(
+    web3: &Web3<Http>,
+    header: &Header,
+    contract: Address,
+    slots: &[U256],
+    block: BlockNumber,
+) -> anyhow::Result<Vec<U256>> {
+    let proof = web3
+        .eth()
+        .proof(contract, slots.to_vec(), Some(block))
+        .await?
+        .ok_or_else(|| anyhow!("node returned no proof for {contract:?}"))?;
+
+    let account = verify_proof(header.state_root, contract.as_bytes(), &proof.account_proof)?
+        .ok_or_else(|| anyhow!("contract {contract:?} is not in the proven state"))?;
+    let storage_root = H256::from_slice(Rlp::new(&account).at(2)?.data()?);
+
+    ensure!(
+        proof.storage_proof.len() == slots.len(),
+        "node returned {} storage proofs for {} slots",
+        proof.storage_proof.len(),
+        slots.len()
+    );
+    slots
+        .iter()
+        .zip(&proof.storage_proof)
+        .map(|(slot, storage)| {
+            ensure!(storage.key == *slot, "storage proof for unexpected slot");
+            // 不存在的槽位值为0
+            match verify_proof(storage_root, &word(*slot), &storage.proof)? {
+                Some(value) => Ok(U256::from_big_endian(Rlp::new(&value).data()?)),
+                None => Ok(U256::zero()),
+            }
+        })
+        .collect()
+}
+
+/// `batches[issuer][batchId]` 的起始槽位, 依次为 schemaHash、commitments、anchoredAt
+pub fn batch_slot(issuer: Address, batch_id: H256) -> U256 {
+    let inner = keccak(&[H256::from(issuer).as_bytes(), &word(BATCHES_SLOT.into())].concat());
+    U256::from_big_endian(keccak(&[batch_id.as_bytes(), inner.as_bytes()].concat()).as_bytes())
+}
+
+/// 沿 keccak256(key) 路径验证 Merkle-Patricia 证明, 返回叶子中的值, 路径不存在时为 None
+pub fn verify_proof(root: H256, key: &[u8], proof: &[Bytes]) -> anyhow::Result<Option<Vec<u8>>> {
+    if root == EMPTY_TRIE_ROOT.parse()? {
+        return Ok(None);
+    }
+
+    let path = nibbles(keccak(key).as_bytes());
+    let mut offset = 0;
+    let mut proof = proof.iter();
+    let mut next = NodeRef::Hash(root);
+    loop {
+        let node = match next {
+            NodeRef::Hash(hash) => {
+                let node = proof
+                    .next()
+                    .ok_or_else(|| anyhow!("proof ends before the key path"))?;
+                ensure!(
+                    keccak(&node.0) == hash,
+                    "proof node does not match its hash"
+                );
+                node.0.clone()
+            }
+            NodeRef::Inline(node) => node,
+        };
+
+        let node = Rlp::new(&node);
+        match node.item_count()? {
+            17 => {
+                if offset == path.len() {
+                    let value = node.at(16)?.data()?;
+                    return Ok((!value.is_empty()).then(|| value.to_vec()));
+                }
+                match NodeRef::child(&node.at(path[offset] as usize)?)? {
+                    Some(child) => next = child,
+                    None => return Ok(None),
+                }
+                offset += 1;
+            }
+            2 => {
+                let (partial, leaf) = decode_hex_prefix(node.at(0)?.data()?)?;
+                let rest = &path[offset..];
+                if leaf {
+                    if rest != partial.as_slice() {
+                        return Ok(None);
+                    }
+                    return Ok(Some(node.at(1)?.data()?.to_vec()));
+                }
+                if !rest.starts_with(&partial) {
+                    return Ok(None);
+                }
+                offset += partial.len();
+                next = NodeRef::child(&node.at(1)?)?
+                    .ok_or_else(|| anyhow!("extension node without child"))?;
+            }
+            n => bail!("invalid trie node with {n} items"),
+        }
+    }
+}
+
+enum NodeRef {
+    Hash(H256),
+    // 编码不足32字节的子节点直接嵌在父节点中
+    Inline(Vec<u8>),
+}
+
+impl NodeRef {
+    fn child(item: &Rlp) -> anyhow::Result<Option<Self>> {
+        if item.is_list() {
+            return Ok(Some(Self::Inline(item.as_raw().to_vec())));
+        }
+        match item.data()? {
+            [] => Ok(None),
+            hash if hash.len() == 32 => Ok(Some(Self::Hash(H256::from_slice(hash)))),
+            _ => bail!("invalid trie child reference"),
+        }
+    }
+}
+
+/// 解码路径的 hex-prefix 编码, 返回半字节路径与是否为叶子
+fn decode_hex_prefix(encoded: &[u8]) -> anyhow::Result<(Vec<u8>, bool)> {
+    let first = *encoded.first().ok_or_else(|| anyhow!("empty trie path"))?;
+    let flag = first >> 4;
+    ensure!(flag <= 3, "invalid hex prefix {flag}");
+
+    let mut path = Vec::new();
+    if flag & 1 == 1 {
+        path.push(first & 0x0f);
+    }
+    path.extend(nibbles(&encoded[1..]));
+    Ok((path, flag & 2 == 2))
+}
+
+fn nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+fn keccak(data: &[u8]) -> H256 {
+    H256::from_slice(&Keccak256::digest(data))
+}
+
+fn word(value: U256) -> [u8; 32] {
+    let mut word = [0; 32];
+    value.to_big_endian(&mut word);
+    word
+}
+
+// 整数的 RLP 编码使用去掉前导零的大端字节
+fn uint(value: U256) -> Vec<u8> {
+    let word = word(value);
+    let start = word.iter().position(|b| *b != 0).unwrap_or(32);
+    word[start..].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex_prefix(path: &[u8], leaf: bool) -> Vec<u8> {
+        let flag = if leaf { 2 } else { 0 } + (path.len() % 2) as u8;
+        let mut encoded = vec![flag << 4];
+        let mut rest = path;
+        if path.len() % 2 == 1 {
+            encoded[0] |= path[0];
+            rest = &path[1..];
+        }
+        encoded.extend(rest.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+        encoded
+    }
+
+    fn leaf(path: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(path, true));
+        stream.append(&value.to_vec());
+        stream.out().to_vec()
+    }
+
+    #[test]
+    fn test_mainnet_genesis_header_hash() {
+        let header: Header = serde_json::from_value(serde_json::json!({
+            "parentHash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "sha3Uncles": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+            "miner": "0x0000000000000000000000000000000000000000",
+            "stateRoot": "0xd7f8974fb5ac78d9ac099b9ad5018bedc2ce0a72dad1827a1709da30580f0544",
+            "transactionsRoot": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+            "receiptsRoot": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+            "logsBloom": format!("0x{}", "0".repeat(512)),
+            "difficulty": "0x400000000",
+            "number": "0x0",
+            "gasLimit": "0x1388",
+            "gasUsed": "0x0",
+            "timestamp": "0x0",
+            "extraData": "0x11bbe8db4e347b4e8c937c1c8370e4b5ed33adb3db69cbdb7a38e1e50b1b82fa",
+            "mixHash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+            "nonce": "0x0000000000000042",
+        }))
+        .unwrap();
+        assert_eq!(
+            header.hash(),
+            "0xd4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+                .parse()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_verify_proof() {
+        // 两个首半字节不同的槽位组成一个分支节点下的两个叶子
+        let slot = |i: u64| word(i.into());
+        let path = |i: u64| nibbles(keccak(&slot(i)).as_bytes());
+        let a = 0;
+        let b = (1..).find(|i| path(*i)[0] != path(a)[0]).unwrap();
+        let leaf_a = leaf(&path(a)[1..], &[0x83, 1, 2, 3]);
+        let leaf_b = leaf(&path(b)[1..], &[0x05]);
+
+        let mut branch = RlpStream::new_list(17);
+        for nibble in 0..16 {
+            if nibble == path(a)[0] {
+                branch.append(&keccak(&leaf_a).as_bytes().to_vec());
+            } else if nibble == path(b)[0] {
+                branch.append(&keccak(&leaf_b).as_bytes().to_vec());
+            } else {
+                branch.append_empty_data();
+            }
+        }
+        branch.append_empty_data();
+        let branch = branch.out().to_vec();
+        let root = keccak(&branch);
+
+        let proof_a = vec![Bytes(branch.clone()), Bytes(leaf_a.clone())];
+        assert_eq!(
+            verify_proof(root, &slot(a), &proof_a).unwrap(),
+            Some(vec![0x83, 1, 2, 3])
+        );
+        let proof_b = vec![Bytes(branch.clone()), Bytes(leaf_b)];
+        assert_eq!(
+            verify_proof(root, &slot(b), &proof_b).unwrap(),
+            Some(vec![0x05])
+        );
+
+        // 与 a 共享首半字节的槽位不存在
+        let c = (1..)
+            .find(|i| *i != a && path(*i)[0] == path(a)[0])
+            .unwrap();
+        assert_eq!(verify_proof(root, &slot(c), &proof_a).unwrap(), None);
+
+        // 篡改叶子后哈希不匹配
+        let mut forged = leaf_a;
+        *forged.last_mut().unwrap() = 4;
+        assert!(verify_proof(root, &slot(a), &[Bytes(branch), Bytes(forged)]).is_err());
+    }
+}
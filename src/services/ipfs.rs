@@ -1,68 +1,260 @@
 use anyhow::Context;
-use futures_util::StreamExt;
+use futures_util::{stream, StreamExt, TryStreamExt};
 use ipfs_api_backend_hyper::{IpfsApi, IpfsClient};
-use log::info;
+use log::{info, warn};
 use serde_json::Value;
+use std::future::Future;
 use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use crate::services::cache::{is_valid_cid, CONTENT_CACHE};
+use crate::services::config::env_or;
+use crate::services::image::{normalize_image, IMAGE_POLICY};
+use crate::services::shredding::{is_encrypted, record_key, register_record, RecordKey};
+
+/// IPFS 调用的并发、超时与重试配置
+#[derive(Clone, Copy, Debug)]
+pub struct IpfsExecutorConfig {
+    pub concurrency: usize,
+    pub timeout: Duration,
+    pub retries: usize,
+    pub backoff: Duration,
+}
+
+impl IpfsExecutorConfig {
+    pub fn from_env() -> Self {
+        Self {
+            concurrency: env_or("IPFS_CONCURRENCY", 16usize).max(1),
+            timeout: Duration::from_secs(env_or("IPFS_TIMEOUT_SECS", 30)),
+            retries: env_or("IPFS_RETRIES", 3),
+            backoff: Duration::from_millis(env_or("IPFS_RETRY_BACKOFF_MS", 200)),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref IPFS_EXECUTOR_CONFIG: IpfsExecutorConfig = IpfsExecutorConfig::from_env();
+    // 所有请求共享的IPFS并发许可
+    static ref IPFS_PERMITS: Semaphore = Semaphore::new(IPFS_EXECUTOR_CONFIG.concurrency);
+}
+
+/// 在共享并发上限内执行一次IPFS调用, 超时或失败时按指数退避重试
+async fn with_retry<T, F, Fut>(op: &str, mut f: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let config = *IPFS_EXECUTOR_CONFIG;
+    let mut backoff = config.backoff;
+    let mut attempt = 0;
+
+    loop {
+        let res = {
+            let _permit = IPFS_PERMITS
+                .acquire()
+                .await
+                .context("IPFS executor closed")?;
+            tokio::time::timeout(config.timeout, f())
+                .await
+                .unwrap_or_else(|_| {
+                    Err(anyhow::anyhow!("{op} timed out after {:?}", config.timeout))
+                })
+        };
+
+        match res {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < config.retries => {
+                attempt += 1;
+                warn!("{op} 失败, {backoff:?} 后第{attempt}次重试: {e:#}");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// 并发执行 `f`, 结果顺序与输入顺序一致, 遇到第一个错误即返回
+pub async fn try_join_ordered<I, F, Fut, T, E>(items: I, f: F) -> Result<Vec<T>, E>
+where
+    I: IntoIterator,
+    F: FnMut(I::Item) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    stream::iter(items)
+        .map(f)
+        .buffered(IPFS_EXECUTOR_CONFIG.concurrency)
+        .try_collect()
+        .await
+}
 
 pub async fn upload_to_ipfs(client: &IpfsClient, data: Vec<u8>) -> anyhow::Result<String> {
     info!("开始上传文件到IPFS...");
-    let cursor = Cursor::new(data);
-    let res = client
-        .add(cursor)
-        .await
-        .context("Failed to upload to IPFS")?;
+    let data = &data;
+    let res = with_retry("IPFS add", || async move {
+        client
+            .add(Cursor::new(data.clone()))
+            .await
+            .context("Failed to upload to IPFS")
+    })
+    .await?;
     info!("IPFS上传成功, CID: {}", res.hash);
     Ok(res.hash)
 }
 
-pub async fn get_image_from_ipfs(ipfs_client: &IpfsClient, cid: &str) -> anyhow::Result<Vec<u8>> {
-    info!("从IPFS获取图片数据, CID: {cid}");
-    let mut stream = ipfs_client.cat(cid);
-    let mut data = Vec::new();
+/// 以新的记录密钥加密后上传学历记录, 登记密钥后返回 CID
+pub async fn upload_record_to_ipfs(client: &IpfsClient, data: Vec<u8>) -> anyhow::Result<String> {
+    let key_id = hex::encode(rand::random::<[u8; 16]>());
+    upload_encrypted(client, data, &key_id, &RecordKey::generate()).await
+}
+
+/// 上传记录的副本 (如展示时隐去部分字段的记录), 沿用原记录的密钥, 原记录为明文时副本也为明文
+pub async fn upload_record_copy_to_ipfs(
+    client: &IpfsClient,
+    source_cid: &str,
+    data: Vec<u8>,
+) -> anyhow::Result<String> {
+    match record_key(source_cid)? {
+        Some((key_id, key)) => upload_encrypted(client, data, &key_id, &key).await,
+        None => upload_to_ipfs(client, data).await,
+    }
+}
+
+async fn upload_encrypted(
+    client: &IpfsClient,
+    data: Vec<u8>,
+    key_id: &str,
+    key: &RecordKey,
+) -> anyhow::Result<String> {
+    let cid = upload_to_ipfs(client, key.encrypt(&data)).await?;
+    register_record(&cid, key_id, key)?;
+    Ok(cid)
+}
+
+pub async fn unpin_from_ipfs(client: &IpfsClient, cid: &str) -> anyhow::Result<()> {
+    info!("从IPFS节点取消固定, CID: {cid}");
+    CONTENT_CACHE.remove(cid);
+    client
+        .pin_rm(cid, true)
+        .await
+        .context("Failed to unpin from IPFS")?;
+    Ok(())
+}
 
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.context("Failed to get chunk from IPFS")?;
-        data.extend_from_slice(&chunk);
+pub async fn get_content_from_ipfs(
+    ipfs_client: &IpfsClient,
+    cid: &str,
+) -> anyhow::Result<Arc<Vec<u8>>> {
+    if let Some(data) = CONTENT_CACHE.get(cid) {
+        return Ok(data);
     }
 
-    info!("成功从IPFS获取图片数据，大小: {} bytes", data.len());
+    let data = with_retry("IPFS cat", || async move {
+        let mut stream = ipfs_client.cat(cid);
+        let mut data = Vec::new();
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.context("Failed to get chunk from IPFS")?;
+            data.extend_from_slice(&chunk);
+        }
+
+        Ok(data)
+    })
+    .await?;
+
+    let data = Arc::new(data);
+    CONTENT_CACHE.insert(cid, data.clone());
     Ok(data)
 }
 
 pub async fn get_json_from_ipfs(ipfs_client: &IpfsClient, cid: &str) -> anyhow::Result<String> {
     info!("从IPFS获取json数据, CID: {cid}");
-    let mut stream = ipfs_client.cat(cid);
-    let mut data = Vec::new();
-
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.context("Failed to get chunk from IPFS")?;
-        data.extend_from_slice(&chunk);
-    }
+    let data = get_content_from_ipfs(ipfs_client, cid).await?;
+    // 学历记录以密文存储, 密钥已销毁时无法读取
+    let data = match record_key(cid)? {
+        Some((_, key)) => key.decrypt(&data)?,
+        None if is_encrypted(&data) => anyhow::bail!("no record key for {cid}"),
+        None => data.to_vec(),
+    };
 
     let json_str = String::from_utf8(data).context("Failed to parse JSON")?;
     info!("成功从IPFS获取json数据, {json_str}");
     Ok(json_str)
 }
 
+enum ImageSource {
+    Cid(usize, String),
+    Data(Vec<u8>),
+}
+
 pub async fn process_images(
     ipfs_client: &IpfsClient,
     images: &Vec<Value>,
 ) -> anyhow::Result<Vec<String>> {
-    let mut processed_images = Vec::new();
-
-    for image in images {
-        if let Some(data) = image.get("data").and_then(Value::as_str) {
-            // 解码Base64数据
-            let image_data = base64::decode(data)
-                .map_err(|e| anyhow::anyhow!("Failed to decode base64 data: {}", e))?;
+    let sources = images
+        .iter()
+        .enumerate()
+        .filter_map(|(i, image)| {
+            // 通过 multipart 接口上传的图片, 只给出CID
+            if let Some(cid) = image.get("ipfs_cid").and_then(Value::as_str) {
+                if !is_valid_cid(cid) {
+                    return Some(Err(anyhow::anyhow!("image {i}: invalid CID {cid}")));
+                }
+                return Some(Ok(ImageSource::Cid(i, cid.to_string())));
+            }
 
-            // 上传到IPFS
-            let ipfs_hash = upload_to_ipfs(ipfs_client, image_data.clone()).await?;
+            image.get("data").and_then(Value::as_str).map(|data| {
+                // 解码Base64数据
+                let image_data = base64::decode(data)
+                    .map_err(|e| anyhow::anyhow!("Failed to decode base64 data: {}", e))?;
+                let normalized = normalize_image(&image_data, &IMAGE_POLICY)
+                    .with_context(|| format!("image {i} rejected"))?;
+                Ok(ImageSource::Data(normalized.data))
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
-            processed_images.push(ipfs_hash);
+    // 上传到IPFS
+    try_join_ordered(sources, |source| async move {
+        match source {
+            // 客户端给出的CID可能指向任意内容, 取回后按相同策略校验,
+            // 规范化后内容有变化时上传规范化的版本
+            ImageSource::Cid(i, cid) => {
+                let data = get_content_from_ipfs(ipfs_client, &cid).await?;
+                let normalized = normalize_image(&data, &IMAGE_POLICY)
+                    .with_context(|| format!("image {i} ({cid}) rejected"))?;
+                if normalized.data == *data {
+                    Ok(cid)
+                } else {
+                    upload_to_ipfs(ipfs_client, normalized.data).await
+                }
+            }
+            ImageSource::Data(image_data) => upload_to_ipfs(ipfs_client, image_data).await,
         }
-    }
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_try_join_ordered_keeps_order() {
+        let items = (0..64u64).collect::<Vec<_>>();
 
-    Ok(processed_images)
+        let res: Result<Vec<u64>, ()> = try_join_ordered(items.clone(), |i| async move {
+            // 越靠前的任务越晚完成
+            tokio::time::sleep(Duration::from_millis(64 - i)).await;
+            Ok(i * 2)
+        })
+        .await;
+
+        assert_eq!(
+            res.unwrap(),
+            items.iter().map(|i| i * 2).collect::<Vec<_>>()
+        );
+    }
 }
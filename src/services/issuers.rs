@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use web3::types::{Address, H256};
+
+use crate::services::bindings::IssuerRegistry;
+use crate::services::wallet::wallet;
+
+// 发证方目录文件路径
+const DEFAULT_ISSUER_DIRECTORY: &str = "issuers.json";
+
+/// 发证方身份
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssuerInfo {
+    pub address: Address,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub website: Option<String>,
+}
+
+/// 发证方目录: 锚定交易的发送地址 -> 学校身份
+#[derive(Debug, Default)]
+pub struct IssuerDirectory {
+    issuers: BTreeMap<Address, IssuerInfo>,
+}
+
+impl IssuerDirectory {
+    pub fn new(issuers: impl IntoIterator<Item = IssuerInfo>) -> Self {
+        Self {
+            issuers: issuers
+                .into_iter()
+                .map(|issuer| (issuer.address, issuer))
+                .collect(),
+        }
+    }
+
+    /// 读取 ISSUER_DIRECTORY, 再补充本服务钱包中的学校签名账户
+    pub fn load() -> Self {
+        let path = std::env::var("ISSUER_DIRECTORY")
+            .unwrap_or_else(|_| DEFAULT_ISSUER_DIRECTORY.to_string());
+        let issuers = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!("发证方目录格式错误 {path}: {e}");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+
+        let mut directory = Self::new(issuers);
+        if let Ok(wallet) = wallet() {
+            for (name, signer) in wallet.signers() {
+                directory
+                    .issuers
+                    .entry(signer.address())
+                    .or_insert_with(|| IssuerInfo {
+                        address: signer.address(),
+                        name: name.clone(),
+                        website: None,
+                    });
+            }
+        }
+        directory
+    }
+
+    pub fn resolve(&self, address: Address) -> Option<&IssuerInfo> {
+        self.issuers.get(&address)
+    }
+}
+
+/// 锚定账户在链上发证方登记合约中的状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssuerStatus {
+    /// 网络未配置登记合约
+    Unchecked,
+    /// 锚定时密钥与学校认证均有效
+    Authorized { name: String, code: String },
+    /// 账户未登记为任何学校的密钥
+    Unregistered,
+    /// 密钥已停用或学校认证不在有效期内
+    Unauthorized { issuer_id: H256 },
+}
+
+impl IssuerStatus {
+    /// 未配置登记合约时不拒绝锚定
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, Self::Unchecked | Self::Authorized { .. })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unchecked => "unchecked",
+            Self::Authorized { .. } => "authorized",
+            Self::Unregistered => "unregistered",
+            Self::Unauthorized { .. } => "unauthorized",
+        }
+    }
+}
+
+/// 查询 sender 在锚定时刻 timestamp 是否是已认证学校的有效密钥
+pub async fn issuer_status(
+    registry: Option<&IssuerRegistry>,
+    sender: Address,
+    timestamp: u64,
+) -> anyhow::Result<IssuerStatus> {
+    let Some(registry) = registry else {
+        return Ok(IssuerStatus::Unchecked);
+    };
+    let (authorized, issuer_id) = registry.is_authorized(sender, timestamp).await?;
+    if issuer_id.is_zero() {
+        return Ok(IssuerStatus::Unregistered);
+    }
+    if !authorized {
+        return Ok(IssuerStatus::Unauthorized { issuer_id });
+    }
+    let issuer = registry.get_issuer(issuer_id).await?;
+    Ok(IssuerStatus::Authorized {
+        name: issuer.name,
+        code: issuer.code,
+    })
+}
+
+lazy_static::lazy_static! {
+    pub static ref ISSUER_DIRECTORY: IssuerDirectory = IssuerDirectory::load();
+}
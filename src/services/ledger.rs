@@ -0,0 +1,835 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use chrono::Utc;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use web3::transports::Http;
+use web3::types::{Address, H256};
+use web3::Web3;
+
+use crate::services::anchor::{
+    anchor_commitments, anchor_update, load_anchor_info, load_batch, new_batch_id, Anchor,
+    AnchorInfo,
+};
+use crate::services::bindings::{CertificateVerifier, VerifyDataCall};
+use crate::services::evm::{
+    local_verify_data, local_verify_data_blocking, spawn_verify, VerifyBackend, VERIFY_BACKEND,
+};
+use crate::services::inclusion::{prove_anchor, AnchorStatus, HeaderChain};
+use crate::services::wallet::{wallet, Signer};
+
+// 合约中 G1 点的编码长度
+const G1_SIZE: usize = 96;
+
+lazy_static::lazy_static! {
+    // 是否允许请求方要求链上记录验证结果, 记录交易由服务端账户付费
+    pub static ref ATTESTATION_ENABLED: bool = std::env::var("ATTESTATION_ENABLED")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false);
+}
+
+/// 存证账本: handler 只依赖该接口, 不关心具体链
+#[async_trait]
+pub trait Ledger: Send + Sync {
+    /// 以签名方身份锚定一批承诺, students 为批次的学生数, 撤销槽位不能超出
+    async fn anchor_batch(
+        &self,
+        signer: &Signer,
+        schema_hash: H256,
+        commitments: Vec<u8>,
+        students: u64,
+    ) -> anyhow::Result<Anchor>;
+
+    /// 锚定在签名方已有批次上追加或更新学生后的承诺, delta 为各承诺的增量, 只作记录,
+    /// 账本不校验 commitments = parent + delta. 分片换用更大定义域时 delta 只是新旧承诺之差,
+    /// 不对应槽位增量. 原批次保持不变, 被更新学生的原证书须另行取代
+    async fn update_batch(
+        &self,
+        signer: &Signer,
+        parent_batch_id: H256,
+        schema_hash: H256,
+        commitments: Vec<u8>,
+        delta: Vec<u8>,
+        students: u64,
+    ) -> anyhow::Result<Anchor>;
+
+    /// 按证书中的锚定交易读取承诺
+    async fn fetch_batch(&self, tx_hash: &str) -> anyhow::Result<FetchedBatch>;
+
+    /// 锚定的时间、发送方与确认数
+    async fn anchor_info(&self, tx_hash: &str) -> anyhow::Result<AnchorInfo>;
+
+    /// 以发证方身份撤销批次中槽位为 index 的证书, 槽位见 `revocation_index`, 返回撤销交易哈希
+    async fn revoke(
+        &self,
+        signer: &Signer,
+        batch_id: H256,
+        index: u64,
+        reason: RevocationReason,
+    ) -> anyhow::Result<H256>;
+
+    /// 证书的撤销原因, 未撤销时为空
+    async fn revocation(
+        &self,
+        issuer: Address,
+        batch_id: H256,
+        index: u64,
+    ) -> anyhow::Result<Option<RevocationReason>>;
+
+    /// 以发证方身份用更正批次 new_batch_id 中的证书取代旧证书,
+    /// 旧证书同时以 Superseded 撤销, 返回交易哈希
+    async fn supersede(
+        &self,
+        signer: &Signer,
+        batch_id: H256,
+        index: u64,
+        new_batch_id: H256,
+    ) -> anyhow::Result<H256>;
+
+    /// 取代该证书的更正批次ID, 未被取代时为空
+    async fn superseded_by(
+        &self,
+        issuer: Address,
+        batch_id: H256,
+        index: u64,
+    ) -> anyhow::Result<Option<H256>>;
+
+    /// 更新零知识展示所对照的撤销累加器根, 没有链上合约的后端无需发布
+    async fn publish_revocation_root(&self, _root: H256) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// 只读执行 `verifyData`, 不产生交易
+    async fn verify(&self, call: VerifyDataCall) -> anyhow::Result<bool>;
+
+    /// 显式开启时在链上记录一次验证结果
+    async fn attest_verification(
+        &self,
+        signer: &Signer,
+        call: VerifyDataCall,
+    ) -> anyhow::Result<Attestation>;
+}
+
+/// 锚定的批次及其来源是否经过证明
+#[derive(Debug, Clone)]
+pub struct FetchedBatch {
+    pub issuer: Address,
+    // 取自锚定事件, 不能由承诺重新计算
+    pub batch_id: H256,
+    pub commitments: Vec<u8>,
+    pub anchor: AnchorStatus,
+}
+
+/// 撤销原因, 链上以原因码记录, 0 表示未撤销
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RevocationReason {
+    /// 信息录入错误
+    ClericalError = 1,
+    /// 学位被撤销
+    Rescinded = 2,
+    /// 已由更正后的证书取代
+    Superseded = 3,
+    Other = 255,
+}
+
+impl RevocationReason {
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Self::ClericalError),
+            2 => Some(Self::Rescinded),
+            3 => Some(Self::Superseded),
+            255 => Some(Self::Other),
+            _ => None,
+        }
+    }
+}
+
+/// 链上验证记录
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+    pub tx_hash: H256,
+    pub block_number: u64,
+    pub request_hash: H256,
+    pub verified: bool,
+}
+
+/// 通过 JSON-RPC 访问以太坊上的 CertificateVerifier
+pub struct EthereumLedger {
+    web3: Web3<Http>,
+    verifier: CertificateVerifier,
+    // 配置检查点后对照可信区块头证明锚定数据
+    headers: Option<HeaderChain>,
+}
+
+impl EthereumLedger {
+    pub fn new(web3: Web3<Http>, verifier: CertificateVerifier) -> Self {
+        Self {
+            web3,
+            verifier,
+            headers: None,
+        }
+    }
+
+    pub fn with_header_chain(mut self, headers: Option<HeaderChain>) -> Self {
+        self.headers = headers;
+        self
+    }
+}
+
+#[async_trait]
+impl Ledger for EthereumLedger {
+    async fn anchor_batch(
+        &self,
+        signer: &Signer,
+        schema_hash: H256,
+        commitments: Vec<u8>,
+        students: u64,
+    ) -> anyhow::Result<Anchor> {
+        anchor_commitments(&self.verifier, signer, schema_hash, commitments, students).await
+    }
+
+    async fn update_batch(
+        &self,
+        signer: &Signer,
+        parent_batch_id: H256,
+        schema_hash: H256,
+        commitments: Vec<u8>,
+        delta: Vec<u8>,
+        students: u64,
+    ) -> anyhow::Result<Anchor> {
+        anchor_update(
+            &self.verifier,
+            signer,
+            parent_batch_id,
+            schema_hash,
+            commitments,
+            delta,
+            students,
+        )
+        .await
+    }
+
+    async fn fetch_batch(&self, tx_hash: &str) -> anyhow::Result<FetchedBatch> {
+        if let Some(headers) = &self.headers {
+            match prove_anchor(&self.web3, &self.verifier, headers, tx_hash).await {
+                Ok(batch) => {
+                    return Ok(FetchedBatch {
+                        issuer: batch.issuer,
+                        batch_id: batch.batch_id,
+                        commitments: batch.commitments,
+                        anchor: AnchorStatus::Proven {
+                            block_number: batch.block_number,
+                            block_hash: batch.block_hash,
+                        },
+                    })
+                }
+                Err(e) if headers.required() => return Err(e),
+                Err(e) => warn!("交易 {tx_hash} 的锚定无法证明, 使用节点返回的数据: {e}"),
+            }
+        }
+
+        load_batch(&self.web3, &self.verifier, tx_hash).await
+    }
+
+    async fn anchor_info(&self, tx_hash: &str) -> anyhow::Result<AnchorInfo> {
+        load_anchor_info(&self.web3, tx_hash).await
+    }
+
+    async fn revoke(
+        &self,
+        signer: &Signer,
+        batch_id: H256,
+        index: u64,
+        reason: RevocationReason,
+    ) -> anyhow::Result<H256> {
+        let tx_hash = self
+            .verifier
+            .revoke_certificate(batch_id, index, reason.code(), signer)
+            .await?;
+        info!("证书已撤销, 批次: {batch_id:?}, 槽位: {index}, 原因: {reason:?}, 交易: {tx_hash:?}");
+        Ok(tx_hash)
+    }
+
+    async fn revocation(
+        &self,
+        issuer: Address,
+        batch_id: H256,
+        index: u64,
+    ) -> anyhow::Result<Option<RevocationReason>> {
+        // 未知原因码按 Other 处理, 已撤销的证书不能因此被视为有效
+        Ok(self
+            .verifier
+            .get_revocation(issuer, batch_id, index)
+            .await?
+            .map(|code| RevocationReason::from_code(code).unwrap_or(RevocationReason::Other)))
+    }
+
+    async fn supersede(
+        &self,
+        signer: &Signer,
+        batch_id: H256,
+        index: u64,
+        new_batch_id: H256,
+    ) -> anyhow::Result<H256> {
+        let tx_hash = self
+            .verifier
+            .supersede_certificate(batch_id, index, new_batch_id, signer)
+            .await?;
+        info!(
+            "证书已被取代, 批次: {batch_id:?}, 槽位: {index}, 更正批次: {new_batch_id:?}, 交易: {tx_hash:?}"
+        );
+        Ok(tx_hash)
+    }
+
+    async fn superseded_by(
+        &self,
+        issuer: Address,
+        batch_id: H256,
+        index: u64,
+    ) -> anyhow::Result<Option<H256>> {
+        self.verifier
+            .get_supersession(issuer, batch_id, index)
+            .await
+    }
+
+    async fn publish_revocation_root(&self, root: H256) -> anyhow::Result<()> {
+        if self.verifier.revocation_root().await? == root {
+            return Ok(());
+        }
+        // 合约只接受部署账户的更新
+        let tx_hash = self
+            .verifier
+            .update_revocation_root(root, wallet()?.default_signer())
+            .await?;
+        info!("撤销累加器根已更新为 {root:?}, 交易: {tx_hash:?}");
+        Ok(())
+    }
+
+    async fn verify(&self, call: VerifyDataCall) -> anyhow::Result<bool> {
+        match *VERIFY_BACKEND {
+            VerifyBackend::Evm => local_verify_data(call).await,
+            VerifyBackend::Node => {
+                let from = wallet()?.default_signer().address();
+                self.verifier.verify_data(call, from).await
+            }
+        }
+    }
+
+    async fn attest_verification(
+        &self,
+        signer: &Signer,
+        call: VerifyDataCall,
+    ) -> anyhow::Result<Attestation> {
+        let (receipt, event) = self.verifier.attest_verification(call, signer).await?;
+        Ok(Attestation {
+            tx_hash: receipt.transaction_hash,
+            block_number: receipt.block_number.unwrap_or_default().as_u64(),
+            request_hash: event.request_hash,
+            verified: event.verified,
+        })
+    }
+}
+
+struct StoredBatch {
+    issuer: Address,
+    batch_id: H256,
+    commitments: Vec<u8>,
+    students: u64,
+    // 锚定顺序, 相当于区块号
+    sequence: u64,
+    anchored_at: u64,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    // 锚定交易哈希 -> 批次
+    batches: HashMap<H256, StoredBatch>,
+    revoked: HashMap<(Address, H256, u64), RevocationReason>,
+    // 被取代的证书 -> 更正批次
+    superseded: HashMap<(Address, H256, u64), H256>,
+    attestations: Vec<Attestation>,
+}
+
+/// 进程内账本, 规则与合约一致, 用于离线开发和测试
+pub struct MemoryLedger {
+    state: Mutex<MemoryState>,
+    verifier: fn(VerifyDataCall) -> anyhow::Result<bool>,
+}
+
+impl MemoryState {
+    fn is_anchored(&self, issuer: Address, batch_id: H256) -> bool {
+        self.batches
+            .values()
+            .any(|batch| batch.issuer == issuer && batch.batch_id == batch_id)
+    }
+
+    fn revoke(
+        &mut self,
+        issuer: Address,
+        batch_id: H256,
+        index: u64,
+        reason: RevocationReason,
+    ) -> anyhow::Result<()> {
+        let Some(batch) = self
+            .batches
+            .values()
+            .find(|batch| batch.issuer == issuer && batch.batch_id == batch_id)
+        else {
+            bail!("Batch not found");
+        };
+        // 槽位为 shard * MAX_DEGREE + index, 分片依次填满, 只有最后一个分片不满,
+        // 分片在批次之内且槽位在该分片的学生之内即槽位小于学生数
+        if index >= batch.students {
+            bail!("Index outside batch");
+        }
+        if self.revoked.contains_key(&(issuer, batch_id, index)) {
+            bail!("Certificate already revoked");
+        }
+        self.revoked.insert((issuer, batch_id, index), reason);
+        Ok(())
+    }
+}
+
+impl MemoryLedger {
+    /// 使用本地 EVM 中部署的验证合约
+    pub fn new() -> Self {
+        Self::with_verifier(local_verify_data_blocking)
+    }
+
+    pub fn with_verifier(verifier: fn(VerifyDataCall) -> anyhow::Result<bool>) -> Self {
+        Self {
+            state: Mutex::new(MemoryState::default()),
+            verifier,
+        }
+    }
+
+    pub fn attestations(&self) -> Vec<Attestation> {
+        self.state.lock().unwrap().attestations.clone()
+    }
+}
+
+impl Default for MemoryLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Ledger for MemoryLedger {
+    async fn anchor_batch(
+        &self,
+        signer: &Signer,
+        _schema_hash: H256,
+        commitments: Vec<u8>,
+        students: u64,
+    ) -> anyhow::Result<Anchor> {
+        if commitments.is_empty() || !commitments.len().is_multiple_of(G1_SIZE) {
+            bail!("Commitment length must be multiple of G1_SIZE");
+        }
+        if students == 0 {
+            bail!("Batch has no students");
+        }
+
+        let issuer = signer.address();
+        let batch_id = new_batch_id(&commitments);
+        let tx_hash = H256::from_slice(&Keccak256::digest(
+            [issuer.as_bytes(), batch_id.as_bytes()].concat(),
+        ));
+
+        let mut state = self.state.lock().unwrap();
+        if state.batches.contains_key(&tx_hash) {
+            bail!("Batch already anchored");
+        }
+        let sequence = state.batches.len() as u64 + 1;
+        state.batches.insert(
+            tx_hash,
+            StoredBatch {
+                issuer,
+                batch_id,
+                commitments,
+                students,
+                sequence,
+                anchored_at: Utc::now().timestamp() as u64,
+            },
+        );
+
+        Ok(Anchor {
+            tx_hash,
+            issuer,
+            batch_id,
+            inclusion: None,
+        })
+    }
+
+    async fn update_batch(
+        &self,
+        signer: &Signer,
+        parent_batch_id: H256,
+        schema_hash: H256,
+        commitments: Vec<u8>,
+        delta: Vec<u8>,
+        students: u64,
+    ) -> anyhow::Result<Anchor> {
+        if !self
+            .state
+            .lock()
+            .unwrap()
+            .is_anchored(signer.address(), parent_batch_id)
+        {
+            bail!("Parent batch not found");
+        }
+        if delta.len() != commitments.len() {
+            bail!("Delta length must match commitments");
+        }
+        self.anchor_batch(signer, schema_hash, commitments, students)
+            .await
+    }
+
+    async fn fetch_batch(&self, tx_hash: &str) -> anyhow::Result<FetchedBatch> {
+        let hash: H256 = tx_hash.parse()?;
+        self.state
+            .lock()
+            .unwrap()
+            .batches
+            .get(&hash)
+            .map(|batch| FetchedBatch {
+                issuer: batch.issuer,
+                batch_id: batch.batch_id,
+                commitments: batch.commitments.clone(),
+                anchor: AnchorStatus::Asserted,
+            })
+            .ok_or_else(|| anyhow!("Batch not found"))
+    }
+
+    async fn anchor_info(&self, tx_hash: &str) -> anyhow::Result<AnchorInfo> {
+        let hash: H256 = tx_hash.parse()?;
+        let state = self.state.lock().unwrap();
+        let batch = state
+            .batches
+            .get(&hash)
+            .ok_or_else(|| anyhow!("Batch not found"))?;
+        Ok(AnchorInfo {
+            block_number: batch.sequence,
+            timestamp: batch.anchored_at,
+            sender: batch.issuer,
+            confirmations: state.batches.len() as u64 - batch.sequence + 1,
+        })
+    }
+
+    async fn revoke(
+        &self,
+        signer: &Signer,
+        batch_id: H256,
+        index: u64,
+        reason: RevocationReason,
+    ) -> anyhow::Result<H256> {
+        let issuer = signer.address();
+        self.state
+            .lock()
+            .unwrap()
+            .revoke(issuer, batch_id, index, reason)?;
+        Ok(H256::from_slice(&Keccak256::digest(
+            [issuer.as_bytes(), batch_id.as_bytes(), &index.to_be_bytes()].concat(),
+        )))
+    }
+
+    async fn revocation(
+        &self,
+        issuer: Address,
+        batch_id: H256,
+        index: u64,
+    ) -> anyhow::Result<Option<RevocationReason>> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .revoked
+            .get(&(issuer, batch_id, index))
+            .copied())
+    }
+
+    async fn supersede(
+        &self,
+        signer: &Signer,
+        batch_id: H256,
+        index: u64,
+        new_batch_id: H256,
+    ) -> anyhow::Result<H256> {
+        let issuer = signer.address();
+        let mut state = self.state.lock().unwrap();
+        if new_batch_id == batch_id {
+            bail!("Certificate cannot supersede itself");
+        }
+        if !state.is_anchored(issuer, new_batch_id) {
+            bail!("Correction batch not found");
+        }
+        state.revoke(issuer, batch_id, index, RevocationReason::Superseded)?;
+        state
+            .superseded
+            .insert((issuer, batch_id, index), new_batch_id);
+        Ok(H256::from_slice(&Keccak256::digest(
+            [
+                issuer.as_bytes(),
+                batch_id.as_bytes(),
+                &index.to_be_bytes(),
+                new_batch_id.as_bytes(),
+            ]
+            .concat(),
+        )))
+    }
+
+    async fn superseded_by(
+        &self,
+        issuer: Address,
+        batch_id: H256,
+        index: u64,
+    ) -> anyhow::Result<Option<H256>> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .superseded
+            .get(&(issuer, batch_id, index))
+            .copied())
+    }
+
+    async fn verify(&self, call: VerifyDataCall) -> anyhow::Result<bool> {
+        spawn_verify(self.verifier, call).await
+    }
+
+    async fn attest_verification(
+        &self,
+        signer: &Signer,
+        call: VerifyDataCall,
+    ) -> anyhow::Result<Attestation> {
+        let verified = spawn_verify(self.verifier, call.clone()).await?;
+        let request_hash = H256::from_slice(&Keccak256::digest(
+            CertificateVerifier::encode_attest_verification(call),
+        ));
+
+        let mut state = self.state.lock().unwrap();
+        let block_number = state.attestations.len() as u64 + 1;
+        let tx_hash = H256::from_slice(&Keccak256::digest(
+            [
+                signer.address().as_bytes(),
+                &block_number.to_be_bytes(),
+                request_hash.as_bytes(),
+            ]
+            .concat(),
+        ));
+        let attestation = Attestation {
+            tx_hash,
+            block_number,
+            request_hash,
+            verified,
+        };
+        state.attestations.push(attestation.clone());
+        Ok(attestation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::shplonk::MAX_DEGREE;
+
+    fn signer(byte: u64) -> Signer {
+        Signer::Node(Address::from_low_u64_be(byte))
+    }
+
+    #[tokio::test]
+    async fn test_memory_ledger() {
+        let ledger =
+            MemoryLedger::with_verifier(|call| Ok(call.json_data.len().is_multiple_of(32)));
+        let commitments = vec![7; 2 * G1_SIZE];
+
+        let anchor = ledger
+            .anchor_batch(&signer(1), H256::zero(), commitments.clone(), 64)
+            .await
+            .unwrap();
+        assert_eq!(anchor.issuer, Address::from_low_u64_be(1));
+        let fetched = ledger
+            .fetch_batch(&format!("{:?}", anchor.tx_hash))
+            .await
+            .unwrap();
+        assert_eq!(fetched.commitments, commitments);
+        assert_eq!(
+            (fetched.issuer, fetched.batch_id),
+            (anchor.issuer, anchor.batch_id)
+        );
+        let info = ledger
+            .anchor_info(&format!("{:?}", anchor.tx_hash))
+            .await
+            .unwrap();
+        assert_eq!((info.block_number, info.sender), (1, anchor.issuer));
+
+        // 内容相同的批次可以再次签发, 得到新的批次ID
+        let again = ledger
+            .anchor_batch(&signer(1), H256::zero(), commitments.clone(), 64)
+            .await
+            .unwrap();
+        assert_ne!(again.batch_id, anchor.batch_id);
+        assert!(ledger
+            .anchor_batch(&signer(2), H256::zero(), commitments, 64)
+            .await
+            .is_ok());
+        assert!(ledger
+            .anchor_batch(&signer(1), H256::zero(), vec![1; 95], 64)
+            .await
+            .is_err());
+
+        // 只能在自己锚定的批次上追加或更新
+        let updated = vec![9; 2 * G1_SIZE];
+        assert!(ledger
+            .update_batch(
+                &signer(3),
+                anchor.batch_id,
+                H256::zero(),
+                updated.clone(),
+                vec![2; 2 * G1_SIZE],
+                65
+            )
+            .await
+            .is_err());
+        assert!(ledger
+            .update_batch(
+                &signer(1),
+                anchor.batch_id,
+                H256::zero(),
+                updated.clone(),
+                vec![2; G1_SIZE],
+                65
+            )
+            .await
+            .is_err());
+        let update = ledger
+            .update_batch(
+                &signer(1),
+                anchor.batch_id,
+                H256::zero(),
+                updated.clone(),
+                vec![2; 2 * G1_SIZE],
+                65,
+            )
+            .await
+            .unwrap();
+        assert_ne!(update.batch_id, anchor.batch_id);
+        assert_eq!(
+            ledger
+                .fetch_batch(&format!("{:?}", update.tx_hash))
+                .await
+                .unwrap()
+                .commitments,
+            updated
+        );
+
+        assert_eq!(
+            ledger
+                .revocation(anchor.issuer, anchor.batch_id, 42)
+                .await
+                .unwrap(),
+            None
+        );
+        // 只有发证学校能撤销自己批次中的证书
+        assert!(ledger
+            .revoke(&signer(3), anchor.batch_id, 42, RevocationReason::Rescinded)
+            .await
+            .is_err());
+        ledger
+            .revoke(&signer(1), anchor.batch_id, 42, RevocationReason::Rescinded)
+            .await
+            .unwrap();
+        assert_eq!(
+            ledger
+                .revocation(anchor.issuer, anchor.batch_id, 42)
+                .await
+                .unwrap(),
+            Some(RevocationReason::Rescinded)
+        );
+        assert!(ledger
+            .revoke(&signer(1), anchor.batch_id, 42, RevocationReason::Other)
+            .await
+            .is_err());
+        // 槽位须在批次的学生之内: 64 个学生都在第一个分片
+        for index in [64, MAX_DEGREE as u64, (MAX_DEGREE * 64) as u64] {
+            assert!(ledger
+                .revoke(&signer(1), anchor.batch_id, index, RevocationReason::Other)
+                .await
+                .is_err());
+        }
+        assert_eq!(
+            ledger
+                .revocation(anchor.issuer, anchor.batch_id, 43)
+                .await
+                .unwrap(),
+            None
+        );
+
+        // 更正批次取代旧证书, 旧证书同时以 Superseded 撤销
+        let correction = ledger
+            .anchor_batch(&signer(1), H256::zero(), vec![8; G1_SIZE], 64)
+            .await
+            .unwrap();
+        assert!(ledger
+            .supersede(&signer(2), anchor.batch_id, 43, correction.batch_id)
+            .await
+            .is_err());
+        assert!(ledger
+            .supersede(&signer(1), anchor.batch_id, 43, anchor.batch_id)
+            .await
+            .is_err());
+        ledger
+            .supersede(&signer(1), anchor.batch_id, 43, correction.batch_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            ledger
+                .revocation(anchor.issuer, anchor.batch_id, 43)
+                .await
+                .unwrap(),
+            Some(RevocationReason::Superseded)
+        );
+        assert_eq!(
+            ledger
+                .superseded_by(anchor.issuer, anchor.batch_id, 43)
+                .await
+                .unwrap(),
+            Some(correction.batch_id)
+        );
+        assert_eq!(
+            ledger
+                .superseded_by(anchor.issuer, anchor.batch_id, 42)
+                .await
+                .unwrap(),
+            None
+        );
+
+        let call = VerifyDataCall {
+            json_data: vec![0; 32],
+            commitment: fetched.commitments.clone(),
+            ..Default::default()
+        };
+        assert!(ledger.verify(call.clone()).await.unwrap());
+        // 只读验证不留下记录
+        assert!(ledger.attestations().is_empty());
+
+        let first = ledger
+            .attest_verification(&signer(3), call.clone())
+            .await
+            .unwrap();
+        let second = ledger.attest_verification(&signer(3), call).await.unwrap();
+        assert!(first.verified);
+        assert_eq!(first.request_hash, second.request_hash);
+        assert_ne!(first.tx_hash, second.tx_hash);
+        assert_eq!(ledger.attestations(), vec![first, second]);
+    }
+}
@@ -1,11 +1,29 @@
+pub mod anchor;
+pub mod batches;
+pub mod bindings;
+pub mod cache;
 pub mod certificate;
 pub mod circuit;
 pub mod commit;
+pub mod config;
+pub mod deployment;
+pub mod erasure;
 pub mod ethereum;
+pub mod evm;
+pub mod image;
+pub mod inclusion;
 pub mod ipfs;
+pub mod issuers;
+pub mod ledger;
+pub mod network;
 pub mod poseidon;
+pub mod revocation;
 pub mod shplonk;
 mod shplonk_inner;
+pub mod shredding;
+pub mod transaction;
+pub mod translog;
 mod util;
+pub mod wallet;
 
 pub use util::*;
@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context};
+use log::info;
+use serde::Deserialize;
+use web3::transports::Http;
+use web3::Web3;
+
+use crate::services::bindings::{CertificateVerifier, IssuerRegistry};
+use crate::services::deployment::load_contract_address;
+use crate::services::ethereum::connect_web3;
+use crate::services::inclusion::{HeaderChain, ProofConfig};
+use crate::services::ledger::{EthereumLedger, Ledger};
+use crate::services::translog::TransparencyLog;
+
+// 网络配置文件, 未配置时使用 ETHEREUM_NODE_URL 与 CONTRACT_ADDRESS
+const DEFAULT_NETWORKS_CONFIG: &str = "networks.json";
+
+/// 锚定后端
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Backend {
+    #[default]
+    Ethereum,
+    /// 本地透明日志, 供无法使用区块链的机构, 链ID仅作为证书中的标识
+    TransparencyLog {
+        path: String,
+        // 树头签名私钥所在的环境变量
+        #[serde(default = "default_log_key_env")]
+        key_env: String,
+    },
+}
+
+fn default_log_key_env() -> String {
+    "TRANSPARENCY_LOG_KEY".to_string()
+}
+
+/// 单个网络: 链ID、节点地址、合约地址与确认数
+#[derive(Debug, Clone, Deserialize)]
+pub struct NetworkConfig {
+    pub name: String,
+    pub chain_id: u64,
+    #[serde(default)]
+    pub backend: Backend,
+    // 透明日志后端不需要节点
+    #[serde(default)]
+    pub rpc_url: String,
+    // 未配置时读取部署记录
+    #[serde(default)]
+    pub contract_address: Option<String>,
+    // 未配置时使用 TX_CONFIRMATIONS
+    #[serde(default)]
+    pub confirmations: Option<u64>,
+    // 配置可信检查点后对锚定数据做包含证明
+    #[serde(default)]
+    pub proof: Option<ProofConfig>,
+    // 发证方登记合约地址, 配置后拒绝未登记或已停用密钥的锚定
+    #[serde(default)]
+    pub issuer_registry: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct NetworksConfig {
+    // 上传未指定链时使用的链ID, 默认取第一个网络
+    pub default: Option<u64>,
+    pub networks: Vec<NetworkConfig>,
+}
+
+impl NetworksConfig {
+    /// 读取 NETWORKS_CONFIG, 文件不存在时由 ETHEREUM_NODE_URL 生成单网络配置
+    pub async fn load() -> anyhow::Result<Self> {
+        let path = std::env::var("NETWORKS_CONFIG")
+            .unwrap_or_else(|_| DEFAULT_NETWORKS_CONFIG.to_string());
+        if let Ok(bytes) = std::fs::read(&path) {
+            let config: Self = serde_json::from_slice(&bytes)
+                .with_context(|| format!("invalid networks config {path}"))?;
+            config.default_network()?;
+            return Ok(config);
+        }
+
+        let rpc_url = std::env::var("ETHEREUM_NODE_URL")
+            .unwrap_or_else(|_| "http://127.0.0.1:8545".to_string());
+        let chain_id = connect_web3(&rpc_url)?.eth().chain_id().await?.as_u64();
+        Ok(Self {
+            default: Some(chain_id),
+            networks: vec![NetworkConfig {
+                name: "default".to_string(),
+                chain_id,
+                backend: Backend::Ethereum,
+                rpc_url,
+                contract_address: std::env::var("CONTRACT_ADDRESS").ok(),
+                confirmations: None,
+                proof: ProofConfig::from_env()?,
+                issuer_registry: std::env::var("ISSUER_REGISTRY_ADDRESS").ok(),
+            }],
+        })
+    }
+
+    pub fn network(&self, chain_id: u64) -> anyhow::Result<&NetworkConfig> {
+        self.networks
+            .iter()
+            .find(|network| network.chain_id == chain_id)
+            .ok_or_else(|| anyhow!("chain {chain_id} is not configured"))
+    }
+
+    pub fn default_network(&self) -> anyhow::Result<&NetworkConfig> {
+        match self.default {
+            Some(chain_id) => self.network(chain_id),
+            None => self
+                .networks
+                .first()
+                .ok_or_else(|| anyhow!("no network configured")),
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// 连接节点并确认链ID与配置一致
+    pub async fn connect(&self) -> anyhow::Result<Web3<Http>> {
+        if let Backend::TransparencyLog { .. } = self.backend {
+            bail!("network {} is a transparency log", self.name);
+        }
+        let web3 = connect_web3(&self.rpc_url)?;
+        let chain_id = web3.eth().chain_id().await?.as_u64();
+        if chain_id != self.chain_id {
+            bail!(
+                "network {} expects chain {} but {} reports chain {chain_id}",
+                self.name,
+                self.chain_id,
+                self.rpc_url
+            );
+        }
+        Ok(web3)
+    }
+}
+
+/// 已连接的网络
+#[derive(Clone)]
+pub struct Network {
+    pub name: String,
+    pub chain_id: u64,
+    pub ledger: Arc<dyn Ledger>,
+    // 透明日志后端, 提供树头与一致性证明
+    pub log: Option<Arc<TransparencyLog>>,
+    // 发证方登记合约, 未配置时不校验锚定账户
+    pub issuer_registry: Option<Arc<IssuerRegistry>>,
+}
+
+/// 链ID -> 网络, 证书按其中的链ID解析到对应网络
+pub struct NetworkRegistry {
+    networks: BTreeMap<u64, Network>,
+    default_chain_id: u64,
+}
+
+impl NetworkRegistry {
+    pub fn new(default_chain_id: u64) -> Self {
+        Self {
+            networks: BTreeMap::new(),
+            default_chain_id,
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, chain_id: u64, ledger: Arc<dyn Ledger>) {
+        self.networks.insert(
+            chain_id,
+            Network {
+                name: name.to_string(),
+                chain_id,
+                ledger,
+                log: None,
+                issuer_registry: None,
+            },
+        );
+    }
+
+    pub fn insert_log(&mut self, name: &str, chain_id: u64, log: Arc<TransparencyLog>) {
+        self.networks.insert(
+            chain_id,
+            Network {
+                name: name.to_string(),
+                chain_id,
+                ledger: log.clone(),
+                log: Some(log),
+                issuer_registry: None,
+            },
+        );
+    }
+
+    pub fn networks(&self) -> impl Iterator<Item = &Network> {
+        self.networks.values()
+    }
+
+    pub fn set_issuer_registry(&mut self, chain_id: u64, registry: Arc<IssuerRegistry>) {
+        if let Some(network) = self.networks.get_mut(&chain_id) {
+            network.issuer_registry = Some(registry);
+        }
+    }
+
+    /// 连接所有配置的网络并校验各自的合约部署
+    pub async fn connect(config: &NetworksConfig) -> anyhow::Result<Self> {
+        let mut registry = Self::new(config.default_network()?.chain_id);
+        for network in &config.networks {
+            if let Backend::TransparencyLog { path, key_env } = &network.backend {
+                let log = TransparencyLog::open_with_key_env(path, key_env)
+                    .with_context(|| format!("network {}", network.name))?;
+                registry.insert_log(&network.name, network.chain_id, Arc::new(log));
+                continue;
+            }
+
+            let web3 = network.connect().await?;
+            let address = load_contract_address(&web3, network.contract_address.as_deref())
+                .await
+                .with_context(|| format!("network {}", network.name))?;
+            let verifier =
+                CertificateVerifier::new(web3.eth(), address.parse().context("invalid address")?)?
+                    .with_confirmations(network.confirmations);
+            info!(
+                "已连接网络 {}, 链ID: {}, 合约地址: {address}",
+                network.name, network.chain_id
+            );
+            let issuer_registry = match &network.issuer_registry {
+                Some(address) => {
+                    info!("网络 {} 的发证方登记合约: {address}", network.name);
+                    Some(Arc::new(
+                        IssuerRegistry::new(
+                            web3.eth(),
+                            address.parse().context("invalid issuer registry address")?,
+                        )?
+                        .with_confirmations(network.confirmations),
+                    ))
+                }
+                None => None,
+            };
+            registry.insert(
+                &network.name,
+                network.chain_id,
+                Arc::new(
+                    EthereumLedger::new(web3, verifier)
+                        .with_header_chain(network.proof.clone().map(HeaderChain::new)),
+                ),
+            );
+            if let Some(issuer_registry) = issuer_registry {
+                registry.set_issuer_registry(network.chain_id, issuer_registry);
+            }
+        }
+        Ok(registry)
+    }
+
+    /// 按链ID解析网络, 未指定(或旧证书中为0)时使用默认网络
+    pub fn network(&self, chain_id: Option<u64>) -> anyhow::Result<&Network> {
+        let chain_id = chain_id
+            .filter(|chain_id| *chain_id != 0)
+            .unwrap_or(self.default_chain_id);
+        self.networks
+            .get(&chain_id)
+            .ok_or_else(|| anyhow!("chain {chain_id} is not configured"))
+    }
+
+    pub fn default_network(&self) -> anyhow::Result<&Network> {
+        self.network(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::ledger::MemoryLedger;
+
+    #[test]
+    fn test_network_registry() {
+        let config: NetworksConfig = serde_json::from_value(serde_json::json!({
+            "default": 10,
+            "networks": [
+                { "name": "consortium", "chain_id": 2025, "rpc_url": "http://10.0.0.1:8545", "confirmations": 1 },
+                { "name": "optimism", "chain_id": 10, "rpc_url": "https://mainnet.optimism.io", "confirmations": 12 },
+                { "name": "partner-log", "chain_id": 900001, "backend": { "type": "transparency_log", "path": "translog/partner.jsonl" } }
+            ]
+        }))
+        .unwrap();
+        assert_eq!(config.default_network().unwrap().name, "optimism");
+        assert!(config.network(1).is_err());
+        assert!(matches!(
+            &config.network(900001).unwrap().backend,
+            Backend::TransparencyLog { key_env, .. } if key_env == "TRANSPARENCY_LOG_KEY"
+        ));
+
+        let mut registry = NetworkRegistry::new(10);
+        for network in &config.networks {
+            registry.insert(
+                &network.name,
+                network.chain_id,
+                Arc::new(MemoryLedger::default()),
+            );
+        }
+        assert_eq!(registry.network(Some(2025)).unwrap().name, "consortium");
+        // 旧证书没有链ID
+        assert_eq!(registry.network(Some(0)).unwrap().chain_id, 10);
+        assert_eq!(registry.default_network().unwrap().chain_id, 10);
+        assert!(registry.network(Some(1)).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{ensure, Context};
+use log::{info, warn};
+use serde_json::Value;
+use snark_verifier_sdk::snark_verifier::halo2_base::halo2_proofs::halo2curves::bn256::Fr;
+use web3::types::H256;
+
+use crate::services::circuit::record_fields;
+use crate::services::evm::set_local_revocation_root;
+use crate::services::network::NetworkRegistry;
+use crate::services::poseidon::poseidon;
+
+/// 撤销累加器的 Merkle 深度, 含哨兵叶子最多 2^16 个叶子
+pub const REVOCATION_TREE_DEPTH: usize = 16;
+
+// 哨兵叶子 (0, KEY_MAX) 覆盖整个键空间, 撤销键取值于 (0, KEY_MAX)
+const KEY_MAX: u128 = u128::MAX;
+
+// 累加器文件, 按插入顺序保存已撤销的键
+const DEFAULT_REVOCATION_ACCUMULATOR: &str = "revocations/accumulator.json";
+
+pub fn fr_from_u128(value: u128) -> Fr {
+    Fr::from_raw([value as u64, (value >> 64) as u64, 0, 0])
+}
+
+/// 大端 32 字节, 与 EVM 校验器 calldata 中公开输入的编码一致
+pub fn fr_to_h256(value: Fr) -> H256 {
+    let mut bytes = value.to_bytes();
+    bytes.reverse();
+    H256(bytes)
+}
+
+/// 记录的撤销键: 记录字段的 Poseidon 哈希取低 128 位.
+/// 字段与电路中一样按 record_fields 补零, 撤销时与证明时得到同一个键;
+/// 电路中对哈希做规范的 hi || lo 分解, 证明方无法换用其他键
+pub fn revocation_key(record: &BTreeMap<String, Value>) -> u128 {
+    let hash = poseidon(&record_fields(record)).to_bytes();
+    u128::from_le_bytes(hash[..16].try_into().unwrap())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Leaf {
+    key: u128,
+    // 有序链表中的下一个键
+    next: u128,
+}
+
+impl Leaf {
+    fn hash(&self) -> Fr {
+        poseidon(&[fr_from_u128(self.key), fr_from_u128(self.next)])
+    }
+}
+
+/// 非成员证明: 有序链表中夹住该键的叶子 `key < x < next` 及其 Merkle 路径
+#[derive(Debug, Clone)]
+pub struct NonMembershipWitness {
+    pub low_key: u128,
+    pub next_key: u128,
+    pub leaf_index: usize,
+    pub siblings: Vec<Fr>,
+}
+
+/// 已撤销记录的有序 Poseidon Merkle 树 (indexed Merkle tree).
+/// 叶子按插入顺序排列, 每个叶子记录其后继键, 非成员证明只需一条路径
+#[derive(Debug, Clone)]
+pub struct RevocationTree {
+    leaves: Vec<Leaf>,
+    path: Option<PathBuf>,
+}
+
+impl Default for RevocationTree {
+    fn default() -> Self {
+        Self {
+            leaves: vec![Leaf {
+                key: 0,
+                next: KEY_MAX,
+            }],
+            path: None,
+        }
+    }
+}
+
+fn zero_hashes() -> Vec<Fr> {
+    let mut zeros = vec![Fr::zero()];
+    for depth in 0..REVOCATION_TREE_DEPTH {
+        zeros.push(poseidon(&[zeros[depth], zeros[depth]]));
+    }
+    zeros
+}
+
+impl RevocationTree {
+    /// 读取累加器文件, 不存在时创建空树
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut tree = Self::default();
+        if let Ok(bytes) = std::fs::read(&path) {
+            let keys: Vec<String> = serde_json::from_slice(&bytes)
+                .with_context(|| format!("invalid revocation accumulator {}", path.display()))?;
+            for key in keys {
+                tree.insert(u128::from_str_radix(&key, 16).context("invalid revocation key")?)?;
+            }
+        }
+        info!(
+            "已加载撤销累加器 {}, 撤销数: {}",
+            path.display(),
+            tree.leaves.len() - 1
+        );
+        tree.path = Some(path);
+        Ok(tree)
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let keys = self.leaves[1..]
+            .iter()
+            .map(|leaf| format!("{:032x}", leaf.key))
+            .collect::<Vec<_>>();
+        std::fs::write(path, serde_json::to_vec_pretty(&keys)?)?;
+        Ok(())
+    }
+
+    pub fn contains(&self, key: u128) -> bool {
+        self.leaves.iter().any(|leaf| leaf.key == key)
+    }
+
+    pub fn is_revoked(&self, record: &BTreeMap<String, Value>) -> bool {
+        self.contains(revocation_key(record))
+    }
+
+    /// 插入撤销键, 已存在时返回 false
+    fn insert(&mut self, key: u128) -> anyhow::Result<bool> {
+        ensure!(key != 0 && key != KEY_MAX, "revocation key out of range");
+        if self.contains(key) {
+            return Ok(false);
+        }
+        ensure!(
+            self.leaves.len() < 1 << REVOCATION_TREE_DEPTH,
+            "revocation accumulator is full"
+        );
+        let low = self.low_leaf(key).expect("sentinel covers every key");
+        let next = self.leaves[low].next;
+        self.leaves[low].next = key;
+        self.leaves.push(Leaf { key, next });
+        Ok(true)
+    }
+
+    /// 撤销记录并写盘, 返回新增的撤销数
+    pub fn revoke<'a>(
+        &mut self,
+        records: impl IntoIterator<Item = &'a BTreeMap<String, Value>>,
+    ) -> anyhow::Result<usize> {
+        let mut inserted = 0;
+        for record in records {
+            if self.insert(revocation_key(record))? {
+                inserted += 1;
+            }
+        }
+        self.save()?;
+        Ok(inserted)
+    }
+
+    fn low_leaf(&self, key: u128) -> Option<usize> {
+        self.leaves
+            .iter()
+            .position(|leaf| leaf.key < key && key < leaf.next)
+    }
+
+    // 自叶子层向上的各层节点, 缺失的节点取同层空子树哈希
+    fn levels(&self) -> Vec<Vec<Fr>> {
+        let zeros = zero_hashes();
+        let mut levels = vec![self.leaves.iter().map(Leaf::hash).collect::<Vec<_>>()];
+        for depth in 0..REVOCATION_TREE_DEPTH {
+            let level = levels[depth]
+                .chunks(2)
+                .map(|pair| poseidon(&[pair[0], pair.get(1).copied().unwrap_or(zeros[depth])]))
+                .collect();
+            levels.push(level);
+        }
+        levels
+    }
+
+    pub fn root(&self) -> Fr {
+        self.levels()[REVOCATION_TREE_DEPTH][0]
+    }
+
+    /// 未撤销键的非成员证明, 已撤销时为空
+    pub fn non_membership(&self, key: u128) -> Option<NonMembershipWitness> {
+        let leaf_index = self.low_leaf(key)?;
+        let zeros = zero_hashes();
+        let levels = self.levels();
+        let siblings = (0..REVOCATION_TREE_DEPTH)
+            .map(|depth| {
+                levels[depth]
+                    .get((leaf_index >> depth) ^ 1)
+                    .copied()
+                    .unwrap_or(zeros[depth])
+            })
+            .collect();
+        Some(NonMembershipWitness {
+            low_key: self.leaves[leaf_index].key,
+            next_key: self.leaves[leaf_index].next,
+            leaf_index,
+            siblings,
+        })
+    }
+}
+
+/// 与电路中的约束相同的本地校验
+pub fn verify_non_membership(root: Fr, key: u128, witness: &NonMembershipWitness) -> bool {
+    if !(witness.low_key < key && key < witness.next_key)
+        || witness.siblings.len() != REVOCATION_TREE_DEPTH
+    {
+        return false;
+    }
+    let mut node = Leaf {
+        key: witness.low_key,
+        next: witness.next_key,
+    }
+    .hash();
+    for (depth, sibling) in witness.siblings.iter().enumerate() {
+        node = if (witness.leaf_index >> depth) & 1 == 1 {
+            poseidon(&[*sibling, node])
+        } else {
+            poseidon(&[node, *sibling])
+        };
+    }
+    node == root
+}
+
+/// 将当前根发布到各网络的验证合约及本地 EVM, 零知识展示对照该根校验.
+/// 累加器按记录键控且由各学校共用, 根只能由部署合约的服务账户更新:
+/// 零知识展示信任本服务发布了全部撤销, 学校自身的撤销以 `revokeCertificate` 的链上记录为准
+pub async fn publish_revocation_root(networks: &NetworkRegistry) -> anyhow::Result<()> {
+    let root = fr_to_h256(REVOCATION_TREE.lock().unwrap().root());
+    for network in networks.networks() {
+        network
+            .ledger
+            .publish_revocation_root(root)
+            .await
+            .with_context(|| format!("network {}", network.name))?;
+    }
+    set_local_revocation_root(root).await
+}
+
+lazy_static::lazy_static! {
+    pub static ref REVOCATION_TREE: Mutex<RevocationTree> = {
+        let path = std::env::var("REVOCATION_ACCUMULATOR")
+            .unwrap_or_else(|_| DEFAULT_REVOCATION_ACCUMULATOR.to_string());
+        Mutex::new(RevocationTree::load(&path).unwrap_or_else(|e| {
+            warn!("撤销累加器加载失败 {path}: {e}");
+            RevocationTree::default()
+        }))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_revocation_tree() {
+        let mut tree = RevocationTree::default();
+        let empty_root = tree.root();
+        let witness = tree.non_membership(42).unwrap();
+        assert!(verify_non_membership(empty_root, 42, &witness));
+
+        for key in [100, 7, 42, 1 << 100] {
+            assert!(tree.insert(key).unwrap());
+        }
+        assert!(!tree.insert(42).unwrap());
+        assert!(tree.insert(0).is_err());
+        assert_ne!(tree.root(), empty_root);
+
+        // 已撤销的键没有非成员证明, 旧证明对新根无效
+        assert!(tree.non_membership(42).is_none());
+        assert!(!verify_non_membership(tree.root(), 42, &witness));
+
+        let root = tree.root();
+        for key in [1, 8, 43, 99, 101, u128::MAX - 1] {
+            let witness = tree.non_membership(key).unwrap();
+            assert!(verify_non_membership(root, key, &witness));
+            // 夹住的区间之外的键不能复用该证明
+            assert!(!verify_non_membership(root, witness.next_key, &witness));
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use std::mem::{transmute, transmute_copy};
 
 use crate::services::shplonk_inner::Shplonk;
-use ark_ff::One;
+use ark_ec::CurveGroup;
+use ark_ff::{BigInteger, One, PrimeField, Zero};
 use ark_poly::{EvaluationDomain, Radix2EvaluationDomain};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
@@ -9,7 +10,7 @@ use snark_verifier_sdk::snark_verifier::halo2_base::halo2_proofs::halo2curves::b
     Fr, G1, G2,
 };
 
-const MAX_DEGREE: usize = 1 << 16;
+pub const MAX_DEGREE: usize = 1 << 16;
 const G1_BYTES: [u8; 96] = [
     190, 63, 28, 202, 99, 84, 194, 148, 207, 100, 192, 152, 222, 162, 45, 4, 0, 158, 148, 183, 219,
     251, 107, 244, 110, 120, 59, 126, 79, 212, 221, 42, 86, 41, 155, 94, 102, 129, 225, 218, 98,
@@ -54,14 +55,79 @@ pub struct Commit(pub G1);
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Proof(pub G1);
 
-pub fn shplonk_lagrange_commit(evals: &[Fr]) -> Commit {
+/// 求值向量补零后的规范定义域大小: 不小于其长度的 2 的幂.
+/// 承诺与打开都在该定义域上进行, 槽位 i 即定义域的第 i 个元素
+pub fn domain_size(len: usize) -> usize {
+    let size = len.max(1).next_power_of_two();
+    assert!(size <= MAX_DEGREE);
+    size
+}
+
+/// 大小为 domain_size 的定义域上的第 index 个元素 ω^index, 槽位 index 在此打开
+pub fn domain_point(index: usize, domain_size: usize) -> Fr {
+    assert!(index < domain_size && domain_size <= MAX_DEGREE);
+    DOMAIN[index * (MAX_DEGREE / domain_size)]
+}
+
+fn fq_to_evm(value: ark_bn254::Fq) -> Vec<u8> {
+    value.into_bigint().to_bytes_be()
+}
+
+/// G1 点的仿射坐标 x || y, 各 32 字节大端, 无穷远点为 (0, 0), 与合约及 EVM 预编译的编码一致
+pub fn g1_to_evm(point: &G1) -> Vec<u8> {
+    let point: ark_bn254::G1Projective = unsafe { transmute(*point) };
+    let point = point.into_affine();
+    if point.infinity {
+        return vec![0; 64];
+    }
+    [fq_to_evm(point.x), fq_to_evm(point.y)].concat()
+}
+
+/// ShplonkVerifier 的构造参数 `(G1Point g1, G2Point g2, G2Point g2Tau)`: 本 setup 的 G1、G2 生成元与 τ·G2.
+/// G2 坐标按预编译的顺序, 虚部在前
+pub fn shplonk_verifier_params() -> Vec<u8> {
+    let g2_to_evm = |point: ark_bn254::G2Projective| {
+        let point = point.into_affine();
+        [point.x.c1, point.x.c0, point.y.c1, point.y.c0]
+            .into_iter()
+            .flat_map(fq_to_evm)
+            .collect::<Vec<_>>()
+    };
+    let g1: G1 = unsafe { transmute(SHPLONK_INSTANCE.g1) };
+
+    [
+        g1_to_evm(&g1),
+        g2_to_evm(SHPLONK_INSTANCE.g2),
+        g2_to_evm(SHPLONK_INSTANCE.g2_tau),
+    ]
+    .concat()
+}
+
+pub fn shplonk_lagrange_commit(evals: &[Fr], domain_size: usize) -> Commit {
     let evals_ark: &[ark_bn254::Fr] = unsafe { transmute_copy(&evals) };
 
-    let res: ark_bn254::G1Projective = (*SHPLONK_INSTANCE).commit_lagrange_g1(evals_ark);
+    let res: ark_bn254::G1Projective =
+        (*SHPLONK_INSTANCE).commit_lagrange_g1_domain(evals_ark, domain_size);
 
     Commit(unsafe { transmute(res) })
 }
 
+/// 承诺对求值线性, 槽位 i 的求值增加 v 时承诺增加 v·L_i(τ)
+pub fn shplonk_lagrange_delta(updates: &[(usize, Fr)], domain_size: usize) -> Commit {
+    let (slots, deltas): (Vec<usize>, Vec<Fr>) = updates.iter().copied().unzip();
+    let deltas = deltas.as_slice();
+    let deltas_ark: &[ark_bn254::Fr] = unsafe { transmute_copy(&deltas) };
+
+    let res: ark_bn254::G1Projective =
+        (*SHPLONK_INSTANCE).delta_lagrange_g1(&slots, deltas_ark, domain_size);
+
+    Commit(unsafe { transmute(res) })
+}
+
+pub fn shplonk_lagrange_update(commit: &Commit, delta: &Commit) -> Commit {
+    Commit(commit.0 + delta.0)
+}
+
 pub fn shplonk_lagrange_commit_g2(evals: &[Fr]) -> G2 {
     let evals_ark: &[ark_bn254::Fr] = unsafe { transmute_copy(&evals) };
 
@@ -70,10 +136,13 @@ pub fn shplonk_lagrange_commit_g2(evals: &[Fr]) -> G2 {
     unsafe { transmute(res) }
 }
 
-pub fn shplonk_lagrange_open(evals: &[Fr], point: Fr) -> (Proof, Fr) {
+pub fn shplonk_lagrange_open(evals: &[Fr], point: Fr, domain_size: usize) -> (Proof, Fr) {
     let evals_ark: &[ark_bn254::Fr] = unsafe { transmute_copy(&evals) };
-    let domain = Radix2EvaluationDomain::<ark_bn254::Fr>::new(evals_ark.len()).unwrap();
-    let poly = domain.ifft(evals_ark);
+    assert!(evals_ark.len() <= domain_size);
+    let mut padded = evals_ark.to_vec();
+    padded.resize(domain_size, ark_bn254::Fr::zero());
+    let domain = Radix2EvaluationDomain::<ark_bn254::Fr>::new(domain_size).unwrap();
+    let poly = domain.ifft(&padded);
 
     let (proof, value): (ark_bn254::G1Projective, ark_bn254::Fr) =
         (*SHPLONK_INSTANCE).open(&poly, unsafe { transmute(point) });
@@ -104,8 +173,114 @@ fn test_commit() {
     let evals: Vec<Fr> = (0..n).map(|_| Fr::random(&mut rng)).collect();
     let point: Fr = Fr::random(&mut rng);
 
-    let commit = shplonk_lagrange_commit(&evals);
-    let (proof, value) = shplonk_lagrange_open(&evals, point);
+    let commit = shplonk_lagrange_commit(&evals, n);
+    let (proof, value) = shplonk_lagrange_open(&evals, point, n);
 
     assert!(shplonk_verify(commit, proof, value, point));
 }
+
+#[test]
+fn test_domain_sizes() {
+    use rand_chacha::{rand_core::SeedableRng, ChaCha12Rng};
+    use snark_verifier_sdk::snark_verifier::util::arithmetic::Field;
+
+    let mut rng = ChaCha12Rng::seed_from_u64(0);
+
+    for (n, size) in [(1, 1), (3, 4), (100, 128), (1 << 16, 1 << 16)] {
+        assert_eq!(domain_size(n), size);
+        let evals: Vec<Fr> = (0..n).map(|_| Fr::random(&mut rng)).collect();
+        let commit = shplonk_lagrange_commit(&evals, size);
+
+        let point: Fr = Fr::random(&mut rng);
+        let (proof, value) = shplonk_lagrange_open(&evals, point, size);
+        assert!(shplonk_verify(Commit(commit.0), proof, value, point));
+
+        // ω^i 处打开得到槽位 i 承诺的求值, 补零的槽位为 0
+        for slot in [0, n / 2, n - 1, n, size - 1] {
+            if slot >= size {
+                continue;
+            }
+            let point = domain_point(slot, size);
+            let (proof, value) = shplonk_lagrange_open(&evals, point, size);
+            assert_eq!(value, evals.get(slot).copied().unwrap_or(Fr::zero()));
+            assert!(shplonk_verify(Commit(commit.0), proof, value, point));
+            let (proof, _) = shplonk_lagrange_open(&evals, point, size);
+            assert!(!shplonk_verify(
+                Commit(commit.0),
+                proof,
+                value + Fr::one(),
+                point
+            ));
+        }
+    }
+}
+
+#[test]
+fn test_batched_opening() {
+    use rand_chacha::{rand_core::SeedableRng, ChaCha12Rng};
+    use snark_verifier_sdk::snark_verifier::util::arithmetic::Field;
+
+    let mut rng = ChaCha12Rng::seed_from_u64(0);
+
+    // 同一打开点上的多个打开按 ξ 的幂合并后一次验证, 与 CertificateVerifier.verifyGroup 一致
+    let size = domain_size(5);
+    let polys = (0..3)
+        .map(|_| (0..5).map(|_| Fr::random(&mut rng)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let point = domain_point(3, size);
+    let xi = Fr::random(&mut rng);
+
+    let (mut commit, mut proof, mut value, mut power) =
+        (G1::default(), G1::default(), Fr::zero(), Fr::one());
+    for evals in &polys {
+        let (opening, eval) = shplonk_lagrange_open(evals, point, size);
+        commit += shplonk_lagrange_commit(evals, size).0 * power;
+        proof += opening.0 * power;
+        value += eval * power;
+        power *= xi;
+    }
+    assert!(shplonk_verify(Commit(commit), Proof(proof), value, point));
+    assert!(!shplonk_verify(
+        Commit(commit),
+        Proof(proof),
+        value + Fr::one(),
+        point
+    ));
+
+    assert_eq!(g1_to_evm(&G1::default()), vec![0; 64]);
+    assert_eq!(shplonk_verifier_params().len(), 10 * 32);
+}
+
+#[test]
+fn test_lagrange_update() {
+    use rand_chacha::{rand_core::SeedableRng, ChaCha12Rng};
+    use snark_verifier_sdk::snark_verifier::util::arithmetic::Field;
+
+    let mut rng = ChaCha12Rng::seed_from_u64(0);
+
+    let n = 100;
+    let size = domain_size(n + 1);
+    let mut evals: Vec<Fr> = (0..n).map(|_| Fr::random(&mut rng)).collect();
+    let commit = shplonk_lagrange_commit(&evals, size);
+
+    // 更新槽位 3 与 42, 并在末尾追加一个学生
+    let updates = vec![
+        (3, Fr::random(&mut rng)),
+        (42, Fr::random(&mut rng)),
+        (n, Fr::random(&mut rng)),
+    ];
+    evals.push(Fr::zero());
+    for (slot, delta) in &updates {
+        evals[*slot] += delta;
+    }
+
+    let delta = shplonk_lagrange_delta(&updates, size);
+    let updated = shplonk_lagrange_update(&commit, &delta);
+    assert_eq!(updated.0, shplonk_lagrange_commit(&evals, size).0);
+    assert_eq!(shplonk_lagrange_delta(&[], size).0, G1::default());
+
+    // 更新后的承诺可在新求值上打开
+    let point = Fr::random(&mut rng);
+    let (proof, value) = shplonk_lagrange_open(&evals, point, size);
+    assert!(shplonk_verify(updated, proof, value, point));
+}
@@ -1,9 +1,11 @@
 use ark_ec::pairing::Pairing;
-use ark_ec::CurveGroup;
+use ark_ec::{AffineRepr, CurveGroup};
 use ark_ff::Field;
 use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
 use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use std::collections::BTreeMap;
 use std::ops::Mul;
+use std::sync::{Arc, Mutex};
 
 pub struct Shplonk<E: Pairing> {
     pub g1: E::G1,
@@ -14,6 +16,8 @@ pub struct Shplonk<E: Pairing> {
     pub crs_lagrange_g1_aff: Vec<E::G1Affine>,
     pub crs_g2_aff: Vec<E::G2Affine>,
     pub crs_lagrange_g2_aff: Vec<E::G2Affine>,
+    // lagrange bases of the smaller power-of-two domains, computed on first use
+    crs_lagrange_g1_sub: Mutex<BTreeMap<usize, Arc<Vec<E::G1Affine>>>>,
 }
 
 impl<E: Pairing> Shplonk<E> {
@@ -27,6 +31,7 @@ impl<E: Pairing> Shplonk<E> {
             crs_lagrange_g1_aff: vec![],
             crs_g2_aff: vec![],
             crs_lagrange_g2_aff: vec![],
+            crs_lagrange_g1_sub: Mutex::new(BTreeMap::new()),
         }
     }
 
@@ -58,6 +63,45 @@ impl<E: Pairing> Shplonk<E> {
         ark_ec::VariableBaseMSM::msm(&self.crs_lagrange_g1_aff[..evals.len()], evals).unwrap()
     }
 
+    // lagrange bases of the power-of-two domain of `size`, ifft of the first `size` monomial bases
+    pub fn lagrange_g1(&self, size: usize) -> Arc<Vec<E::G1Affine>> {
+        assert!(size.is_power_of_two() && size <= self.degree);
+        let mut cache = self.crs_lagrange_g1_sub.lock().unwrap();
+        cache
+            .entry(size)
+            .or_insert_with(|| {
+                if size == self.degree {
+                    return Arc::new(self.crs_lagrange_g1_aff.clone());
+                }
+                let crs_g1: Vec<_> = self.crs_g1_aff[..size]
+                    .iter()
+                    .map(|p| p.into_group())
+                    .collect();
+                Arc::new(g1_ifft::<E>(&crs_g1))
+            })
+            .clone()
+    }
+
+    // evals are zero padded to the domain of `size`
+    pub fn commit_lagrange_g1_domain(&self, evals: &[E::ScalarField], size: usize) -> E::G1 {
+        assert!(evals.len() <= size);
+        let bases = self.lagrange_g1(size);
+        ark_ec::VariableBaseMSM::msm(&bases[..evals.len()], evals).unwrap()
+    }
+
+    // commitment of sum(delta_i * L_slot_i) over the domain of `size`, slots may repeat
+    pub fn delta_lagrange_g1(
+        &self,
+        slots: &[usize],
+        deltas: &[E::ScalarField],
+        size: usize,
+    ) -> E::G1 {
+        assert!(slots.len() == deltas.len());
+        let lagrange = self.lagrange_g1(size);
+        let bases: Vec<_> = slots.iter().map(|slot| lagrange[*slot]).collect();
+        ark_ec::VariableBaseMSM::msm(&bases, deltas).unwrap()
+    }
+
     pub fn commit_lagrange_g2(&self, evals: &[E::ScalarField]) -> E::G2 {
         assert!(evals.len() <= self.degree);
         ark_ec::VariableBaseMSM::msm(&self.crs_lagrange_g2_aff[..evals.len()], evals).unwrap()
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use aes::cipher::{KeyIvInit, StreamCipher};
+use anyhow::{anyhow, bail, ensure, Context};
+use hmac::{Hmac, Mac};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+// 记录密钥登记文件路径
+const DEFAULT_RECORD_KEYS_PATH: &str = "record_keys/registry.json";
+// 加密记录的前缀, 早期以明文上传的记录没有该前缀
+const ENCRYPTED_PREFIX: &[u8] = b"edu-verify:aes-256-ctr+hmac-sha256:";
+const IV_SIZE: usize = 16;
+const TAG_SIZE: usize = 32;
+
+type Aes256Ctr = ctr::Ctr128BE<aes::Aes256>;
+
+/// 一条学历记录的密钥: AES-256-CTR 加密密钥与 HMAC-SHA256 认证密钥.
+/// 记录以密文存入IPFS, 销毁密钥后其他节点或缓存中的副本也无法读取 (crypto-shredding)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordKey {
+    cipher: [u8; 32],
+    mac: [u8; 32],
+}
+
+impl RecordKey {
+    pub fn generate() -> Self {
+        Self {
+            cipher: rand::random(),
+            mac: rand::random(),
+        }
+    }
+
+    /// 前缀 || iv || 密文 || HMAC(iv || 密文)
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let iv: [u8; IV_SIZE] = rand::random();
+        let mut ciphertext = plaintext.to_vec();
+        Aes256Ctr::new_from_slices(&self.cipher, &iv)
+            .expect("AES-256-CTR key and iv sizes")
+            .apply_keystream(&mut ciphertext);
+        let tag = self.mac(&iv, &ciphertext).finalize().into_bytes();
+        [ENCRYPTED_PREFIX, &iv, &ciphertext, &tag].concat()
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let body = data
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .ok_or_else(|| anyhow!("record is not encrypted"))?;
+        ensure!(
+            body.len() >= IV_SIZE + TAG_SIZE,
+            "encrypted record too short"
+        );
+        let (iv, rest) = body.split_at(IV_SIZE);
+        let (ciphertext, tag) = rest.split_at(rest.len() - TAG_SIZE);
+        self.mac(iv, ciphertext)
+            .verify_slice(tag)
+            .map_err(|_| anyhow!("encrypted record failed authentication"))?;
+
+        let mut plaintext = ciphertext.to_vec();
+        Aes256Ctr::new_from_slices(&self.cipher, iv)
+            .expect("AES-256-CTR key and iv sizes")
+            .apply_keystream(&mut plaintext);
+        Ok(plaintext)
+    }
+
+    fn mac(&self, iv: &[u8], ciphertext: &[u8]) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.mac).expect("HMAC accepts keys of any size");
+        mac.update(iv);
+        mac.update(ciphertext);
+        mac
+    }
+
+    fn to_hex(self) -> String {
+        hex::encode([self.cipher, self.mac].concat())
+    }
+
+    fn from_hex(key: &str) -> anyhow::Result<Self> {
+        let bytes = hex::decode(key).context("invalid record key")?;
+        ensure!(bytes.len() == 64, "invalid record key");
+        Ok(Self {
+            cipher: bytes[..32].try_into()?,
+            mac: bytes[32..].try_into()?,
+        })
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// 记录密钥登记: 密钥编号 -> 密钥, 内容 CID -> 密钥编号.
+/// 证书的每条记录一个密钥, 展示时上传的记录副本沿用原记录的密钥, 销毁密钥即同时使副本不可读.
+/// 密钥销毁后 CID 仍保留编号, 以区分已销毁的记录与早期的明文记录
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct RecordKeys {
+    keys: BTreeMap<String, String>,
+    contents: BTreeMap<String, String>,
+}
+
+impl RecordKeys {
+    /// CID 对应的密钥编号与密钥, 明文记录为空, 密钥已销毁时报错
+    pub fn get(&self, cid: &str) -> anyhow::Result<Option<(String, RecordKey)>> {
+        let Some(key_id) = self.contents.get(cid) else {
+            return Ok(None);
+        };
+        match self.keys.get(key_id) {
+            Some(key) => Ok(Some((key_id.clone(), RecordKey::from_hex(key)?))),
+            None => bail!("record key of {cid} destroyed by subject request"),
+        }
+    }
+
+    pub fn insert(&mut self, cid: &str, key_id: &str, key: &RecordKey) {
+        self.keys
+            .entry(key_id.to_string())
+            .or_insert_with(|| key.to_hex());
+        self.contents.insert(cid.to_string(), key_id.to_string());
+    }
+
+    /// 销毁各 CID 的密钥, 返回密钥被销毁的 CID; 明文记录没有密钥, 不在其中
+    pub fn destroy(&mut self, cids: &[String]) -> Vec<String> {
+        cids.iter()
+            .filter(|cid| {
+                self.contents
+                    .get(cid.as_str())
+                    .is_some_and(|key_id| self.keys.remove(key_id).is_some())
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref RECORD_KEYS: Mutex<RecordKeys> = Mutex::new(load_keys());
+}
+
+fn keys_path() -> String {
+    std::env::var("RECORD_KEYS_PATH").unwrap_or_else(|_| DEFAULT_RECORD_KEYS_PATH.to_string())
+}
+
+fn load_keys() -> RecordKeys {
+    let path = keys_path();
+    match std::fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+            warn!("记录密钥登记文件格式错误 {path}: {e}");
+            RecordKeys::default()
+        }),
+        Err(_) => RecordKeys::default(),
+    }
+}
+
+fn save_keys(keys: &RecordKeys) -> anyhow::Result<()> {
+    let path = keys_path();
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_string_pretty(keys)?)
+        .with_context(|| format!("write record keys {path}"))?;
+    Ok(())
+}
+
+pub fn record_key(cid: &str) -> anyhow::Result<Option<(String, RecordKey)>> {
+    RECORD_KEYS.lock().unwrap().get(cid)
+}
+
+/// 登记已上传的密文, 写盘失败时密文无法解密, 上传视为失败
+pub fn register_record(cid: &str, key_id: &str, key: &RecordKey) -> anyhow::Result<()> {
+    let mut keys = RECORD_KEYS.lock().unwrap();
+    keys.insert(cid, key_id, key);
+    save_keys(&keys)
+}
+
+/// 销毁记录密钥, 返回密钥被销毁的 CID
+pub fn destroy_record_keys(cids: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut keys = RECORD_KEYS.lock().unwrap();
+    let destroyed = keys.destroy(cids);
+    save_keys(&keys)?;
+    info!("已销毁 {} 条记录的密钥", destroyed.len());
+    Ok(destroyed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_keys() {
+        let key = RecordKey::generate();
+        let plaintext = br#"{"name":"Alice"}"#;
+        let encrypted = key.encrypt(plaintext);
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(key.decrypt(&encrypted).unwrap(), plaintext);
+        // 篡改或换用其他密钥都无法解密
+        let mut tampered = encrypted.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(key.decrypt(&tampered).is_err());
+        assert!(RecordKey::generate().decrypt(&encrypted).is_err());
+
+        // 副本沿用原记录的密钥, 销毁后两者都不可读, 明文记录不受影响
+        let mut keys = RecordKeys::default();
+        keys.insert("QmRecord", "k1", &key);
+        keys.insert("QmCopy", "k1", &RecordKey::generate());
+        assert_eq!(keys.get("QmCopy").unwrap(), Some(("k1".to_string(), key)));
+        assert_eq!(keys.get("QmPlain").unwrap(), None);
+
+        let destroyed = keys.destroy(&["QmRecord".to_string(), "QmPlain".to_string()]);
+        assert_eq!(destroyed, vec!["QmRecord".to_string()]);
+        assert!(keys.get("QmRecord").is_err());
+        assert!(keys.get("QmCopy").is_err());
+        assert_eq!(keys.get("QmPlain").unwrap(), None);
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context};
+use log::{info, warn};
+use tokio::sync::Mutex;
+use web3::api::{Accounts, Eth, Namespace};
+use web3::transports::Http;
+use web3::types::{
+    Address, BlockNumber, Bytes, CallRequest, FeeHistory, TransactionId, TransactionParameters,
+    TransactionReceipt, TransactionRequest, H256, U256, U64,
+};
+
+use crate::services::config::env_or;
+use crate::services::wallet::Signer;
+
+// eth_feeHistory 统计的区块数
+const FEE_HISTORY_BLOCKS: u64 = 10;
+// 节点没有小费数据时使用的默认小费: 1.5 gwei
+const DEFAULT_PRIORITY_FEE: u64 = 1_500_000_000;
+const EIP1559_TX_TYPE: u64 = 2;
+
+/// 交易发送的确认数、超时与替换配置
+#[derive(Clone, Copy, Debug)]
+pub struct TxConfig {
+    pub confirmations: u64,
+    pub timeout: Duration,
+    pub replace_after: Duration,
+    pub poll_interval: Duration,
+    // gas 估算值的放大百分比
+    pub gas_margin: u64,
+}
+
+impl TxConfig {
+    pub fn from_env() -> Self {
+        Self {
+            confirmations: env_or("TX_CONFIRMATIONS", 1u64).max(1),
+            timeout: Duration::from_secs(env_or("TX_TIMEOUT_SECS", 300)),
+            replace_after: Duration::from_secs(env_or("TX_REPLACE_AFTER_SECS", 60)),
+            poll_interval: Duration::from_millis(env_or("TX_POLL_INTERVAL_MS", 1000)),
+            gas_margin: env_or("TX_GAS_MARGIN_PERCENT", 120u64).max(100),
+        }
+    }
+}
+
+/// 交易费用: 节点支持 EIP-1559 时使用动态费用, 否则使用 legacy gas price
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fees {
+    Legacy {
+        gas_price: U256,
+    },
+    Eip1559 {
+        max_fee_per_gas: U256,
+        max_priority_fee_per_gas: U256,
+    },
+}
+
+impl Fees {
+    /// 由 eth_feeHistory 计算动态费用: 小费取近期区块的中位数,
+    /// 最大费用取下一区块基础费用的两倍加小费. 基础费用为0表示节点不支持 EIP-1559
+    pub fn from_history(history: &FeeHistory) -> Option<Self> {
+        let base_fee = *history.base_fee_per_gas.last()?;
+        if base_fee.is_zero() {
+            return None;
+        }
+
+        let mut tips = history
+            .reward
+            .iter()
+            .flatten()
+            .filter_map(|rewards| rewards.first().copied())
+            .filter(|tip| !tip.is_zero())
+            .collect::<Vec<_>>();
+        tips.sort();
+        let priority = tips
+            .get(tips.len() / 2)
+            .copied()
+            .unwrap_or_else(|| DEFAULT_PRIORITY_FEE.into());
+
+        Some(Self::Eip1559 {
+            max_fee_per_gas: base_fee * 2 + priority,
+            max_priority_fee_per_gas: priority,
+        })
+    }
+
+    /// 替换交易的费用: 节点要求至少提高10%, 这里提高12.5%
+    pub fn bump(self) -> Self {
+        let bump = |fee: U256| fee + fee / 8 + 1;
+        match self {
+            Self::Legacy { gas_price } => Self::Legacy {
+                gas_price: bump(gas_price),
+            },
+            Self::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => Self::Eip1559 {
+                max_fee_per_gas: bump(max_fee_per_gas),
+                max_priority_fee_per_gas: bump(max_priority_fee_per_gas),
+            },
+        }
+    }
+}
+
+// 已分配 nonce 的交易, 替换时保持 nonce 不变
+struct PendingTx {
+    to: Option<Address>,
+    data: Vec<u8>,
+    gas: U256,
+    nonce: U256,
+    fees: Fees,
+}
+
+/// 交易管理: 估算 gas、选择费用、本地分配 nonce, 发送后等待确认,
+/// 检测回滚与丢弃, 长时间未打包时提高费用替换
+pub struct TxManager {
+    config: TxConfig,
+    // (链ID, 签名账户) -> 下一个可用 nonce, 并发上传时避免 nonce 冲突
+    nonces: Mutex<HashMap<(U256, Address), U256>>,
+}
+
+impl TxManager {
+    pub fn new(config: TxConfig) -> Self {
+        Self {
+            config,
+            nonces: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 取本地记录与节点 pending nonce 中较大者, 节点重启或外部发送交易后自动对齐
+    async fn next_nonce(&self, eth: &Eth<Http>, key: (U256, Address)) -> anyhow::Result<U256> {
+        let mut nonces = self.nonces.lock().await;
+        let pending = eth
+            .transaction_count(key.1, Some(BlockNumber::Pending))
+            .await?;
+        let nonce = nonces
+            .get(&key)
+            .map_or(pending, |nonce| (*nonce).max(pending));
+        nonces.insert(key, nonce + 1);
+        Ok(nonce)
+    }
+
+    // 发送失败时丢弃本地记录, 下次从节点重新读取
+    async fn reset_nonce(&self, key: (U256, Address)) {
+        self.nonces.lock().await.remove(&key);
+    }
+
+    async fn fees(&self, eth: &Eth<Http>) -> anyhow::Result<Fees> {
+        match eth
+            .fee_history(
+                FEE_HISTORY_BLOCKS.into(),
+                BlockNumber::Latest,
+                Some(vec![50.0]),
+            )
+            .await
+        {
+            Ok(history) => {
+                if let Some(fees) = Fees::from_history(&history) {
+                    return Ok(fees);
+                }
+            }
+            Err(e) => warn!("eth_feeHistory 不可用, 使用 legacy gas price: {e}"),
+        }
+
+        Ok(Fees::Legacy {
+            gas_price: eth.gas_price().await?,
+        })
+    }
+
+    async fn broadcast(
+        &self,
+        eth: &Eth<Http>,
+        signer: &Signer,
+        tx: &PendingTx,
+    ) -> anyhow::Result<H256> {
+        let (transaction_type, gas_price, max_fee_per_gas, max_priority_fee_per_gas) = match tx.fees
+        {
+            Fees::Legacy { gas_price } => (None, Some(gas_price), None, None),
+            Fees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => (
+                Some(U64::from(EIP1559_TX_TYPE)),
+                None,
+                Some(max_fee_per_gas),
+                Some(max_priority_fee_per_gas),
+            ),
+        };
+
+        let hash = match signer {
+            Signer::Local { key, .. } => {
+                let params = TransactionParameters {
+                    nonce: Some(tx.nonce),
+                    to: tx.to,
+                    gas: tx.gas,
+                    gas_price,
+                    data: Bytes(tx.data.clone()),
+                    transaction_type,
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                    ..Default::default()
+                };
+                let signed = Accounts::new(eth.transport().clone())
+                    .sign_transaction(params, key)
+                    .await?;
+                eth.send_raw_transaction(signed.raw_transaction).await?
+            }
+            Signer::Node(from) => {
+                let request = TransactionRequest {
+                    from: *from,
+                    to: tx.to,
+                    gas: Some(tx.gas),
+                    gas_price,
+                    data: Some(Bytes(tx.data.clone())),
+                    nonce: Some(tx.nonce),
+                    transaction_type,
+                    max_fee_per_gas,
+                    max_priority_fee_per_gas,
+                    ..Default::default()
+                };
+                eth.send_transaction(request).await?
+            }
+        };
+
+        Ok(hash)
+    }
+
+    /// 发送交易并等待确认, 返回最终打包的交易回执.
+    /// `confirmations` 为空时使用 TX_CONFIRMATIONS
+    pub async fn send(
+        &self,
+        eth: &Eth<Http>,
+        signer: &Signer,
+        to: Option<Address>,
+        data: Vec<u8>,
+        confirmations: Option<u64>,
+    ) -> anyhow::Result<TransactionReceipt> {
+        let from = signer.address();
+        let confirmations = confirmations.unwrap_or(self.config.confirmations).max(1);
+        let nonce_key = (eth.chain_id().await?, from);
+        let estimated = eth
+            .estimate_gas(
+                CallRequest {
+                    from: Some(from),
+                    to,
+                    data: Some(Bytes(data.clone())),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await
+            .context("gas estimation failed")?;
+
+        let fees = self.fees(eth).await?;
+        let nonce = self.next_nonce(eth, nonce_key).await?;
+        let mut tx = PendingTx {
+            to,
+            data,
+            gas: estimated * self.config.gas_margin / 100,
+            nonce,
+            fees,
+        };
+
+        let mut hashes = match self.broadcast(eth, signer, &tx).await {
+            Ok(hash) => vec![hash],
+            Err(e) => {
+                self.reset_nonce(nonce_key).await;
+                return Err(e);
+            }
+        };
+        info!(
+            "交易已发送: {:?}, 账户: {from:?}, nonce: {nonce}, gas: {}, 费用: {:?}",
+            hashes[0], tx.gas, tx.fees
+        );
+
+        let started = Instant::now();
+        let mut last_sent = started;
+        loop {
+            tokio::time::sleep(self.config.poll_interval).await;
+
+            // 任一替换交易被打包即可
+            for hash in &hashes {
+                if let Some(receipt) = eth.transaction_receipt(*hash).await? {
+                    return self.confirm(eth, receipt, confirmations).await;
+                }
+            }
+
+            let latest = *hashes.last().expect("at least one transaction sent");
+            if started.elapsed() > self.config.timeout {
+                bail!(
+                    "transaction {latest:?} not mined within {:?}",
+                    self.config.timeout
+                );
+            }
+
+            // nonce 已被打包却没有我们的回执: 被外部交易替换
+            let mined_nonce = eth
+                .transaction_count(from, Some(BlockNumber::Latest))
+                .await?;
+            if mined_nonce > nonce {
+                for hash in &hashes {
+                    if let Some(receipt) = eth.transaction_receipt(*hash).await? {
+                        return self.confirm(eth, receipt, confirmations).await;
+                    }
+                }
+                bail!("transaction {latest:?} dropped, nonce {nonce} used by another transaction");
+            }
+
+            let dropped = eth
+                .transaction(TransactionId::Hash(latest))
+                .await?
+                .is_none();
+            if dropped || last_sent.elapsed() >= self.config.replace_after {
+                tx.fees = tx.fees.bump();
+                if dropped {
+                    warn!("交易 {latest:?} 已从交易池中丢失, 提高费用后重新发送");
+                } else {
+                    warn!(
+                        "交易 {latest:?} 长时间未打包, 提高费用后替换: {:?}",
+                        tx.fees
+                    );
+                }
+                match self.broadcast(eth, signer, &tx).await {
+                    Ok(hash) => hashes.push(hash),
+                    Err(e) => warn!("替换交易发送失败: {e}"),
+                }
+                last_sent = Instant::now();
+            }
+        }
+    }
+
+    /// 检查回执状态并等待确认数, 区块重组后以新的回执为准
+    async fn confirm(
+        &self,
+        eth: &Eth<Http>,
+        mut receipt: TransactionReceipt,
+        confirmations: u64,
+    ) -> anyhow::Result<TransactionReceipt> {
+        let hash = receipt.transaction_hash;
+        loop {
+            if receipt.status == Some(U64::zero()) {
+                bail!("transaction {hash:?} reverted");
+            }
+            let mined_in = receipt
+                .block_number
+                .ok_or_else(|| anyhow!("transaction {hash:?} receipt has no block number"))?;
+
+            let latest = eth.block_number().await?;
+            if latest + 1 >= mined_in + confirmations {
+                match eth.transaction_receipt(hash).await? {
+                    Some(current) if current.block_hash == receipt.block_hash => {
+                        info!(
+                            "交易 {hash:?} 已确认, 区块: {mined_in}, 确认数: {}",
+                            latest + 1 - mined_in
+                        );
+                        return Ok(current);
+                    }
+                    Some(current) => {
+                        warn!("交易 {hash:?} 所在区块发生重组, 重新等待确认");
+                        receipt = current;
+                        continue;
+                    }
+                    None => bail!("transaction {hash:?} removed by a chain reorganization"),
+                }
+            }
+
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref TX_CONFIG: TxConfig = TxConfig::from_env();
+    // 所有交易共享的 nonce 记录
+    pub static ref TX_MANAGER: TxManager = TxManager::new(*TX_CONFIG);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(base_fees: &[u64], tips: &[u64]) -> FeeHistory {
+        FeeHistory {
+            oldest_block: BlockNumber::Number(1.into()),
+            base_fee_per_gas: base_fees.iter().map(|&fee| fee.into()).collect(),
+            gas_used_ratio: vec![0.5; tips.len()],
+            reward: Some(tips.iter().map(|&tip| vec![tip.into()]).collect()),
+        }
+    }
+
+    #[test]
+    fn test_fees_from_history() {
+        // 取最后一个(下一区块)基础费用和非零小费的中位数
+        let fees = Fees::from_history(&history(&[10, 20, 30], &[0, 3, 1, 2])).unwrap();
+        assert_eq!(
+            fees,
+            Fees::Eip1559 {
+                max_fee_per_gas: 62.into(),
+                max_priority_fee_per_gas: 2.into(),
+            }
+        );
+
+        let fees = Fees::from_history(&history(&[100], &[])).unwrap();
+        assert_eq!(
+            fees,
+            Fees::Eip1559 {
+                max_fee_per_gas: (200 + DEFAULT_PRIORITY_FEE).into(),
+                max_priority_fee_per_gas: DEFAULT_PRIORITY_FEE.into(),
+            }
+        );
+
+        // 不支持 EIP-1559 的节点返回0
+        assert!(Fees::from_history(&history(&[0, 0], &[1])).is_none());
+    }
+
+    #[test]
+    fn test_fees_bump() {
+        let bumped = Fees::Legacy {
+            gas_price: 1000.into(),
+        }
+        .bump();
+        assert_eq!(
+            bumped,
+            Fees::Legacy {
+                gas_price: 1126.into()
+            }
+        );
+
+        match (Fees::Eip1559 {
+            max_fee_per_gas: 80.into(),
+            max_priority_fee_per_gas: 8.into(),
+        })
+        .bump()
+        {
+            Fees::Eip1559 {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+            } => {
+                assert!(max_fee_per_gas * 10 >= U256::from(80 * 11));
+                assert!(max_priority_fee_per_gas * 10 >= U256::from(8 * 11));
+            }
+            other => panic!("unexpected fees {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,916 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, ensure, Context};
+use async_trait::async_trait;
+use chrono::Utc;
+use log::info;
+use secp256k1::SecretKey;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use web3::signing::{recover, Key, SecretKeyRef};
+use web3::types::{Address, H256};
+
+use crate::services::anchor::{new_batch_id, Anchor, AnchorInfo};
+use crate::services::bindings::VerifyDataCall;
+use crate::services::evm::local_verify_data;
+use crate::services::inclusion::AnchorStatus;
+use crate::services::ledger::{Attestation, FetchedBatch, Ledger, RevocationReason};
+use crate::services::wallet::{parse_private_key, Signer};
+
+// 合约中 G1 点的编码长度
+const G1_SIZE: usize = 96;
+
+/// RFC 6962 叶子哈希: SHA-256(0x00 || data)
+pub fn leaf_hash(data: &[u8]) -> H256 {
+    H256::from_slice(
+        &Sha256::new()
+            .chain_update([0])
+            .chain_update(data)
+            .finalize(),
+    )
+}
+
+fn node_hash(left: &H256, right: &H256) -> H256 {
+    H256::from_slice(
+        &Sha256::new()
+            .chain_update([1])
+            .chain_update(left)
+            .chain_update(right)
+            .finalize(),
+    )
+}
+
+// 小于 n 的最大的2的幂, n > 1
+fn split_point(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+/// MTH(D[n]), 空树为 SHA-256("")
+pub fn tree_root(leaves: &[H256]) -> H256 {
+    match leaves.len() {
+        0 => H256::from_slice(&Sha256::digest(b"")),
+        1 => leaves[0],
+        n => {
+            let k = split_point(n);
+            node_hash(&tree_root(&leaves[..k]), &tree_root(&leaves[k..]))
+        }
+    }
+}
+
+/// PATH(m, D[n]): 第 m 个叶子的审计路径
+pub fn inclusion_proof(leaves: &[H256], m: usize) -> Vec<H256> {
+    if leaves.len() <= 1 {
+        return Vec::new();
+    }
+    let k = split_point(leaves.len());
+    let (mut path, sibling) = if m < k {
+        (inclusion_proof(&leaves[..k], m), tree_root(&leaves[k..]))
+    } else {
+        (
+            inclusion_proof(&leaves[k..], m - k),
+            tree_root(&leaves[..k]),
+        )
+    };
+    path.push(sibling);
+    path
+}
+
+/// PROOF(m, D[n]): 前 m 个叶子组成的树与当前树的一致性证明
+pub fn consistency_proof(leaves: &[H256], m: usize) -> Vec<H256> {
+    fn subproof(leaves: &[H256], m: usize, complete: bool) -> Vec<H256> {
+        let n = leaves.len();
+        if m == n {
+            return if complete {
+                Vec::new()
+            } else {
+                vec![tree_root(leaves)]
+            };
+        }
+        let k = split_point(n);
+        let (mut proof, sibling) = if m <= k {
+            (subproof(&leaves[..k], m, complete), tree_root(&leaves[k..]))
+        } else {
+            (
+                subproof(&leaves[k..], m - k, false),
+                tree_root(&leaves[..k]),
+            )
+        };
+        proof.push(sibling);
+        proof
+    }
+
+    if m == 0 || m >= leaves.len() {
+        return Vec::new();
+    }
+    subproof(leaves, m, true)
+}
+
+/// 按 RFC 9162 2.1.3.2 验证审计路径
+pub fn verify_inclusion(
+    leaf: H256,
+    index: u64,
+    tree_size: u64,
+    proof: &[H256],
+    root: H256,
+) -> bool {
+    if index >= tree_size {
+        return false;
+    }
+    let (mut fn_, mut sn) = (index, tree_size - 1);
+    let mut r = leaf;
+    for p in proof {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            r = node_hash(p, &r);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            r = node_hash(&r, p);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    sn == 0 && r == root
+}
+
+/// 按 RFC 9162 2.1.4.2 验证一致性证明: 新树是旧树的追加, 日志没有分叉
+pub fn verify_consistency(
+    first_size: u64,
+    second_size: u64,
+    first_root: H256,
+    second_root: H256,
+    proof: &[H256],
+) -> bool {
+    if first_size > second_size {
+        return false;
+    }
+    if first_size == second_size {
+        return proof.is_empty() && first_root == second_root;
+    }
+    if first_size == 0 {
+        return proof.is_empty();
+    }
+    if proof.is_empty() {
+        return false;
+    }
+
+    let mut path = proof.to_vec();
+    if first_size.is_power_of_two() {
+        path.insert(0, first_root);
+    }
+    let (mut fn_, mut sn) = (first_size - 1, second_size - 1);
+    while fn_ & 1 == 1 {
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    let (mut fr, mut sr) = (path[0], path[0]);
+    for c in &path[1..] {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            fr = node_hash(c, &fr);
+            sr = node_hash(c, &sr);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            sr = node_hash(&sr, c);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    fr == first_root && sr == second_root && sn == 0
+}
+
+/// 日志中的一个批次, 或对已锚定批次中某张证书的撤销
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub issuer: Address,
+    pub batch_id: H256,
+    pub schema_hash: H256,
+    pub commitments: String,
+    // 追加时间, 毫秒
+    pub timestamp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revocation: Option<LogRevocation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update: Option<LogUpdate>,
+    // 批次的学生数, 撤销槽位不能超出; 早期的批次条目没有该字段, 不检查槽位
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub students: Option<u64>,
+}
+
+/// 批次更新: 新批次所基于的批次与承诺增量, 分片换用更大定义域时增量只是新旧承诺之差
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogUpdate {
+    pub parent_batch_id: H256,
+    pub delta: String,
+}
+
+/// 撤销条目: 证书在批次中的槽位与原因, 被更正证书取代时附带更正批次
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRevocation {
+    pub index: u64,
+    pub reason: RevocationReason,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superseded_by: Option<H256>,
+}
+
+impl LogEntry {
+    /// 叶子内容: issuer || batch_id || schema_hash || timestamp || commitments,
+    /// 撤销条目再追加 index || reason [|| superseded_by], 更新条目再追加 parent_batch_id || delta,
+    /// 记录学生数的批次条目最后追加 students
+    pub fn leaf_data(&self) -> anyhow::Result<Vec<u8>> {
+        let mut data = [
+            self.issuer.as_bytes(),
+            self.batch_id.as_bytes(),
+            self.schema_hash.as_bytes(),
+            &self.timestamp.to_be_bytes(),
+            &hex::decode(&self.commitments).context("invalid log entry")?,
+        ]
+        .concat();
+        if let Some(revocation) = &self.revocation {
+            data.extend(revocation.index.to_be_bytes());
+            data.push(revocation.reason.code());
+            if let Some(new_batch_id) = revocation.superseded_by {
+                data.extend(new_batch_id.as_bytes());
+            }
+        }
+        if let Some(update) = &self.update {
+            data.extend(update.parent_batch_id.as_bytes());
+            data.extend(hex::decode(&update.delta).context("invalid log entry")?);
+        }
+        if let Some(students) = self.students {
+            data.extend(students.to_be_bytes());
+        }
+        Ok(data)
+    }
+}
+
+/// 日志签名的树头
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedTreeHead {
+    pub tree_size: u64,
+    // 签名时间, 毫秒
+    pub timestamp: i64,
+    pub root_hash: H256,
+    // secp256k1 签名 r || s || v
+    pub signature: String,
+}
+
+impl SignedTreeHead {
+    fn message(tree_size: u64, timestamp: i64, root_hash: H256) -> [u8; 32] {
+        Sha256::new()
+            .chain_update(b"edu-verify tree head")
+            .chain_update(tree_size.to_be_bytes())
+            .chain_update(timestamp.to_be_bytes())
+            .chain_update(root_hash)
+            .finalize()
+            .into()
+    }
+
+    fn sign(key: &SecretKey, tree_size: u64, root_hash: H256) -> anyhow::Result<Self> {
+        let timestamp = Utc::now().timestamp_millis();
+        let signature = SecretKeyRef::new(key)
+            .sign_message(&Self::message(tree_size, timestamp, root_hash))
+            .map_err(|e| anyhow!("failed to sign tree head: {e}"))?;
+        Ok(Self {
+            tree_size,
+            timestamp,
+            root_hash,
+            signature: hex::encode(
+                [
+                    signature.r.as_bytes(),
+                    signature.s.as_bytes(),
+                    &[signature.v as u8],
+                ]
+                .concat(),
+            ),
+        })
+    }
+
+    /// 签名是否来自日志公布的地址
+    pub fn verify(&self, log_address: Address) -> bool {
+        let Ok(signature) = hex::decode(&self.signature) else {
+            return false;
+        };
+        if signature.len() != 65 {
+            return false;
+        }
+        let message = Self::message(self.tree_size, self.timestamp, self.root_hash);
+        recover(&message, &signature[..64], signature[64] as i32)
+            .is_ok_and(|address| address == log_address)
+    }
+}
+
+/// 写入证书的包含证明, 对应追加时的树头
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogInclusion {
+    pub leaf_index: u64,
+    pub audit_path: Vec<H256>,
+    pub tree_head: SignedTreeHead,
+}
+
+#[derive(Default)]
+struct LogState {
+    entries: Vec<LogEntry>,
+    leaves: Vec<H256>,
+    // 叶子哈希 -> 序号
+    index: HashMap<H256, usize>,
+    // 已锚定的批次 -> 学生数
+    batches: HashMap<(Address, H256), Option<u64>>,
+    revoked: HashMap<(Address, H256, u64), RevocationReason>,
+    superseded: HashMap<(Address, H256, u64), H256>,
+}
+
+impl LogState {
+    fn push(&mut self, entry: LogEntry) -> anyhow::Result<H256> {
+        let leaf = leaf_hash(&entry.leaf_data()?);
+        self.index.insert(leaf, self.entries.len());
+        match &entry.revocation {
+            Some(revocation) => {
+                let key = (entry.issuer, entry.batch_id, revocation.index);
+                self.revoked.insert(key, revocation.reason);
+                if let Some(new_batch_id) = revocation.superseded_by {
+                    self.superseded.insert(key, new_batch_id);
+                }
+            }
+            None => {
+                self.batches
+                    .insert((entry.issuer, entry.batch_id), entry.students);
+            }
+        }
+        self.leaves.push(leaf);
+        self.entries.push(entry);
+        Ok(leaf)
+    }
+}
+
+/// RFC 6962 风格的只追加透明日志, 供无法使用区块链的机构锚定承诺.
+/// 条目逐行写入 JSON Lines 文件, 证书中的 `tx_hash` 为叶子哈希
+pub struct TransparencyLog {
+    path: PathBuf,
+    key: SecretKey,
+    address: Address,
+    state: Mutex<LogState>,
+}
+
+impl TransparencyLog {
+    /// 打开日志文件, 不存在时创建空日志
+    pub fn open(path: impl AsRef<Path>, key: SecretKey) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut state = LogState::default();
+        if let Ok(content) = std::fs::read_to_string(&path) {
+            for (i, line) in content.lines().enumerate() {
+                let entry = serde_json::from_str(line)
+                    .with_context(|| format!("invalid entry {i} in {}", path.display()))?;
+                state.push(entry)?;
+            }
+        }
+
+        let address = SecretKeyRef::new(&key).address();
+        info!(
+            "已加载透明日志 {}, 条目数: {}, 签名地址: {address:?}",
+            path.display(),
+            state.entries.len()
+        );
+        Ok(Self {
+            path,
+            key,
+            address,
+            state: Mutex::new(state),
+        })
+    }
+
+    /// 从环境变量读取日志签名私钥
+    pub fn open_with_key_env(path: impl AsRef<Path>, key_env: &str) -> anyhow::Result<Self> {
+        let key = std::env::var(key_env).with_context(|| format!("{key_env} is not set"))?;
+        Self::open(path, parse_private_key(&key)?)
+    }
+
+    /// 树头签名地址, 审计方用它验证树头
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn tree_head(&self) -> anyhow::Result<SignedTreeHead> {
+        let state = self.state.lock().unwrap();
+        SignedTreeHead::sign(
+            &self.key,
+            state.leaves.len() as u64,
+            tree_root(&state.leaves),
+        )
+    }
+
+    /// 追加一个条目, 写盘后返回叶子哈希与对应树头下的包含证明
+    pub fn append(&self, entry: LogEntry) -> anyhow::Result<(H256, LogInclusion)> {
+        let mut state = self.state.lock().unwrap();
+        let line = serde_json::to_string(&entry)?;
+        let leaf = leaf_hash(&entry.leaf_data()?);
+        let anchored = state.batches.get(&(entry.issuer, entry.batch_id)).copied();
+        match &entry.revocation {
+            Some(revocation) => {
+                let Some(students) = anchored else {
+                    bail!("Batch not found");
+                };
+                // 槽位为 shard * MAX_DEGREE + index, 分片依次填满, 槽位小于学生数即在批次之内
+                ensure!(
+                    students.is_none_or(|students| revocation.index < students),
+                    "Index outside batch"
+                );
+                ensure!(
+                    !state
+                        .revoked
+                        .contains_key(&(entry.issuer, entry.batch_id, revocation.index)),
+                    "Certificate already revoked"
+                );
+                if let Some(new_batch_id) = revocation.superseded_by {
+                    ensure!(
+                        revocation.reason == RevocationReason::Superseded,
+                        "Invalid reason"
+                    );
+                    ensure!(
+                        new_batch_id != entry.batch_id,
+                        "Certificate cannot supersede itself"
+                    );
+                    ensure!(
+                        state.batches.contains_key(&(entry.issuer, new_batch_id)),
+                        "Correction batch not found"
+                    );
+                }
+            }
+            None => {
+                ensure!(anchored.is_none(), "Batch already anchored");
+                ensure!(entry.students != Some(0), "Batch has no students");
+                if let Some(update) = &entry.update {
+                    ensure!(
+                        state
+                            .batches
+                            .contains_key(&(entry.issuer, update.parent_batch_id)),
+                        "Parent batch not found"
+                    );
+                    ensure!(
+                        update.delta.len() == entry.commitments.len(),
+                        "Delta length must match commitments"
+                    );
+                }
+            }
+        }
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{line}")?;
+        file.sync_data()?;
+
+        state.push(entry)?;
+        let leaf_index = state.leaves.len() - 1;
+        let tree_head = SignedTreeHead::sign(
+            &self.key,
+            state.leaves.len() as u64,
+            tree_root(&state.leaves),
+        )?;
+        Ok((
+            leaf,
+            LogInclusion {
+                leaf_index: leaf_index as u64,
+                audit_path: inclusion_proof(&state.leaves, leaf_index),
+                tree_head,
+            },
+        ))
+    }
+
+    /// 叶子在前 `tree_size` 个条目组成的树中的审计路径
+    pub fn inclusion(&self, leaf: H256, tree_size: u64) -> anyhow::Result<(u64, Vec<H256>)> {
+        let state = self.state.lock().unwrap();
+        let index = *state
+            .index
+            .get(&leaf)
+            .ok_or_else(|| anyhow!("leaf {leaf:?} is not in the log"))?;
+        ensure!(
+            index < tree_size as usize && tree_size as usize <= state.leaves.len(),
+            "leaf {leaf:?} is not in tree of size {tree_size}"
+        );
+        Ok((
+            index as u64,
+            inclusion_proof(&state.leaves[..tree_size as usize], index),
+        ))
+    }
+
+    /// 两个树大小之间的一致性证明
+    pub fn consistency(&self, first: u64, second: u64) -> anyhow::Result<Vec<H256>> {
+        let state = self.state.lock().unwrap();
+        ensure!(
+            first <= second && second as usize <= state.leaves.len(),
+            "invalid tree sizes {first}..{second}"
+        );
+        Ok(consistency_proof(
+            &state.leaves[..second as usize],
+            first as usize,
+        ))
+    }
+
+    pub fn entries(&self, start: u64, end: u64) -> Vec<LogEntry> {
+        let state = self.state.lock().unwrap();
+        let end = (end as usize).min(state.entries.len());
+        let start = (start as usize).min(end);
+        state.entries[start..end].to_vec()
+    }
+
+    /// 追加批次条目, 更新条目同时记录所基于的批次与承诺增量
+    fn append_batch(
+        &self,
+        signer: &Signer,
+        schema_hash: H256,
+        commitments: Vec<u8>,
+        update: Option<LogUpdate>,
+        students: u64,
+    ) -> anyhow::Result<Anchor> {
+        if commitments.is_empty() || !commitments.len().is_multiple_of(G1_SIZE) {
+            bail!("Commitment length must be multiple of G1_SIZE");
+        }
+
+        let issuer = signer.address();
+        let batch_id = new_batch_id(&commitments);
+        let (leaf, inclusion) = self.append(LogEntry {
+            issuer,
+            batch_id,
+            schema_hash,
+            commitments: hex::encode(commitments),
+            timestamp: Utc::now().timestamp_millis(),
+            revocation: None,
+            update,
+            students: Some(students),
+        })?;
+        info!(
+            "批次承诺已写入透明日志, 学校: {issuer:?}, 批次: {batch_id:?}, 序号: {}",
+            inclusion.leaf_index
+        );
+
+        Ok(Anchor {
+            tx_hash: leaf,
+            issuer,
+            batch_id,
+            inclusion: Some(inclusion),
+        })
+    }
+}
+
+#[async_trait]
+impl Ledger for TransparencyLog {
+    async fn anchor_batch(
+        &self,
+        signer: &Signer,
+        schema_hash: H256,
+        commitments: Vec<u8>,
+        students: u64,
+    ) -> anyhow::Result<Anchor> {
+        self.append_batch(signer, schema_hash, commitments, None, students)
+    }
+
+    async fn update_batch(
+        &self,
+        signer: &Signer,
+        parent_batch_id: H256,
+        schema_hash: H256,
+        commitments: Vec<u8>,
+        delta: Vec<u8>,
+        students: u64,
+    ) -> anyhow::Result<Anchor> {
+        self.append_batch(
+            signer,
+            schema_hash,
+            commitments,
+            Some(LogUpdate {
+                parent_batch_id,
+                delta: hex::encode(delta),
+            }),
+            students,
+        )
+    }
+
+    async fn fetch_batch(&self, tx_hash: &str) -> anyhow::Result<FetchedBatch> {
+        let leaf: H256 = tx_hash.parse().context("invalid leaf hash")?;
+        let state = self.state.lock().unwrap();
+        let entry = state
+            .index
+            .get(&leaf)
+            .map(|index| &state.entries[*index])
+            .filter(|entry| entry.revocation.is_none())
+            .ok_or_else(|| anyhow!("Batch not found"))?;
+        Ok(FetchedBatch {
+            issuer: entry.issuer,
+            batch_id: entry.batch_id,
+            commitments: hex::decode(&entry.commitments)?,
+            anchor: AnchorStatus::Asserted,
+        })
+    }
+
+    async fn anchor_info(&self, tx_hash: &str) -> anyhow::Result<AnchorInfo> {
+        let leaf: H256 = tx_hash.parse().context("invalid leaf hash")?;
+        let state = self.state.lock().unwrap();
+        let index = *state
+            .index
+            .get(&leaf)
+            .ok_or_else(|| anyhow!("Batch not found"))?;
+        let entry = &state.entries[index];
+        // 日志序号相当于区块号, 之后追加的条目数相当于确认数
+        Ok(AnchorInfo {
+            block_number: index as u64,
+            timestamp: (entry.timestamp / 1000) as u64,
+            sender: entry.issuer,
+            confirmations: (state.entries.len() - index) as u64,
+        })
+    }
+
+    async fn revoke(
+        &self,
+        signer: &Signer,
+        batch_id: H256,
+        index: u64,
+        reason: RevocationReason,
+    ) -> anyhow::Result<H256> {
+        let issuer = signer.address();
+        let (leaf, inclusion) = self.append(LogEntry {
+            issuer,
+            batch_id,
+            schema_hash: H256::zero(),
+            commitments: String::new(),
+            timestamp: Utc::now().timestamp_millis(),
+            revocation: Some(LogRevocation {
+                index,
+                reason,
+                superseded_by: None,
+            }),
+            update: None,
+            students: None,
+        })?;
+        info!(
+            "撤销已写入透明日志, 学校: {issuer:?}, 批次: {batch_id:?}, 槽位: {index}, 序号: {}",
+            inclusion.leaf_index
+        );
+        Ok(leaf)
+    }
+
+    async fn revocation(
+        &self,
+        issuer: Address,
+        batch_id: H256,
+        index: u64,
+    ) -> anyhow::Result<Option<RevocationReason>> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .revoked
+            .get(&(issuer, batch_id, index))
+            .copied())
+    }
+
+    async fn supersede(
+        &self,
+        signer: &Signer,
+        batch_id: H256,
+        index: u64,
+        new_batch_id: H256,
+    ) -> anyhow::Result<H256> {
+        let issuer = signer.address();
+        let (leaf, inclusion) = self.append(LogEntry {
+            issuer,
+            batch_id,
+            schema_hash: H256::zero(),
+            commitments: String::new(),
+            timestamp: Utc::now().timestamp_millis(),
+            revocation: Some(LogRevocation {
+                index,
+                reason: RevocationReason::Superseded,
+                superseded_by: Some(new_batch_id),
+            }),
+            update: None,
+            students: None,
+        })?;
+        info!(
+            "取代记录已写入透明日志, 学校: {issuer:?}, 批次: {batch_id:?}, 槽位: {index}, 更正批次: {new_batch_id:?}, 序号: {}",
+            inclusion.leaf_index
+        );
+        Ok(leaf)
+    }
+
+    async fn superseded_by(
+        &self,
+        issuer: Address,
+        batch_id: H256,
+        index: u64,
+    ) -> anyhow::Result<Option<H256>> {
+        Ok(self
+            .state
+            .lock()
+            .unwrap()
+            .superseded
+            .get(&(issuer, batch_id, index))
+            .copied())
+    }
+
+    async fn verify(&self, call: VerifyDataCall) -> anyhow::Result<bool> {
+        // 没有链上合约, 使用本地 EVM 中部署的验证合约
+        local_verify_data(call).await
+    }
+
+    async fn attest_verification(
+        &self,
+        _signer: &Signer,
+        _call: VerifyDataCall,
+    ) -> anyhow::Result<Attestation> {
+        bail!("transparency log does not record verifications")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(n: usize) -> Vec<H256> {
+        (0..n).map(|i| leaf_hash(&[i as u8])).collect()
+    }
+
+    #[test]
+    fn test_merkle_proofs() {
+        // RFC 6962 测试向量: 空树
+        assert_eq!(
+            tree_root(&[]),
+            "0xe3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+                .parse()
+                .unwrap()
+        );
+
+        for n in 1..=17 {
+            let tree = leaves(n);
+            let root = tree_root(&tree);
+            for m in 0..n {
+                let path = inclusion_proof(&tree, m);
+                assert!(verify_inclusion(tree[m], m as u64, n as u64, &path, root));
+                assert!(!verify_inclusion(
+                    leaf_hash(b"forged"),
+                    m as u64,
+                    n as u64,
+                    &path,
+                    root
+                ));
+            }
+            for m in 0..=n {
+                let proof = consistency_proof(&tree, m);
+                let first = tree_root(&tree[..m]);
+                assert!(verify_consistency(m as u64, n as u64, first, root, &proof));
+                if m > 0 && m < n {
+                    // 分叉的旧树无法证明一致
+                    let forked = tree_root(&leaves(m + 1)[1..]);
+                    assert!(!verify_consistency(
+                        m as u64, n as u64, forked, root, &proof
+                    ));
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transparency_log() {
+        let path = std::env::temp_dir().join(format!("translog-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let signer = Signer::Node(Address::from_low_u64_be(1));
+
+        let log = TransparencyLog::open(&path, key).unwrap();
+        let first = log
+            .anchor_batch(&signer, H256::zero(), vec![1; G1_SIZE], 12)
+            .await
+            .unwrap();
+        let inclusion = first.inclusion.unwrap();
+        assert!(inclusion.tree_head.verify(log.address()));
+        let second = log
+            .anchor_batch(&signer, H256::zero(), vec![2; G1_SIZE], 12)
+            .await
+            .unwrap()
+            .batch_id;
+        let revocation = log
+            .revoke(&signer, first.batch_id, 9, RevocationReason::ClericalError)
+            .await
+            .unwrap();
+        assert!(log
+            .revoke(&signer, first.batch_id, 9, RevocationReason::Other)
+            .await
+            .is_err());
+        assert!(log
+            .revoke(&signer, H256::zero(), 9, RevocationReason::Other)
+            .await
+            .is_err());
+        // 槽位须在批次的学生之内
+        assert!(log
+            .revoke(&signer, first.batch_id, 12, RevocationReason::Other)
+            .await
+            .is_err());
+        log.supersede(&signer, first.batch_id, 10, second)
+            .await
+            .unwrap();
+        assert!(log
+            .supersede(&signer, first.batch_id, 11, H256::zero())
+            .await
+            .is_err());
+        assert!(log
+            .update_batch(
+                &signer,
+                H256::zero(),
+                H256::zero(),
+                vec![3; G1_SIZE],
+                vec![1; G1_SIZE],
+                12
+            )
+            .await
+            .is_err());
+        let update = log
+            .update_batch(
+                &signer,
+                second,
+                H256::zero(),
+                vec![3; G1_SIZE],
+                vec![1; G1_SIZE],
+                12,
+            )
+            .await
+            .unwrap();
+
+        // 重新打开后状态一致, 旧树头与新树头一致
+        let log = TransparencyLog::open(&path, key).unwrap();
+        let head = log.tree_head().unwrap();
+        assert_eq!(head.tree_size, 5);
+        assert_eq!(
+            log.revocation(first.issuer, first.batch_id, 9)
+                .await
+                .unwrap(),
+            Some(RevocationReason::ClericalError)
+        );
+        assert_eq!(
+            log.revocation(first.issuer, first.batch_id, 10)
+                .await
+                .unwrap(),
+            Some(RevocationReason::Superseded)
+        );
+        assert_eq!(
+            log.superseded_by(first.issuer, first.batch_id, 10)
+                .await
+                .unwrap(),
+            Some(second)
+        );
+        // 撤销条目不能当作批次读取
+        assert!(log.fetch_batch(&format!("{revocation:?}")).await.is_err());
+        assert!(verify_consistency(
+            inclusion.tree_head.tree_size,
+            head.tree_size,
+            inclusion.tree_head.root_hash,
+            head.root_hash,
+            &log.consistency(1, 5).unwrap(),
+        ));
+        let (index, path_5) = log.inclusion(first.tx_hash, 5).unwrap();
+        assert!(verify_inclusion(
+            first.tx_hash,
+            index,
+            5,
+            &path_5,
+            head.root_hash
+        ));
+        assert_eq!(
+            log.fetch_batch(&format!("{:?}", first.tx_hash))
+                .await
+                .unwrap()
+                .commitments,
+            vec![1; G1_SIZE]
+        );
+        assert_eq!(
+            log.entries(4, 5)[0].update,
+            Some(LogUpdate {
+                parent_batch_id: second,
+                delta: hex::encode([1; G1_SIZE]),
+            })
+        );
+        assert_eq!(
+            log.fetch_batch(&format!("{:?}", update.tx_hash))
+                .await
+                .unwrap()
+                .commitments,
+            vec![3; G1_SIZE]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, bail, Context};
+use log::{info, warn};
+use secp256k1::SecretKey;
+use serde::Deserialize;
+use web3::signing::{Key, SecretKeyRef};
+use web3::transports::Http;
+use web3::types::Address;
+use web3::Web3;
+
+// 签名配置文件, 按学校配置签名私钥
+const DEFAULT_WALLET_CONFIG: &str = "wallets.json";
+pub const DEFAULT_SIGNER: &str = "default";
+
+/// 交易签名方
+#[derive(Clone)]
+pub enum Signer {
+    /// 本地私钥, 按 EIP-155 签名后通过 eth_sendRawTransaction 发送
+    Local {
+        name: String,
+        key: SecretKey,
+        address: Address,
+    },
+    /// 节点托管的已解锁账户, 仅适用于开发节点
+    Node(Address),
+}
+
+impl Signer {
+    pub fn from_key(name: &str, key: SecretKey) -> Self {
+        let address = SecretKeyRef::new(&key).address();
+        Self::Local {
+            name: name.to_string(),
+            key,
+            address,
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        match self {
+            Self::Local { address, .. } => *address,
+            Self::Node(address) => *address,
+        }
+    }
+}
+
+// 不输出私钥
+impl std::fmt::Debug for Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Local { name, address, .. } => write!(f, "Local({name}, {address:?})"),
+            Self::Node(address) => write!(f, "Node({address:?})"),
+        }
+    }
+}
+
+/// 单个签名方的配置, 私钥可直接给出、从环境变量读取或从加密 keystore 解密
+#[derive(Debug, Default, Deserialize)]
+pub struct SignerConfig {
+    pub private_key: Option<String>,
+    pub private_key_env: Option<String>,
+    pub keystore: Option<PathBuf>,
+    // keystore 必须配置口令来源, 不以空口令解密
+    pub password_env: Option<String>,
+    // 学校接口令牌所在的环境变量, 请求以该令牌选定签名方; 未配置时该签名方不能用于学校接口
+    pub api_token_env: Option<String>,
+}
+
+impl SignerConfig {
+    fn load(&self, name: &str) -> anyhow::Result<Signer> {
+        let key = match (&self.private_key, &self.private_key_env, &self.keystore) {
+            (Some(key), _, _) => parse_private_key(key)?,
+            (None, Some(env), _) => {
+                parse_private_key(&std::env::var(env).with_context(|| format!("{env} not set"))?)?
+            }
+            (None, None, Some(path)) => {
+                let env = self
+                    .password_env
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("signer {name} keystore has no password_env"))?;
+                let password = std::env::var(env).with_context(|| format!("{env} not set"))?;
+                let key = eth_keystore::decrypt_key(path, password)
+                    .map_err(|e| anyhow!("failed to decrypt keystore {path:?}: {e}"))?;
+                SecretKey::from_slice(&key).context("invalid keystore private key")?
+            }
+            (None, None, None) => bail!("signer {name} has no private key or keystore"),
+        };
+        Ok(Signer::from_key(name, key))
+    }
+
+    fn api_token(&self) -> anyhow::Result<Option<String>> {
+        let Some(env) = &self.api_token_env else {
+            return Ok(None);
+        };
+        let token = std::env::var(env).with_context(|| format!("{env} not set"))?;
+        if token.is_empty() {
+            bail!("{env} is empty");
+        }
+        Ok(Some(token))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct WalletConfig {
+    pub default: Option<String>,
+    #[serde(default)]
+    pub signers: BTreeMap<String, SignerConfig>,
+}
+
+pub fn parse_private_key(key: &str) -> anyhow::Result<SecretKey> {
+    let bytes = hex::decode(key.trim().trim_start_matches("0x")).context("invalid private key")?;
+    SecretKey::from_slice(&bytes).context("invalid private key")
+}
+
+/// 发证方钱包: 学校名 -> 签名方, 学校接口令牌 -> 签名方
+pub struct Wallet {
+    signers: BTreeMap<String, Signer>,
+    default: Signer,
+    tokens: BTreeMap<String, Signer>,
+}
+
+// 不输出令牌
+impl std::fmt::Debug for Wallet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Wallet")
+            .field("signers", &self.signers)
+            .field("default", &self.default)
+            .field("tokens", &self.tokens.len())
+            .finish()
+    }
+}
+
+impl Wallet {
+    pub fn new(
+        signers: BTreeMap<String, Signer>,
+        default: Signer,
+        tokens: BTreeMap<String, Signer>,
+    ) -> Self {
+        Self {
+            signers,
+            default,
+            tokens,
+        }
+    }
+
+    pub fn from_config(config: &WalletConfig) -> anyhow::Result<Option<Self>> {
+        let signers = config
+            .signers
+            .iter()
+            .map(|(name, signer)| Ok((name.clone(), signer.load(name)?)))
+            .collect::<anyhow::Result<BTreeMap<_, _>>>()?;
+
+        let mut tokens = BTreeMap::new();
+        for (name, config) in &config.signers {
+            if let Some(token) = config.api_token()? {
+                if tokens.insert(token, signers[name].clone()).is_some() {
+                    bail!("signer {name} shares its API token with another signer");
+                }
+            }
+        }
+
+        let default = match &config.default {
+            Some(name) => signers
+                .get(name)
+                .ok_or_else(|| anyhow!("default signer {name} not configured"))?,
+            None => match signers.get(DEFAULT_SIGNER).or(signers.values().next()) {
+                Some(signer) => signer,
+                None => return Ok(None),
+            },
+        }
+        .clone();
+
+        Ok(Some(Self::new(signers, default, tokens)))
+    }
+
+    /// 读取 WALLET_CONFIG 与 ISSUER_PRIVATE_KEY / ISSUER_KEYSTORE,
+    /// 都未配置时退回节点托管账户; 默认网络为透明日志时没有节点, 必须配置本地私钥
+    pub async fn load(web3: Option<&Web3<Http>>) -> anyhow::Result<Self> {
+        let path =
+            std::env::var("WALLET_CONFIG").unwrap_or_else(|_| DEFAULT_WALLET_CONFIG.to_string());
+        let mut config = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice::<WalletConfig>(&bytes)
+                .with_context(|| format!("invalid wallet config {path}"))?,
+            Err(_) => WalletConfig::default(),
+        };
+
+        if std::env::var("ISSUER_PRIVATE_KEY").is_ok() || std::env::var("ISSUER_KEYSTORE").is_ok() {
+            config.signers.insert(
+                DEFAULT_SIGNER.to_string(),
+                SignerConfig {
+                    private_key_env: std::env::var("ISSUER_PRIVATE_KEY")
+                        .ok()
+                        .map(|_| "ISSUER_PRIVATE_KEY".to_string()),
+                    keystore: std::env::var("ISSUER_KEYSTORE").ok().map(PathBuf::from),
+                    password_env: Some("ISSUER_KEYSTORE_PASSWORD".to_string()),
+                    api_token_env: std::env::var("ISSUER_API_TOKEN")
+                        .ok()
+                        .map(|_| "ISSUER_API_TOKEN".to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        if let Some(wallet) = Self::from_config(&config)? {
+            info!("已加载签名账户: {:?}", wallet.signers.values());
+            return Ok(wallet);
+        }
+
+        let web3 = web3.ok_or_else(|| anyhow!("未配置签名私钥"))?;
+        warn!("未配置签名私钥, 使用节点托管账户, 仅适用于开发节点");
+        let accounts = web3.eth().accounts().await?;
+        let account = accounts
+            .first()
+            .ok_or_else(|| anyhow!("没有找到可用的以太坊账户"))?;
+        let signer = Signer::Node(*account);
+        let tokens = match std::env::var("ISSUER_API_TOKEN") {
+            Ok(token) if token.is_empty() => bail!("ISSUER_API_TOKEN is empty"),
+            Ok(token) => BTreeMap::from([(token, signer.clone())]),
+            Err(_) => BTreeMap::new(),
+        };
+        Ok(Self::new(BTreeMap::new(), signer, tokens))
+    }
+
+    pub fn signers(&self) -> &BTreeMap<String, Signer> {
+        &self.signers
+    }
+
+    pub fn default_signer(&self) -> &Signer {
+        &self.default
+    }
+
+    /// 按学校接口令牌确定签名方, 请求方不能自行指定以哪所学校签名
+    pub fn authenticate(&self, token: &str) -> anyhow::Result<&Signer> {
+        self.tokens
+            .get(token)
+            .ok_or_else(|| anyhow!("unknown issuer API token"))
+    }
+
+    /// 学校的签名方, 未指定学校时使用默认签名方
+    pub fn signer(&self, issuer: Option<&str>) -> anyhow::Result<&Signer> {
+        match issuer {
+            Some(name) => self
+                .signers
+                .get(name)
+                .ok_or_else(|| anyhow!("no signing key configured for issuer {name}")),
+            None => Ok(&self.default),
+        }
+    }
+}
+
+// 启动时加载的钱包
+pub static WALLET: OnceLock<Wallet> = OnceLock::new();
+
+pub fn wallet() -> anyhow::Result<&'static Wallet> {
+    WALLET.get().ok_or_else(|| anyhow!("wallet not loaded"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_chacha::rand_core::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    const KEY: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    const ADDRESS: &str = "0x2c7536e3605d9c16a7a3d7b1898e529396a65c23";
+
+    #[test]
+    fn test_private_key_address() {
+        let signer = Signer::from_key("school-a", parse_private_key(KEY).unwrap());
+        assert_eq!(signer.address(), ADDRESS.parse().unwrap());
+        assert!(!format!("{signer:?}").contains(&KEY[2..]));
+        assert!(parse_private_key("0x1234").is_err());
+    }
+
+    #[test]
+    fn test_wallet_config() {
+        let dir = std::env::temp_dir().join(format!("edu-verify-wallet-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        eth_keystore::encrypt_key(
+            &dir,
+            &mut rng,
+            hex::decode(&KEY[2..]).unwrap(),
+            "password",
+            Some("school-b.json"),
+        )
+        .unwrap();
+        std::env::set_var("EDU_VERIFY_TEST_KEYSTORE_PASSWORD", "password");
+        std::env::set_var("EDU_VERIFY_TEST_API_TOKEN", "school-a-token");
+
+        let config: WalletConfig = serde_json::from_value(serde_json::json!({
+            "default": "school-a",
+            "signers": {
+                "school-a": { "private_key": KEY, "api_token_env": "EDU_VERIFY_TEST_API_TOKEN" },
+                "school-b": {
+                    "keystore": dir.join("school-b.json"),
+                    "password_env": "EDU_VERIFY_TEST_KEYSTORE_PASSWORD"
+                }
+            }
+        }))
+        .unwrap();
+        let wallet = Wallet::from_config(&config).unwrap().unwrap();
+
+        assert_eq!(wallet.default_signer().address(), ADDRESS.parse().unwrap());
+        assert_eq!(
+            wallet.signer(Some("school-b")).unwrap().address(),
+            ADDRESS.parse().unwrap()
+        );
+        assert!(wallet.signer(Some("school-c")).is_err());
+        assert_eq!(
+            wallet.authenticate("school-a-token").unwrap().address(),
+            ADDRESS.parse().unwrap()
+        );
+        assert!(wallet.authenticate("").is_err());
+        assert!(!format!("{wallet:?}").contains("school-a-token"));
+
+        // keystore 未配置口令来源时拒绝加载, 不以空口令尝试解密
+        let config: WalletConfig = serde_json::from_value(serde_json::json!({
+            "signers": { "school-b": { "keystore": dir.join("school-b.json") } }
+        }))
+        .unwrap();
+        assert!(Wallet::from_config(&config).is_err());
+        assert!(Wallet::from_config(&WalletConfig::default())
+            .unwrap()
+            .is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -12,14 +12,27 @@ async function sendToBackend(data) {
 
         console.log('Sending data to backend:', formattedData);
         
+        // 学校接口令牌, 后端据此确定发证学校的签名账户
+        let apiToken = sessionStorage.getItem('schoolApiToken');
+        if (!apiToken) {
+            apiToken = prompt('请输入学校接口令牌') || '';
+            sessionStorage.setItem('schoolApiToken', apiToken);
+        }
+
         const response = await fetch('http://localhost:3000/api/school/upload', {
             method: 'POST',
             headers: {
                 'Content-Type': 'application/json',
+                'Authorization': `Bearer ${apiToken}`,
             },
             body: JSON.stringify(formattedData)
         });
 
+        if (response.status === 401) {
+            // 令牌无效时清除, 下次提交重新输入
+            sessionStorage.removeItem('schoolApiToken');
+        }
+
         if (!response.ok) {
             throw new Error(`HTTP error! status: ${response.status}`);
         }
@@ -33,7 +33,7 @@ function displayImages(images) {
         html += `
             <div class="image-item">
                 <h4>${image.name}</h4>
-                <img src="data:image/jpeg;base64,${image.data}" alt="${image.name}" />
+                <img src="${image.url}" alt="${image.ipfs_cid}" />
                 <div class="image-info">
                     <p>IPFS CID: ${image.ipfs_cid}</p>
                     <p>大小: ${(image.size / 1024).toFixed(2)} KB</p>